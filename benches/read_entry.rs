@@ -39,5 +39,24 @@ fn read_entry(bench: &mut Bencher) {
     bench.bytes = size as u64;
 }
 
-benchmark_group!(benches, read_entry);
+fn read_entry_no_crc(bench: &mut Bencher) {
+    let size = 1024 * 1024;
+    let bytes = generate_random_archive(size);
+    let mut archive = ZipArchive::new(Cursor::new(bytes.as_slice())).unwrap();
+
+    bench.iter(|| {
+        let mut file = archive.by_index_no_crc(0).unwrap();
+        let mut buf = [0u8; 1024];
+        loop {
+            let n = file.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+        }
+    });
+
+    bench.bytes = size as u64;
+}
+
+benchmark_group!(benches, read_entry, read_entry_no_crc);
 benchmark_main!(benches);
@@ -0,0 +1,66 @@
+use bencher::{benchmark_group, benchmark_main};
+
+use std::io::Cursor;
+
+use bencher::Bencher;
+use zip::read::ZipArchiveOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const FILE_COUNT: usize = 15_000;
+const LARGE_FILE_COUNT: usize = 100_000;
+
+fn generate_archive(count: usize) -> Vec<u8> {
+    let data = Vec::new();
+    let mut writer = ZipWriter::new(Cursor::new(data));
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+    for i in 0..count {
+        let name = format!("directory/subdirectory/file_{:05}.txt", i);
+        writer.start_file(name, options.clone()).unwrap();
+    }
+
+    writer.finish().unwrap().into_inner()
+}
+
+fn read_metadata(bench: &mut Bencher) {
+    let bytes = generate_archive(FILE_COUNT);
+
+    bench.iter(|| {
+        let archive = ZipArchive::new(Cursor::new(bytes.as_slice())).unwrap();
+        archive.len()
+    });
+
+    bench.bytes = bytes.len() as u64;
+}
+
+fn read_large_metadata(bench: &mut Bencher) {
+    let bytes = generate_archive(LARGE_FILE_COUNT);
+
+    bench.iter(|| {
+        let archive = ZipArchive::new(Cursor::new(bytes.as_slice())).unwrap();
+        archive.len()
+    });
+
+    bench.bytes = bytes.len() as u64;
+}
+
+fn read_large_metadata_lazy_names(bench: &mut Bencher) {
+    let bytes = generate_archive(LARGE_FILE_COUNT);
+    let options = ZipArchiveOptions::default().lazy_names(true);
+
+    bench.iter(|| {
+        let archive = ZipArchive::with_options(Cursor::new(bytes.as_slice()), options).unwrap();
+        archive.len()
+    });
+
+    bench.bytes = bytes.len() as u64;
+}
+
+benchmark_group!(
+    benches,
+    read_metadata,
+    read_large_metadata,
+    read_large_metadata_lazy_names
+);
+benchmark_main!(benches);
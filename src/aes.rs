@@ -0,0 +1,224 @@
+//! Encryption in the WinZip AES format
+//!
+//! The data of an encrypted file consists of a random salt, a password verification value, the
+//! data encrypted with AES in counter mode, and an authentication code computed over the
+//! encrypted data. The keys are derived from the password and the salt.
+
+use crate::result::{ZipError, ZipResult};
+use crate::types::AesMode;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::io;
+use std::io::prelude::*;
+
+/// The number of PBKDF2 iterations used to derive the keys
+const ITERATION_COUNT: u32 = 1000;
+/// The length of the password verification value that follows the salt
+pub const PWD_VERIFY_LENGTH: usize = 2;
+/// The length of the authentication code that follows the encrypted data
+pub const AUTH_CODE_LENGTH: usize = 10;
+
+/// The counter starts at 1 and is incremented as a little-endian number
+type Aes128Ctr = ctr::Ctr128LE<aes_cipher::Aes128>;
+type Aes192Ctr = ctr::Ctr128LE<aes_cipher::Aes192>;
+type Aes256Ctr = ctr::Ctr128LE<aes_cipher::Aes256>;
+
+enum Cipher {
+    Aes128(Box<Aes128Ctr>),
+    Aes192(Box<Aes192Ctr>),
+    Aes256(Box<Aes256Ctr>),
+}
+
+impl Cipher {
+    fn new(mode: AesMode, key: &[u8]) -> Cipher {
+        let mut iv = [0u8; 16];
+        iv[0] = 1;
+        match mode {
+            AesMode::Aes128 => Cipher::Aes128(Box::new(Aes128Ctr::new(key.into(), &iv.into()))),
+            AesMode::Aes192 => Cipher::Aes192(Box::new(Aes192Ctr::new(key.into(), &iv.into()))),
+            AesMode::Aes256 => Cipher::Aes256(Box::new(Aes256Ctr::new(key.into(), &iv.into()))),
+        }
+    }
+
+    fn apply_keystream(&mut self, buf: &mut [u8]) {
+        match *self {
+            Cipher::Aes128(ref mut cipher) => cipher.apply_keystream(buf),
+            Cipher::Aes192(ref mut cipher) => cipher.apply_keystream(buf),
+            Cipher::Aes256(ref mut cipher) => cipher.apply_keystream(buf),
+        }
+    }
+}
+
+/// The cipher and authentication state for the data of a single file
+pub struct AesCipher {
+    cipher: Cipher,
+    hmac: Hmac<Sha1>,
+}
+
+impl AesCipher {
+    /// Derive the keys from the password and salt
+    ///
+    /// Returns the cipher, and the password verification value that is stored after the salt.
+    pub fn new(
+        mode: AesMode,
+        password: &[u8],
+        salt: &[u8],
+    ) -> (AesCipher, [u8; PWD_VERIFY_LENGTH]) {
+        let key_length = mode.key_length();
+        let mut derived = vec![0u8; 2 * key_length + PWD_VERIFY_LENGTH];
+        pbkdf2::pbkdf2::<Hmac<Sha1>>(password, salt, ITERATION_COUNT, &mut derived);
+
+        let (encryption_key, rest) = derived.split_at(key_length);
+        let (authentication_key, verification) = rest.split_at(key_length);
+        let cipher = AesCipher {
+            cipher: Cipher::new(mode, encryption_key),
+            hmac: Hmac::<Sha1>::new_from_slice(authentication_key)
+                .expect("HMAC accepts keys of any length"),
+        };
+        let mut password_verification = [0u8; PWD_VERIFY_LENGTH];
+        password_verification.copy_from_slice(verification);
+        (cipher, password_verification)
+    }
+
+    /// Encrypt data in place
+    pub fn encrypt(&mut self, buf: &mut [u8]) {
+        self.cipher.apply_keystream(buf);
+        self.hmac.update(buf);
+    }
+
+    /// Decrypt data in place
+    pub fn decrypt(&mut self, buf: &mut [u8]) {
+        self.hmac.update(buf);
+        self.cipher.apply_keystream(buf);
+    }
+
+    /// The authentication code over all data that was encrypted or decrypted
+    pub fn auth_code(self) -> [u8; AUTH_CODE_LENGTH] {
+        let mut auth_code = [0u8; AUTH_CODE_LENGTH];
+        auth_code.copy_from_slice(&self.hmac.finalize().into_bytes()[..AUTH_CODE_LENGTH]);
+        auth_code
+    }
+}
+
+/// A reader that decrypts the data of a file, and checks its authentication code at the end
+pub struct AesReader<R: Read> {
+    inner: R,
+    cipher: Option<AesCipher>,
+    /// The number of encrypted bytes that are left before the authentication code
+    remaining: u64,
+}
+
+impl<R: Read> AesReader<R> {
+    /// Start decrypting a file whose data, including the salt, the password verification value
+    /// and the authentication code, is `data_length` bytes long
+    ///
+    /// Fails if the password verification value does not match the password.
+    pub fn new(
+        mut inner: R,
+        mode: AesMode,
+        password: &[u8],
+        data_length: u64,
+    ) -> ZipResult<AesReader<R>> {
+        let overhead = (mode.salt_length() + PWD_VERIFY_LENGTH + AUTH_CODE_LENGTH) as u64;
+        if data_length < overhead {
+            return Err(ZipError::InvalidArchive("Encrypted data is too short"));
+        }
+        let mut salt = vec![0u8; mode.salt_length()];
+        inner.read_exact(&mut salt)?;
+        let mut password_verification = [0u8; PWD_VERIFY_LENGTH];
+        inner.read_exact(&mut password_verification)?;
+        let (cipher, expected_verification) = AesCipher::new(mode, password, &salt);
+        if password_verification != expected_verification {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid password",
+            )));
+        }
+        Ok(AesReader {
+            inner,
+            cipher: Some(cipher),
+            remaining: data_length - overhead,
+        })
+    }
+}
+
+impl<R: Read> Read for AesReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            // Check the authentication code once, when the end of the data is first reached
+            if let Some(cipher) = self.cipher.take() {
+                let mut auth_code = [0u8; AUTH_CODE_LENGTH];
+                self.inner.read_exact(&mut auth_code)?;
+                if auth_code != cipher.auth_code() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Invalid authentication code",
+                    ));
+                }
+            }
+            return Ok(0);
+        }
+        let len = ::std::cmp::min(buf.len() as u64, self.remaining) as usize;
+        let count = self.inner.read(&mut buf[..len])?;
+        if count == 0 && len > 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= count as u64;
+        if let Some(ref mut cipher) = self.cipher {
+            cipher.decrypt(&mut buf[..count]);
+        }
+        Ok(count)
+    }
+}
+
+/// A writer that encrypts the data of a file
+pub struct AesWriter<W: Write> {
+    inner: W,
+    cipher: AesCipher,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> AesWriter<W> {
+    /// Start encrypting a file, writing a new random salt and the password verification value
+    ///
+    /// The salt and verification value are written before `inner` is taken over, so it is left
+    /// as it was if this fails.
+    pub fn start(inner: &mut W, mode: AesMode, password: &[u8]) -> io::Result<AesCipher> {
+        let mut salt = vec![0u8; mode.salt_length()];
+        getrandom::getrandom(&mut salt).map_err(io::Error::from)?;
+        let (cipher, password_verification) = AesCipher::new(mode, password, &salt);
+        inner.write_all(&salt)?;
+        inner.write_all(&password_verification)?;
+        Ok(cipher)
+    }
+
+    /// Encrypt everything that is written to `inner` with a cipher returned by `start`
+    pub fn new(inner: W, cipher: AesCipher) -> AesWriter<W> {
+        AesWriter {
+            inner,
+            cipher,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Write the authentication code and return the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&self.cipher.auth_code())?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for AesWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(buf);
+        self.cipher.encrypt(&mut self.buffer);
+        self.inner.write_all(&self.buffer)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
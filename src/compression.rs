@@ -3,16 +3,42 @@
 use std::fmt;
 
 /// Compression methods for the contents of a ZIP file.
+///
+/// More methods may be added in minor releases, so matches on this enum need a wildcard arm.
 #[derive(Copy, Clone, PartialEq, Debug)]
+#[non_exhaustive]
 pub enum CompressionMethod {
     /// The file is stored (no compression)
     Stored,
+    /// File is compressed using one of the Reduce methods, with a compression factor of 1 to 4
+    ///
+    /// These methods can only be read.
+    #[cfg(feature = "reduce")]
+    Reduced(u8),
     /// Deflate in pure rust
     #[cfg(feature = "deflate")]
     Deflated,
     /// File is compressed using BZIP2 algorithm
     #[cfg(feature = "bzip2")]
     Bzip2,
+    /// File is compressed using Zstandard
+    #[cfg(feature = "zstd")]
+    Zstd,
+    /// File is an MP3 stream that was repackaged losslessly with an MP3 specific method
+    ///
+    /// This method can not be decompressed, but the raw data can be read with
+    /// `ZipArchive::by_index_raw`. It is the method specific payload, not a playable MP3 file.
+    Mp3,
+    /// File is a JPEG image compressed with a JPEG specific method
+    ///
+    /// This method can not be decompressed, but the raw data can be read with
+    /// `ZipArchive::by_index_raw`.
+    Jpeg,
+    /// File is a WAV file compressed using WavPack
+    ///
+    /// This method can not be decompressed, but the raw data can be read with
+    /// `ZipArchive::by_index_raw`.
+    WavPack,
     /// Unsupported compression method
     Unsupported(u16),
 }
@@ -22,22 +48,50 @@ impl CompressionMethod {
     pub fn from_u16(val: u16) -> CompressionMethod {
         match val {
             0 => CompressionMethod::Stored,
+            #[cfg(feature = "reduce")]
+            2..=5 => CompressionMethod::Reduced(val as u8 - 1),
             #[cfg(feature = "deflate")]
             8 => CompressionMethod::Deflated,
             #[cfg(feature = "bzip2")]
             12 => CompressionMethod::Bzip2,
+            #[cfg(feature = "zstd")]
+            93 => CompressionMethod::Zstd,
+            94 => CompressionMethod::Mp3,
+            96 => CompressionMethod::Jpeg,
+            97 => CompressionMethod::WavPack,
             v => CompressionMethod::Unsupported(v),
         }
     }
 
+    /// The compression methods that files can be written with, which depends on the enabled
+    /// features
+    pub fn supported() -> &'static [CompressionMethod] {
+        &[
+            CompressionMethod::Stored,
+            #[cfg(feature = "deflate")]
+            CompressionMethod::Deflated,
+            #[cfg(feature = "bzip2")]
+            CompressionMethod::Bzip2,
+            #[cfg(feature = "zstd")]
+            CompressionMethod::Zstd,
+        ]
+    }
+
     /// Converts a CompressionMethod to a u16
     pub fn to_u16(self) -> u16 {
         match self {
             CompressionMethod::Stored => 0,
+            #[cfg(feature = "reduce")]
+            CompressionMethod::Reduced(factor) => factor as u16 + 1,
             #[cfg(feature = "deflate")]
             CompressionMethod::Deflated => 8,
             #[cfg(feature = "bzip2")]
             CompressionMethod::Bzip2 => 12,
+            #[cfg(feature = "zstd")]
+            CompressionMethod::Zstd => 93,
+            CompressionMethod::Mp3 => 94,
+            CompressionMethod::Jpeg => 96,
+            CompressionMethod::WavPack => 97,
             CompressionMethod::Unsupported(v) => v,
         }
     }
@@ -56,7 +110,7 @@ mod test {
 
     #[test]
     fn from_eq_to() {
-        for v in 0..(::std::u16::MAX as u32 + 1) {
+        for v in 0..(u16::MAX as u32 + 1) {
             let from = CompressionMethod::from_u16(v as u16);
             let to = from.to_u16() as u32;
             assert_eq!(v, to);
@@ -66,10 +120,17 @@ mod test {
     fn methods() -> Vec<CompressionMethod> {
         let mut methods = Vec::new();
         methods.push(CompressionMethod::Stored);
+        #[cfg(feature = "reduce")]
+        methods.extend((1..=4).map(CompressionMethod::Reduced));
         #[cfg(feature = "deflate")]
         methods.push(CompressionMethod::Deflated);
         #[cfg(feature = "bzip2")]
         methods.push(CompressionMethod::Bzip2);
+        #[cfg(feature = "zstd")]
+        methods.push(CompressionMethod::Zstd);
+        methods.push(CompressionMethod::Mp3);
+        methods.push(CompressionMethod::Jpeg);
+        methods.push(CompressionMethod::WavPack);
         methods
     }
 
@@ -1,4 +1,4 @@
-//! Convert a string in IBM codepage 437 to UTF-8
+//! Convert a string in IBM codepage 437 to UTF-8, and back
 
 /// Trait to convert IBM codepage 437 to the target type
 pub trait FromCp437 {
@@ -34,6 +34,39 @@ impl FromCp437 for Vec<u8> {
     }
 }
 
+/// Trait to convert a string to IBM codepage 437
+pub trait ToCp437 {
+    /// Target type
+    type Target;
+
+    /// Function that does the conversion to cp437.
+    /// Returns the first character that codepage 437 can not represent as the error.
+    fn to_cp437(self) -> Result<Self::Target, char>;
+}
+
+impl<'a> ToCp437 for &'a str {
+    type Target = ::std::borrow::Cow<'a, [u8]>;
+
+    fn to_cp437(self) -> Result<Self::Target, char> {
+        if self.is_ascii() {
+            Ok(self.as_bytes().into())
+        } else {
+            self.chars()
+                .map(|c| from_char(c).ok_or(c))
+                .collect::<Result<Vec<u8>, char>>()
+                .map(Into::into)
+        }
+    }
+}
+
+fn from_char(input: char) -> Option<u8> {
+    if input.is_ascii() {
+        Some(input as u8)
+    } else {
+        (0x80..=0xff).find(|&c| to_char(c) == input)
+    }
+}
+
 fn to_char(input: u8) -> char {
     let output = match input {
         0x00..=0x7f => input as u32,
@@ -189,10 +222,43 @@ mod test {
     fn example_slice() {
         use super::FromCp437;
         let data = b"Cura\x87ao";
-        assert!(::std::str::from_utf8(data).is_err());
+        assert!(String::from_utf8(data.to_vec()).is_err());
         assert_eq!(data.from_cp437(), "Curaçao");
     }
 
+    #[test]
+    fn ascii_slice_is_borrowed() {
+        use super::FromCp437;
+        use std::borrow::Cow;
+        let data: &[u8] = b"dir/file.txt";
+        match data.from_cp437() {
+            Cow::Borrowed(name) => assert_eq!(name, "dir/file.txt"),
+            Cow::Owned(_) => panic!("ASCII names should not be copied"),
+        }
+        assert!(matches!(b"Cura\x87ao"[..].from_cp437(), Cow::Owned(_)));
+    }
+
+    #[test]
+    fn round_trip() {
+        use super::{FromCp437, ToCp437};
+        for i in 0x00_u32..0x100 {
+            let c = super::to_char(i as u8);
+            assert_eq!(super::from_char(c), Some(i as u8));
+        }
+        let name = "╠══╣ Curaçao ½";
+        let encoded = name.to_cp437().unwrap();
+        assert_eq!(encoded.len(), name.chars().count());
+        assert_eq!(encoded.from_cp437(), name);
+    }
+
+    #[test]
+    fn unrepresentable() {
+        use super::ToCp437;
+        assert_eq!("plain".to_cp437().unwrap(), &b"plain"[..]);
+        assert_eq!("日本".to_cp437(), Err('日'));
+        assert_eq!("Curaçao €".to_cp437(), Err('€'));
+    }
+
     #[test]
     fn example_vec() {
         use super::FromCp437;
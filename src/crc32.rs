@@ -1,5 +1,7 @@
 //! Helper module to compute a CRC32 checksum
 
+use std::error;
+use std::fmt;
 use std::io;
 use std::io::prelude::*;
 
@@ -9,12 +11,14 @@ use crc32fast::Hasher;
 pub struct Crc32Reader<R> {
     inner: R,
     hasher: Hasher,
-    check: u32,
+    check: Option<u32>,
 }
 
 impl<R> Crc32Reader<R> {
     /// Get a new Crc32Reader which check the inner reader against checksum.
-    pub fn new(inner: R, checksum: u32) -> Crc32Reader<R> {
+    ///
+    /// Without a checksum, the data is passed through without being hashed or validated.
+    pub fn new(inner: R, checksum: Option<u32>) -> Crc32Reader<R> {
         Crc32Reader {
             inner: inner,
             hasher: Hasher::new(),
@@ -23,7 +27,10 @@ impl<R> Crc32Reader<R> {
     }
 
     fn check_matches(&self) -> bool {
-        self.check == self.hasher.clone().finalize()
+        match self.check {
+            Some(check) => check == self.hasher.clone().finalize(),
+            None => true,
+        }
     }
 
     pub fn into_inner(self) -> R {
@@ -31,16 +38,35 @@ impl<R> Crc32Reader<R> {
     }
 }
 
+/// The error that a Crc32Reader returns when the checksum does not match
+#[derive(Debug)]
+struct ChecksumError;
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid checksum")
+    }
+}
+
+impl error::Error for ChecksumError {}
+
+/// Returns whether an error was caused by a checksum mismatch
+pub fn is_checksum_error(err: &io::Error) -> bool {
+    matches!(err.get_ref(), Some(inner) if inner.is::<ChecksumError>())
+}
+
 impl<R: Read> Read for Crc32Reader<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let count = match self.inner.read(buf) {
-            Ok(0) if !self.check_matches() => {
-                return Err(io::Error::new(io::ErrorKind::Other, "Invalid checksum"))
+            Ok(0) if !buf.is_empty() && !self.check_matches() => {
+                return Err(io::Error::new(io::ErrorKind::Other, ChecksumError))
             }
             Ok(n) => n,
             Err(e) => return Err(e),
         };
-        self.hasher.update(&buf[0..count]);
+        if self.check.is_some() {
+            self.hasher.update(&buf[0..count]);
+        }
         Ok(count)
     }
 }
@@ -4,14 +4,19 @@
 
 pub use crate::compression::CompressionMethod;
 pub use crate::read::ZipArchive;
-pub use crate::types::DateTime;
+pub use crate::types::{AesMode, DateTime, System};
 pub use crate::write::ZipWriter;
 
+#[cfg(feature = "aes")]
+mod aes;
 mod compression;
 mod cp437;
 mod crc32;
+mod multipart;
 pub mod read;
+#[cfg(feature = "reduce")]
+mod reduce;
 pub mod result;
-mod spec;
+pub mod spec;
 mod types;
 pub mod write;
@@ -0,0 +1,124 @@
+//! Reading of archives that are split over multiple parts
+
+use std::io;
+use std::io::prelude::*;
+
+/// A reader that can also seek, such as a file or an object in a remote store
+pub trait ReadSeek: Read + io::Seek {}
+
+impl<T: Read + io::Seek> ReadSeek for T {}
+
+/// A reader over the parts of a split archive, as if they were concatenated
+///
+/// Obtained from `ZipArchive::new_multipart`. Every read and seek is dispatched to the part that
+/// holds the current position, so the parts never have to be joined.
+pub struct MultipartReader {
+    parts: Vec<Box<dyn ReadSeek>>,
+    /// The position of the first byte of each part in the concatenated parts
+    starts: Vec<u64>,
+    len: u64,
+    pos: u64,
+}
+
+impl MultipartReader {
+    /// Combine the parts of a split archive, in the order of their disk numbers
+    pub fn new(parts: Vec<Box<dyn ReadSeek>>, sizes: Vec<u64>) -> io::Result<MultipartReader> {
+        if parts.is_empty() || parts.len() != sizes.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "every part needs exactly one size",
+            ));
+        }
+        let mut starts = Vec::with_capacity(sizes.len());
+        let mut len = 0u64;
+        for size in sizes {
+            starts.push(len);
+            len = len.checked_add(size).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "total size is too large")
+            })?;
+        }
+        Ok(MultipartReader {
+            parts,
+            starts,
+            len,
+            pos: 0,
+        })
+    }
+
+    /// Get the position of a part in the concatenated parts
+    ///
+    /// This translates an offset that is relative to a disk to the position of the reader.
+    pub fn part_start(&self, part: usize) -> Option<u64> {
+        self.starts.get(part).cloned()
+    }
+
+    /// Get the number of parts
+    pub fn parts(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Find the part that holds a position before the end
+    fn part_at(&self, pos: u64) -> usize {
+        match self.starts.binary_search(&pos) {
+            Ok(mut part) => {
+                // Skip empty parts, which start at the same position as the next one
+                while part + 1 < self.starts.len() && self.starts[part + 1] == pos {
+                    part += 1;
+                }
+                part
+            }
+            Err(part) => part - 1,
+        }
+    }
+}
+
+impl Read for MultipartReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let part = self.part_at(self.pos);
+        let part_end = self.starts.get(part + 1).cloned().unwrap_or(self.len);
+        let available = ::std::cmp::min(part_end - self.pos, buf.len() as u64) as usize;
+
+        let reader = &mut self.parts[part];
+        reader.seek(io::SeekFrom::Start(self.pos - self.starts[part]))?;
+        let count = reader.read(&mut buf[..available])?;
+        if count == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "part is shorter than its size",
+            ));
+        }
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl io::Seek for MultipartReader {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::End(offset) => add_signed(self.len, offset),
+            io::SeekFrom::Current(offset) => add_signed(self.pos, offset),
+        };
+        match new_pos {
+            Some(new_pos) => {
+                self.pos = new_pos;
+                Ok(new_pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+fn add_signed(base: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        base.checked_add(offset as u64)
+    } else {
+        base.checked_sub(offset.unsigned_abs())
+    }
+}
@@ -2,28 +2,48 @@
 
 use crate::compression::CompressionMethod;
 use crate::crc32::Crc32Reader;
+pub use crate::multipart::{MultipartReader, ReadSeek};
 use crate::result::{ZipError, ZipResult};
 use crate::spec;
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fs;
 use std::io;
 use std::io::prelude::*;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 use crate::cp437::FromCp437;
-use crate::types::{DateTime, System, ZipFileData};
+use crate::types::{is_enclosed, AesMode, DateTime, System, ZipFileData};
+use crate::write::ZipWriter;
 use podio::{LittleEndian, ReadPodExt};
 
 #[cfg(feature = "deflate")]
-use flate2::read::DeflateDecoder;
+use flate2::bufread::DeflateDecoder;
 
 #[cfg(feature = "bzip2")]
-use bzip2::read::BzDecoder;
+use bzip2::bufread::BzDecoder;
+
+#[cfg(feature = "zstd")]
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+#[cfg(feature = "reduce")]
+use crate::reduce::ReduceDecoder;
+
+#[cfg(feature = "aes")]
+use crate::aes::AesReader;
 
 mod ffi {
     pub const S_IFDIR: u32 = 0o0040000;
     pub const S_IFREG: u32 = 0o0100000;
 }
 
+/// Size of the buffer placed between the archive reader and the decompressor by default
+const DEFAULT_READ_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Largest buffer that is allocated up front based on the size recorded in a header
+const MAX_PREALLOCATION: u64 = 1024 * 1024;
+
 /// Wrapper for reading the contents of a ZIP file.
 ///
 /// ```
@@ -56,38 +76,362 @@ pub struct ZipArchive<R: Read + io::Seek> {
     files: Vec<ZipFileData>,
     names_map: HashMap<String, usize>,
     offset: u64,
+    directory_start: u64,
+    comment: Vec<u8>,
+    comment_start: u64,
+    read_buffer_size: usize,
+    options: ZipArchiveOptions,
+    warnings: Vec<ParseWarning>,
+    entry_warnings: HashMap<usize, Vec<EntryWarning>>,
+    /// The length of the reader, if it was known when the archive was opened
+    reader_len: Option<u64>,
+    /// The password that encrypted files are decrypted with
+    password: Option<Vec<u8>>,
+}
+
+/// Options that control how a ZipArchive is read
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ZipArchiveOptions {
+    strict: bool,
+    recover: bool,
+    lenient_deflate: bool,
+    max_entries: Option<usize>,
+    lazy_names: bool,
+    #[cfg(feature = "encoding-detection")]
+    auto_detect_encoding: bool,
+}
+
+/// A problem that was tolerated while opening an archive in recovery mode
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseWarning {
+    /// The central directory ended in a malformed or truncated header, so fewer files were read
+    /// than the archive declares
+    MissingEntries {
+        /// Number of files declared by the end of central directory record
+        declared: usize,
+        /// Number of files that were actually read
+        parsed: usize,
+    },
+}
+
+/// A reason not to fully trust the data of a file, collected when it is opened
+///
+/// The warnings of a file are available from `ZipArchive::entry_warnings`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntryWarning {
+    /// The file declares that a newer version of the specification is needed to extract it than
+    /// the features this crate finds in it call for, so it may use features that are silently
+    /// ignored
+    VersionNeeded {
+        /// Version needed to extract the file, as declared in the central directory, without the
+        /// host system in its upper byte
+        needed: u16,
+        /// Version that introduced the compression method, encryption and format of the file
+        supported: u16,
+    },
+}
+
+impl ZipArchiveOptions {
+    /// Enable or disable strict mode
+    ///
+    /// In strict mode, values read from the archive headers are checked against the data that is
+    /// actually read where possible, and a mismatch is reported as an error instead of being
+    /// tolerated. This includes comparing the CRC32 in the local header of a file with the one in
+    /// the central directory when the file is opened. The default is `false`.
+    pub fn strict(mut self, strict: bool) -> ZipArchiveOptions {
+        self.strict = strict;
+        self
+    }
+
+    /// Enable or disable recovery mode
+    ///
+    /// In recovery mode, an archive whose central directory is damaged or cut off is still opened
+    /// with the files that could be read, instead of failing entirely. The problems that were
+    /// encountered are available from `ZipArchive::parse_warnings`. The default is `false`.
+    pub fn recover(mut self, recover: bool) -> ZipArchiveOptions {
+        self.recover = recover;
+        self
+    }
+
+    /// Enable or disable accepting deflate streams that lack a final block
+    ///
+    /// Some broken encoders never mark a block of a Deflated file as the final one, so decoding
+    /// fails at the end of the data even though all of it was produced. When enabled, such an
+    /// error is treated as the end of the file if all compressed data was consumed, and the
+    /// contents are still validated against the CRC32. The default is `false`.
+    pub fn lenient_deflate(mut self, lenient_deflate: bool) -> ZipArchiveOptions {
+        self.lenient_deflate = lenient_deflate;
+        self
+    }
+
+    /// Set the largest number of files that an archive may contain
+    ///
+    /// Opening an archive with more files fails with an error as soon as the central directory
+    /// header after the last allowed file is read, which bounds the time and memory that is spent
+    /// on parsing untrusted archives. There is no limit by default.
+    pub fn max_entries(mut self, max_entries: usize) -> ZipArchiveOptions {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Enable or disable deferring the decoding of file names
+    ///
+    /// Normally the name of every file is decoded, and indexed for `ZipArchive::by_name`, when
+    /// the archive is opened. With lazy names, only the raw names are read, which makes opening
+    /// archives with many files faster and lighter when the names are only matched as bytes, e.g.
+    /// with `ZipArchive::raw_names`. The name of a file is decoded when it is opened, and all
+    /// names are decoded and indexed on the first lookup by name. The default is `false`.
+    pub fn lazy_names(mut self, lazy_names: bool) -> ZipArchiveOptions {
+        self.lazy_names = lazy_names;
+        self
+    }
+
+    /// Enable or disable detection of the encoding of file names
+    ///
+    /// File names that are not flagged as UTF-8 are decoded as cp437 by default, even though
+    /// many archivers use the local code page of the system instead. With detection enabled, the
+    /// encoding of each such name is guessed, e.g. Shift-JIS or cp866, and cp437 is only used
+    /// when the guess is not confident. The raw names are available from `ZipFile::name_raw`.
+    /// The default is `false`.
+    #[cfg(feature = "encoding-detection")]
+    pub fn auto_detect_encoding(mut self, auto_detect_encoding: bool) -> ZipArchiveOptions {
+        self.auto_detect_encoding = auto_detect_encoding;
+        self
+    }
+}
+
+/// A read-only view of an in-memory zip archive, which can have multiple files open at once.
+///
+/// Obtained from `ZipArchive::shared`. Every file is read from its own position in the shared
+/// buffer, so no mutable access to the archive is needed.
+#[derive(Clone, Debug)]
+pub struct SharedZipArchive<T: AsRef<[u8]>> {
+    data: T,
+    files: Vec<ZipFileData>,
+    names_map: HashMap<String, usize>,
+    options: ZipArchiveOptions,
+}
+
+/// The parsed central directory of an archive, as returned by `ZipArchive::into_parts`
+///
+/// It can be cloned and attached to new readers of the same archive with
+/// `ZipArchive::from_parts`, so the central directory does not have to be parsed again.
+#[derive(Clone, Debug)]
+pub struct ParsedDirectory {
+    files: Vec<ZipFileData>,
+    names_map: HashMap<String, usize>,
+    offset: u64,
+    directory_start: u64,
     comment: Vec<u8>,
+    comment_start: u64,
+    options: ZipArchiveOptions,
+    warnings: Vec<ParseWarning>,
+}
+
+/// The location of a file's data in an archive, as returned by `ZipArchive::index`
+///
+/// These can be stored in an external index, to later open files with
+/// `ZipArchive::open_from_location` without parsing their local headers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryLocation {
+    /// Name of the file
+    pub name: String,
+    /// Offset of the compressed data in the archive
+    pub data_start: u64,
+    /// Size of the compressed data
+    pub compressed_size: u64,
+    /// Compression method used to store the file
+    pub compression_method: CompressionMethod,
+    /// CRC32 checksum of the uncompressed data
+    pub crc32: u32,
+}
+
+/// The local header of a file, as returned by `ZipArchive::local_header`
+///
+/// The values are returned as they are stored, so e.g. the sizes and checksum are zero or
+/// `0xFFFFFFFF` when they are stored in a data descriptor or ZIP64 extra field instead.
+#[derive(Clone, Debug)]
+pub struct LocalFileHeader {
+    /// Version of the specification needed to extract the file
+    pub version_needed: u16,
+    /// General purpose bit flags
+    pub flags: u16,
+    /// Compression method used to store the file
+    pub compression_method: CompressionMethod,
+    /// Last modified time
+    pub last_modified_time: DateTime,
+    /// CRC32 checksum of the uncompressed data
+    pub crc32: u32,
+    /// Size of the compressed data
+    pub compressed_size: u32,
+    /// Size of the uncompressed data
+    pub uncompressed_size: u32,
+    /// Raw name of the file, as it is encoded in the header
+    pub file_name_raw: Vec<u8>,
+    /// Raw extra field
+    pub extra_field: Vec<u8>,
+    /// Offset of the compressed data in the archive, directly after the header
+    pub data_start: u64,
+}
+
+/// A difference between the entries of two archives, as reported by `ZipArchive::diff`
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntryDiff {
+    /// The entry only exists in the other archive
+    Added(String),
+    /// The entry only exists in this archive
+    Removed(String),
+    /// The entry exists in both archives, but its size, checksum, compression method or
+    /// modification time differs
+    Changed(String),
 }
 
 enum ZipFileReader<'a> {
     NoReader,
-    Stored(Crc32Reader<io::Take<&'a mut dyn Read>>),
+    Raw(Box<dyn BufRead + 'a>),
+    Stored(Crc32Reader<Box<dyn BufRead + 'a>>),
     #[cfg(feature = "deflate")]
-    Deflated(Crc32Reader<flate2::read::DeflateDecoder<io::Take<&'a mut dyn Read>>>),
+    Deflated(Crc32Reader<LenientDeflateDecoder<Box<dyn BufRead + 'a>>>),
     #[cfg(feature = "bzip2")]
-    Bzip2(Crc32Reader<BzDecoder<io::Take<&'a mut dyn Read>>>),
+    Bzip2(Crc32Reader<MultiBzDecoder<Box<dyn BufRead + 'a>>>),
+    #[cfg(feature = "zstd")]
+    Zstd(Crc32Reader<ZstdDecoder<'static, Box<dyn BufRead + 'a>>>),
+    #[cfg(feature = "reduce")]
+    Reduced(Crc32Reader<ReduceDecoder<Box<dyn BufRead + 'a>>>),
 }
 
 /// A struct for reading a zip file
 pub struct ZipFile<'a> {
     data: Cow<'a, ZipFileData>,
     reader: ZipFileReader<'a>,
+    strict: bool,
+    /// Contents that were read ahead by `looks_like_zip`, and are returned first
+    peeked: Vec<u8>,
+    /// The number of bytes that may still be read, if limited with `set_output_limit`
+    output_limit: Option<u64>,
+    /// The number of bytes that were read from the file so far
+    bytes_read: u64,
+    /// Whether the file is read from a stream, rather than from a `ZipArchive`
+    streamed: bool,
+    /// For a streamed file whose checksum is only in the data descriptor that follows it, the
+    /// checksum of the contents read so far, and where to store it once the file is dropped
+    descriptor_crc32: Option<(crc32fast::Hasher, &'a mut Option<u32>)>,
+}
+
+/// A deflate decoder that can accept a stream that ends without a final block
+#[cfg(feature = "deflate")]
+struct LenientDeflateDecoder<R: BufRead> {
+    decoder: DeflateDecoder<R>,
+    lenient: bool,
+}
+
+#[cfg(feature = "deflate")]
+impl<R: BufRead> LenientDeflateDecoder<R> {
+    fn new(reader: R, lenient: bool) -> LenientDeflateDecoder<R> {
+        LenientDeflateDecoder {
+            decoder: DeflateDecoder::new(reader),
+            lenient,
+        }
+    }
+
+    fn into_inner(self) -> R {
+        self.decoder.into_inner()
+    }
+}
+
+#[cfg(feature = "deflate")]
+impl<R: BufRead> Read for LenientDeflateDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.decoder.read(buf) {
+            Err(e) if self.lenient => {
+                // Everything before the error was produced, so the stream only lacks its end if
+                // all compressed data was consumed. The CRC32 check catches anything else.
+                if self.decoder.get_mut().fill_buf()?.is_empty() {
+                    Ok(0)
+                } else {
+                    Err(e)
+                }
+            }
+            result => result,
+        }
+    }
+}
+
+/// A bzip2 decoder that keeps decoding when a stream is followed by another one
+///
+/// Some tools compress a file as several concatenated bzip2 streams, while `BzDecoder` stops at
+/// the end of the first stream.
+#[cfg(feature = "bzip2")]
+struct MultiBzDecoder<R: BufRead> {
+    decoder: Option<BzDecoder<R>>,
+}
+
+#[cfg(feature = "bzip2")]
+impl<R: BufRead> MultiBzDecoder<R> {
+    fn new(reader: R) -> MultiBzDecoder<R> {
+        MultiBzDecoder {
+            decoder: Some(BzDecoder::new(reader)),
+        }
+    }
+
+    fn into_inner(self) -> R {
+        self.decoder.unwrap().into_inner()
+    }
+}
+
+#[cfg(feature = "bzip2")]
+impl<R: BufRead> Read for MultiBzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let count = self.decoder.as_mut().unwrap().read(buf)?;
+            if count > 0 || buf.is_empty() {
+                return Ok(count);
+            }
+            // The current stream has ended, start a new one if there is any data left
+            let mut reader = self.decoder.take().unwrap().into_inner();
+            let finished = reader.fill_buf()?.is_empty();
+            self.decoder = Some(BzDecoder::new(reader));
+            if finished {
+                return Ok(0);
+            }
+        }
+    }
 }
 
 fn unsupported_zip_error<T>(detail: &'static str) -> ZipResult<T> {
     Err(ZipError::UnsupportedArchive(detail))
 }
 
+/// Check that a file is either not encrypted, or can be decrypted with the given password
+fn check_decryption(data: &ZipFileData, password: &Option<Vec<u8>>) -> ZipResult<()> {
+    if !data.encrypted {
+        return Ok(());
+    }
+    if cfg!(feature = "aes") && data.aes_mode.is_some() {
+        return match *password {
+            Some(_) => Ok(()),
+            None => unsupported_zip_error("A password is needed to read encrypted files"),
+        };
+    }
+    unsupported_zip_error("Encrypted files are not supported")
+}
+
+#[cfg_attr(
+    not(all(feature = "deflate", feature = "reduce")),
+    allow(unused_variables)
+)]
 fn make_reader<'a>(
     compression_method: crate::compression::CompressionMethod,
-    crc32: u32,
-    reader: io::Take<&'a mut dyn io::Read>,
+    crc32: Option<u32>,
+    uncompressed_size: u64,
+    lenient_deflate: bool,
+    reader: Box<dyn BufRead + 'a>,
 ) -> ZipResult<ZipFileReader<'a>> {
     match compression_method {
         CompressionMethod::Stored => Ok(ZipFileReader::Stored(Crc32Reader::new(reader, crc32))),
         #[cfg(feature = "deflate")]
         CompressionMethod::Deflated => {
-            let deflate_reader = DeflateDecoder::new(reader);
+            let deflate_reader = LenientDeflateDecoder::new(reader, lenient_deflate);
             Ok(ZipFileReader::Deflated(Crc32Reader::new(
                 deflate_reader,
                 crc32,
@@ -95,17 +439,69 @@ fn make_reader<'a>(
         }
         #[cfg(feature = "bzip2")]
         CompressionMethod::Bzip2 => {
-            let bzip2_reader = BzDecoder::new(reader);
+            let bzip2_reader = MultiBzDecoder::new(reader);
             Ok(ZipFileReader::Bzip2(Crc32Reader::new(bzip2_reader, crc32)))
         }
+        #[cfg(feature = "zstd")]
+        CompressionMethod::Zstd => {
+            let zstd_reader = ZstdDecoder::with_buffer(reader)?;
+            Ok(ZipFileReader::Zstd(Crc32Reader::new(zstd_reader, crc32)))
+        }
+        #[cfg(feature = "reduce")]
+        CompressionMethod::Reduced(factor) => {
+            let reduce_reader = ReduceDecoder::new(reader, factor, uncompressed_size);
+            Ok(ZipFileReader::Reduced(Crc32Reader::new(
+                reduce_reader,
+                crc32,
+            )))
+        }
         _ => unsupported_zip_error("Compression method not supported"),
     }
 }
 
+/// Write a file or create a directory at `outpath`, with the permissions stored in the archive
+fn extract_to(file: &mut ZipFile<'_>, outpath: &Path) -> ZipResult<()> {
+    if file.is_dir() {
+        fs::create_dir_all(outpath)?;
+    } else {
+        if let Some(p) = outpath.parent() {
+            fs::create_dir_all(p)?;
+        }
+        let mut outfile = fs::File::create(outpath)?;
+        file.copy_to(&mut outfile)?;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Some(mode) = file.unix_mode() {
+            fs::set_permissions(outpath, fs::Permissions::from_mode(mode))?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `make_reader` can decompress a compression method with the enabled features
+fn can_decompress(compression_method: CompressionMethod) -> bool {
+    match compression_method {
+        CompressionMethod::Stored => true,
+        #[cfg(feature = "deflate")]
+        CompressionMethod::Deflated => true,
+        #[cfg(feature = "bzip2")]
+        CompressionMethod::Bzip2 => true,
+        #[cfg(feature = "zstd")]
+        CompressionMethod::Zstd => true,
+        #[cfg(feature = "reduce")]
+        CompressionMethod::Reduced(_) => true,
+        _ => false,
+    }
+}
+
 impl<R: Read + io::Seek> ZipArchive<R> {
     /// Get the directory start offset and number of files. This is done in a
     /// separate function to ease the control flow design.
-    fn get_directory_counts(
+    pub(crate) fn get_directory_counts(
         reader: &mut R,
         footer: &spec::CentralDirectoryEnd,
         cde_start_pos: u64,
@@ -137,23 +533,7 @@ impl<R: Read + io::Seek> ZipArchive<R> {
             None
         };
 
-        match zip64locator {
-            None => {
-                // Some zip files have data prepended to them, resulting in the
-                // offsets all being too small. Get the amount of error by comparing
-                // the actual file position we found the CDE at with the offset
-                // recorded in the CDE.
-                let archive_offset = cde_start_pos
-                    .checked_sub(footer.central_directory_size as u64)
-                    .and_then(|x| x.checked_sub(footer.central_directory_offset as u64))
-                    .ok_or(ZipError::InvalidArchive(
-                        "Invalid central directory size or offset",
-                    ))?;
-
-                let directory_start = footer.central_directory_offset as u64 + archive_offset;
-                let number_of_files = footer.number_of_files_on_this_disk as usize;
-                return Ok((archive_offset, directory_start, number_of_files));
-            }
+        let zip64 = match zip64locator {
             Some(locator64) => {
                 // If we got here, this is indeed a ZIP64 file.
 
@@ -174,12 +554,71 @@ impl<R: Read + io::Seek> ZipArchive<R> {
                     .ok_or(ZipError::InvalidArchive(
                         "File cannot contain ZIP64 central directory end",
                     ))?;
-                let (footer, archive_offset) = spec::Zip64CentralDirectoryEnd::find_and_parse(
+
+                // The record normally directly precedes the locator. Look there first, because
+                // searching forward can find the record of an archive that was concatenated
+                // before this one.
+                let locator_pos = cde_start_pos - spec::ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIZE;
+                match spec::Zip64CentralDirectoryEnd::parse_before_locator(
                     reader,
                     locator64.end_of_central_directory_offset,
-                    search_upper_bound,
-                )?;
+                    locator_pos,
+                )? {
+                    Some(found) => Some(found),
+                    None => Some(spec::Zip64CentralDirectoryEnd::find_and_parse(
+                        reader,
+                        locator64.end_of_central_directory_offset,
+                        search_upper_bound,
+                    )?),
+                }
+            }
+            // Some writers leave out the locator, even though the standard footer defers to the
+            // ZIP64 footer. In that case we look for the ZIP64 footer itself.
+            None if footer.has_zip64_markers() => {
+                Self::find_zip64_footer_without_locator(reader, cde_start_pos)?
+            }
+            None => None,
+        };
+
+        match zip64 {
+            None => {
+                // Some zip files have data prepended to them, resulting in the
+                // offsets all being too small. Get the amount of error by comparing
+                // the actual file position we found the CDE at with the offset
+                // recorded in the CDE.
+                let archive_offset = cde_start_pos
+                    .checked_sub(footer.central_directory_size as u64)
+                    .and_then(|x| x.checked_sub(footer.central_directory_offset as u64))
+                    .ok_or(ZipError::InvalidArchive(
+                        "Invalid central directory size or offset",
+                    ))?;
+
+                let number_of_files = footer.number_of_files_on_this_disk as usize;
+                if number_of_files == 0 {
+                    let directory_start = footer.central_directory_offset as u64 + archive_offset;
+                    return Ok((archive_offset, directory_start, number_of_files));
+                }
 
+                // The computed offset is wrong when there is data between the central directory
+                // and its end record. If the archive was written with offsets that already
+                // account for prepended data, e.g. by a self-extractor, the recorded offset is
+                // still right. Use the first candidate that points at a central directory.
+                let candidates: &[u64] = if archive_offset == 0 {
+                    &[0]
+                } else {
+                    &[archive_offset, 0]
+                };
+                for &offset in candidates {
+                    let directory_start = footer.central_directory_offset as u64 + offset;
+                    if Self::is_central_directory_start(reader, directory_start)? {
+                        return Ok((offset, directory_start, number_of_files));
+                    }
+                }
+                Err(ZipError::InvalidArchive(
+                    "Could not find the start of the central directory",
+                ))
+            }
+            Some((footer, archive_offset)) => {
                 if footer.disk_number != footer.disk_with_central_directory {
                     return unsupported_zip_error("Support for multi-disk files is not implemented");
                 }
@@ -194,8 +633,53 @@ impl<R: Read + io::Seek> ZipArchive<R> {
         }
     }
 
+    /// Check whether a central directory header starts at `pos`
+    fn is_central_directory_start(reader: &mut R, pos: u64) -> ZipResult<bool> {
+        reader.seek(io::SeekFrom::Start(pos))?;
+        match reader.read_u32::<LittleEndian>() {
+            Ok(signature) => Ok(signature == spec::CENTRAL_DIRECTORY_HEADER_SIGNATURE),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Find the ZIP64 footer of an archive that has no ZIP64 locator, and the archive offset
+    /// implied by its position. Returns `None` if there is no ZIP64 footer after all.
+    fn find_zip64_footer_without_locator(
+        reader: &mut R,
+        cde_start_pos: u64,
+    ) -> ZipResult<Option<(spec::Zip64CentralDirectoryEnd, u64)>> {
+        let search_upper_bound = match cde_start_pos.checked_sub(56) {
+            // minimum size of Zip64CentralDirectoryEnd
+            Some(bound) => bound,
+            None => return Ok(None),
+        };
+        let search_result =
+            spec::Zip64CentralDirectoryEnd::find_and_parse_backwards(reader, search_upper_bound);
+        let (footer, footer_pos) = match search_result {
+            Ok(found) => found,
+            Err(ZipError::InvalidArchive(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        // The ZIP64 footer directly follows the central directory, which gives us the amount of
+        // data prepended to the archive.
+        let archive_offset = footer_pos
+            .checked_sub(footer.central_directory_size)
+            .and_then(|x| x.checked_sub(footer.central_directory_offset))
+            .ok_or(ZipError::InvalidArchive(
+                "Invalid central directory size or offset",
+            ))?;
+        Ok(Some((footer, archive_offset)))
+    }
+
     /// Opens a Zip archive and parses the central directory
-    pub fn new(mut reader: R) -> ZipResult<ZipArchive<R>> {
+    pub fn new(reader: R) -> ZipResult<ZipArchive<R>> {
+        Self::with_options(reader, Default::default())
+    }
+
+    /// Opens a Zip archive with the given options and parses the central directory
+    pub fn with_options(mut reader: R, options: ZipArchiveOptions) -> ZipResult<ZipArchive<R>> {
         let (footer, cde_start_pos) = spec::CentralDirectoryEnd::find_and_parse(&mut reader)?;
 
         if footer.disk_number != footer.disk_with_central_directory {
@@ -215,20 +699,106 @@ impl<R: Read + io::Seek> ZipArchive<R> {
         }
 
         for _ in 0..number_of_files {
-            let file = central_header_to_zip_file(&mut reader, archive_offset)?;
-            names_map.insert(file.file_name.clone(), files.len());
+            let file = match central_header_to_zip_file(&mut reader, archive_offset, &options) {
+                Ok(file) => file,
+                // The directory is malformed or truncated from here on
+                Err(ZipError::InvalidArchive(_)) | Err(ZipError::Io(_)) if options.recover => break,
+                Err(e) => return Err(e),
+            };
+            if options.max_entries == Some(files.len()) {
+                return Err(ZipError::InvalidArchive("Too many entries"));
+            }
+            if !options.lazy_names {
+                names_map.insert(file.file_name.clone(), files.len());
+            }
             files.push(file);
         }
 
+        // A count of 0xFFFF defers to the ZIP64 footer. If that is missing, the real count was
+        // truncated, which shows as more central headers following the ones that were read.
+        if number_of_files == spec::ZIP64_ENTRY_THR
+            && files.len() == number_of_files
+            && reader.read_u32::<LittleEndian>().ok()
+                == Some(spec::CENTRAL_DIRECTORY_HEADER_SIGNATURE)
+        {
+            return Err(ZipError::InvalidArchive(
+                "More than 65535 files, but no ZIP64 central directory end",
+            ));
+        }
+
+        let mut warnings = Vec::new();
+        if files.len() < number_of_files {
+            warnings.push(ParseWarning::MissingEntries {
+                declared: number_of_files,
+                parsed: files.len(),
+            });
+        }
+
         Ok(ZipArchive {
             reader: reader,
             files: files,
             names_map: names_map,
             offset: archive_offset,
+            directory_start,
             comment: footer.zip_file_comment,
+            comment_start: cde_start_pos + spec::CENTRAL_DIRECTORY_END_SIZE,
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            options,
+            warnings,
+            entry_warnings: HashMap::new(),
+            reader_len: None,
+            password: None,
         })
     }
 
+    /// Get the problems that were tolerated while opening the archive
+    ///
+    /// This is always empty unless the archive was opened in recovery mode, see
+    /// `ZipArchiveOptions::recover`.
+    pub fn parse_warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    /// Get the warnings that were collected when a file was opened
+    ///
+    /// These are collected by `by_index` and the other methods that open a file by its index or
+    /// name, and are empty for a file that has not been opened yet.
+    pub fn entry_warnings(&self, file_number: usize) -> &[EntryWarning] {
+        self.entry_warnings
+            .get(&file_number)
+            .map_or(&[], |warnings| &warnings[..])
+    }
+
+    /// Get the raw extra field of a file's central directory header
+    ///
+    /// This holds every extra field of the file, including the ones this crate does not know,
+    /// exactly as they are stored in the archive. Returns an empty slice if there is no file with
+    /// the given index.
+    pub fn entry_extra(&self, file_number: usize) -> &[u8] {
+        self.files
+            .get(file_number)
+            .map_or(&[], |file| &file.extra_field[..])
+    }
+
+    /// Set the size of the buffer used when reading the compressed data of a file.
+    ///
+    /// The compressed data is read from the underlying reader in chunks of this size, independent
+    /// of how much data is requested from the `ZipFile`. This improves throughput for callers that
+    /// read in small pieces. The default is 8 KiB. A size of zero is treated as one.
+    pub fn set_read_buffer_size(&mut self, size: usize) {
+        self.read_buffer_size = ::std::cmp::max(size, 1);
+    }
+
+    /// Set the password that files encrypted with WinZip AES are decrypted with
+    ///
+    /// Encrypted files are then read like other files with `by_index` and `by_name`, which fail
+    /// if the password does not match. The authentication code that follows the data is checked
+    /// once all data is read. `None`, the default, makes encrypted files unreadable again.
+    #[cfg(feature = "aes")]
+    pub fn set_password(&mut self, password: Option<&[u8]>) {
+        self.password = password.map(|password| password.to_vec());
+    }
+
     /// Number of files contained in this zip.
     ///
     /// ```
@@ -245,6 +815,26 @@ impl<R: Read + io::Seek> ZipArchive<R> {
         self.files.len()
     }
 
+    /// Get the sum of the compressed sizes of all files, as stored in the central directory
+    ///
+    /// Together with `decompressed_size` this gives the compression ratio of the archive. The
+    /// sum saturates at `u64::MAX` for archives that claim impossibly large files.
+    pub fn compressed_size(&self) -> u64 {
+        self.files.iter().fold(0, |total: u64, file| {
+            total.saturating_add(file.compressed_size)
+        })
+    }
+
+    /// Get the sum of the uncompressed sizes of all files, as stored in the central directory
+    ///
+    /// The sum saturates at `u64::MAX`. As the sizes are not checked until the files are read,
+    /// this should not be relied on to limit the size of extracted data.
+    pub fn decompressed_size(&self) -> u64 {
+        self.files.iter().fold(0, |total: u64, file| {
+            total.saturating_add(file.uncompressed_size)
+        })
+    }
+
     /// Get the offset from the beginning of the underlying reader that this zip begins at, in bytes.
     ///
     /// Normally this value is zero, but if the zip has arbitrary data prepended to it, then this value will be the size
@@ -253,8 +843,147 @@ impl<R: Read + io::Seek> ZipArchive<R> {
         self.offset
     }
 
+    /// Get the comment of the archive, which is stored after the central directory
+    pub fn comment(&self) -> &[u8] {
+        &self.comment
+    }
+
+    /// Get the position of the archive comment in the underlying reader, and its length
+    ///
+    /// The comment is the last part of the archive. Together with `into_inner`, this allows
+    /// overwriting it in place. A comment of a different length also requires updating the
+    /// comment length field, which is stored in the two bytes before the comment.
+    pub fn comment_location(&self) -> (u64, usize) {
+        (self.comment_start, self.comment.len())
+    }
+
+    /// Get the indices of all files, ordered by the position of their data in the archive
+    ///
+    /// Reading the files in this order accesses the underlying reader sequentially, which is
+    /// much faster than jumping back and forth on slow storage.
+    pub fn indices_by_offset(&self) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.files.len()).collect();
+        indices.sort_by_key(|&i| self.files[i].header_start);
+        indices
+    }
+
+    /// Get the total size of the archive in bytes, including any data prepended to it
+    ///
+    /// This is the length of the underlying reader, which is useful to track progress through
+    /// the archive.
+    pub fn archive_size(&mut self) -> ZipResult<u64> {
+        Ok(self.reader.seek(io::SeekFrom::End(0))?)
+    }
+
+    /// Find the archives that were concatenated before this one
+    ///
+    /// When archives are appended to each other, the last one is opened, and the earlier ones
+    /// are part of the data that is prepended to it. This searches that data for complete
+    /// archives, and returns the range of each in the underlying reader, in order. Such a range
+    /// can be opened as an archive of its own, e.g. by limiting a reader to it.
+    pub fn inner_archives(&mut self) -> ZipResult<Vec<Range<u64>>> {
+        // The earlier archives end before the first file of this one, even if the offsets of this
+        // archive already account for the prepended data
+        let end = self
+            .files
+            .iter()
+            .map(|file| file.header_start)
+            .fold(self.directory_start, ::std::cmp::min);
+
+        const CHUNK_SIZE: u64 = 1 << 16;
+        let signature = spec::CENTRAL_DIRECTORY_END_SIGNATURE.to_le_bytes();
+        let mut candidates = Vec::new();
+        let mut chunk_start = 0;
+        while chunk_start < end {
+            // Chunks overlap by three bytes, so no signature is split between two chunks
+            let chunk_len = ::std::cmp::min(CHUNK_SIZE + 3, end - chunk_start);
+            self.reader.seek(io::SeekFrom::Start(chunk_start))?;
+            let chunk = ReadPodExt::read_exact(&mut self.reader, chunk_len as usize)?;
+            for (i, window) in chunk.windows(4).enumerate() {
+                if window == signature && (i as u64) < CHUNK_SIZE {
+                    candidates.push(chunk_start + i as u64);
+                }
+            }
+            chunk_start += CHUNK_SIZE;
+        }
+
+        let mut archives = Vec::new();
+        for pos in candidates {
+            if let Some(range) = Self::archive_ending_at(&mut self.reader, pos)? {
+                if range.end <= end {
+                    archives.push(range);
+                }
+            }
+        }
+        Ok(archives)
+    }
+
+    /// Get the range of the archive whose end of central directory record is at `pos`, or `None`
+    /// if the data at `pos` is not the end of a valid archive
+    fn archive_ending_at(reader: &mut R, pos: u64) -> ZipResult<Option<Range<u64>>> {
+        reader.seek(io::SeekFrom::Start(pos))?;
+        let footer = match spec::CentralDirectoryEnd::parse(reader) {
+            Ok(footer) => footer,
+            Err(ZipError::InvalidArchive(_)) => return Ok(None),
+            Err(ZipError::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        };
+        let archive_end =
+            pos + spec::CENTRAL_DIRECTORY_END_SIZE + footer.zip_file_comment.len() as u64;
+
+        // Locate the central directory, in the ZIP64 record directly before the locator if the
+        // values do not fit the standard record
+        let zip64 = footer.has_zip64_markers();
+        let (record_pos, directory_size, directory_offset, number_of_files) = if zip64 {
+            let locator_pos = pos.saturating_sub(spec::ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIZE);
+            let found =
+                spec::Zip64CentralDirectoryEnd::parse_before_locator(reader, 0, locator_pos)?;
+            let (record, record_pos) = match found {
+                Some(found) => found,
+                None => return Ok(None),
+            };
+            (
+                record_pos,
+                record.central_directory_size,
+                record.central_directory_offset,
+                record.number_of_files,
+            )
+        } else {
+            (
+                pos,
+                footer.central_directory_size as u64,
+                footer.central_directory_offset as u64,
+                footer.number_of_files as u64,
+            )
+        };
+        let archive_start = match record_pos
+            .checked_sub(directory_size)
+            .and_then(|x| x.checked_sub(directory_offset))
+        {
+            Some(archive_start) => archive_start,
+            None => return Ok(None),
+        };
+        if number_of_files > 0
+            && !Self::is_central_directory_start(reader, archive_start + directory_offset)?
+        {
+            return Ok(None);
+        }
+        Ok(Some(archive_start..archive_end))
+    }
+
+    /// Iterate over the raw names of all files, in the order of the central directory
+    ///
+    /// The names are returned as stored in the archive, without decoding them, so this does not
+    /// allocate. See `ZipArchiveOptions::lazy_names` to also skip decoding them when opening.
+    pub fn raw_names(&self) -> impl Iterator<Item = &[u8]> {
+        self.files.iter().map(|file| &*file.file_name_raw)
+    }
+
     /// Search for a file entry by name
     pub fn by_name<'a>(&'a mut self, name: &str) -> ZipResult<ZipFile<'a>> {
+        self.decode_names();
         let index = match self.names_map.get(name) {
             Some(index) => *index,
             None => {
@@ -264,497 +993,4477 @@ impl<R: Read + io::Seek> ZipArchive<R> {
         self.by_index(index)
     }
 
+    /// Get a contained file by the offset of its local header
+    ///
+    /// The offset is relative to the start of the underlying reader, as found in an index or in
+    /// a signature record. Returns `ZipError::FileNotFound` if no file starts at this offset.
+    pub fn by_header_offset<'a>(&'a mut self, offset: u64) -> ZipResult<ZipFile<'a>> {
+        let index = self
+            .files
+            .iter()
+            .position(|file| file.header_start == offset)
+            .ok_or(ZipError::FileNotFound)?;
+        self.by_index(index)
+    }
+
     /// Get a contained file by index
     pub fn by_index<'a>(&'a mut self, file_number: usize) -> ZipResult<ZipFile<'a>> {
-        if file_number >= self.files.len() {
-            return Err(ZipError::FileNotFound);
-        }
-        let ref mut data = self.files[file_number];
-
-        if data.encrypted {
-            return unsupported_zip_error("Encrypted files are not supported");
-        }
+        self.by_index_internal(file_number, false, true)
+    }
 
-        // Parse local header
-        self.reader.seek(io::SeekFrom::Start(data.header_start))?;
-        let signature = self.reader.read_u32::<LittleEndian>()?;
-        if signature != spec::LOCAL_FILE_HEADER_SIGNATURE {
-            return Err(ZipError::InvalidArchive("Invalid local file header"));
+    /// Get a contained file by index without decompressing it
+    ///
+    /// The returned `ZipFile` yields the data exactly as it is stored in the archive. This is the
+    /// method-specific payload, e.g. the deflate stream for Deflated files, or the MP3/JPEG/WavPack
+    /// specific encoding for methods that this crate can not decompress. The CRC32 checksum is
+    /// not validated, as it applies to the uncompressed data.
+    pub fn by_index_raw<'a>(&'a mut self, file_number: usize) -> ZipResult<ZipFile<'a>> {
+        self.by_index_internal(file_number, true, false)
+    }
+
+    /// Get a contained file by index without validating its checksum
+    ///
+    /// **Warning: the CRC32 checksum of the data is not computed, so corrupted or tampered data is
+    /// returned without any error.** This avoids the cost of hashing all data, and should only be
+    /// used when the integrity of the archive is guaranteed by other means, e.g. a signature over
+    /// the whole archive. Use `by_index` for all other cases.
+    pub fn by_index_no_crc<'a>(&'a mut self, file_number: usize) -> ZipResult<ZipFile<'a>> {
+        self.by_index_internal(file_number, false, false)
+    }
+
+    /// Check whether a file can be read with `by_index`, without reading from the archive
+    ///
+    /// This returns the error that reading would fail with when the file is encrypted, when its
+    /// compression method is unknown or not enabled by the features of this build, or when the
+    /// central directory stores a placeholder instead of its size. Other problems, such as a
+    /// corrupt local header or corrupt data, are only detected when the file is read.
+    pub fn can_read(&self, file_number: usize) -> ZipResult<()> {
+        let data = self.files.get(file_number).ok_or(ZipError::FileNotFound)?;
+        check_decryption(data, &self.password)?;
+        if !can_decompress(data.compression_method) {
+            return unsupported_zip_error("Compression method not supported");
         }
+        if data.compressed_size == spec::ZIP64_BYTES_THR
+            || data.uncompressed_size == spec::ZIP64_BYTES_THR
+        {
+            return Err(ZipError::InvalidArchive(
+                "File size is missing from the central directory",
+            ));
+        }
+        Ok(())
+    }
 
-        self.reader.seek(io::SeekFrom::Current(22))?;
-        let file_name_length = self.reader.read_u16::<LittleEndian>()? as u64;
-        let extra_field_length = self.reader.read_u16::<LittleEndian>()? as u64;
-        let magic_and_header = 4 + 22 + 2 + 2;
-        data.data_start =
-            data.header_start + magic_and_header + file_name_length + extra_field_length;
+    fn by_index_internal<'a>(
+        &'a mut self,
+        file_number: usize,
+        raw: bool,
+        check_crc: bool,
+    ) -> ZipResult<ZipFile<'a>> {
+        if file_number >= self.files.len() {
+            return Err(ZipError::FileNotFound);
+        }
+        let len = self.reader_len()?;
+        let ref mut data = self.files[file_number];
+        decode_deferred_name(data, &self.options);
+
+        if !raw {
+            check_decryption(data, &self.password)?;
+        }
+
+        if self.options.strict {
+            // A zero CRC in either header means it is stored in a data descriptor instead
+            let local_crc32 = read_local_crc32(&mut self.reader, data)?;
+            if local_crc32 != 0 && data.crc32 != 0 && local_crc32 != data.crc32 {
+                return Err(ZipError::InvalidArchive("Local/central CRC mismatch"));
+            }
+        }
+
+        let data = read_local_header(&mut self.reader, data)?;
 
+        // The features are taken from both headers, as only the local one may hold a ZIP64 field
+        // The upper byte is the host system, which some tools also set in this field
+        let needed = data.version_needed_to_extract & 0xff;
+        let supported = version_of_features(&data);
+        if needed > supported {
+            self.entry_warnings.insert(
+                file_number,
+                vec![EntryWarning::VersionNeeded { needed, supported }],
+            );
+        }
+
+        // Data that does not fit in the archive would otherwise read like a truncated file
+        let data_end = data.data_start.checked_add(data.compressed_size);
+        if !matches!(data_end, Some(end) if end <= len) {
+            return Err(ZipError::InvalidArchive("Entry data out of bounds"));
+        }
+
+        self.open_data(data, raw, check_crc)
+    }
+
+    /// Open the data of a file, once its location is known
+    fn open_data(
+        &mut self,
+        data: ZipFileData,
+        raw: bool,
+        check_crc: bool,
+    ) -> ZipResult<ZipFile<'_>> {
         self.reader.seek(io::SeekFrom::Start(data.data_start))?;
         let limit_reader = (self.reader.by_ref() as &mut dyn Read).take(data.compressed_size);
+        #[cfg(feature = "aes")]
+        let limit_reader: Box<dyn Read> = match (data.aes_mode, self.password.as_ref()) {
+            (Some(mode), Some(password)) if !raw => Box::new(AesReader::new(
+                limit_reader,
+                mode,
+                password,
+                data.compressed_size,
+            )?),
+            _ => Box::new(limit_reader),
+        };
+        let buf_reader = io::BufReader::with_capacity(self.read_buffer_size, limit_reader);
 
         Ok(ZipFile {
-            reader: make_reader(data.compression_method, data.crc32, limit_reader)?,
-            data: Cow::Borrowed(data),
+            reader: if raw {
+                ZipFileReader::Raw(Box::new(buf_reader))
+            } else {
+                // AE-2 encrypted files store no checksum, their authentication code is checked
+                // instead
+                let ae2 = data.aes_mode.is_some() && data.crc32 == 0;
+                let crc32 = if check_crc && !ae2 {
+                    Some(data.crc32)
+                } else {
+                    None
+                };
+                make_reader(
+                    data.compression_method,
+                    crc32,
+                    data.uncompressed_size,
+                    self.options.lenient_deflate,
+                    Box::new(buf_reader),
+                )?
+            },
+            data: Cow::Owned(data),
+            strict: self.options.strict,
+            peeked: Vec::new(),
+            output_limit: None,
+            bytes_read: 0,
+            streamed: false,
+            descriptor_crc32: None,
         })
     }
 
-    /// Unwrap and return the inner reader object
+    /// Parse the local header of a file
     ///
-    /// The position of the reader is undefined.
-    pub fn into_inner(self) -> R {
-        self.reader
+    /// The local header repeats most of the information of the central directory, and can
+    /// differ from it in damaged or crafted archives.
+    pub fn local_header(&mut self, file_number: usize) -> ZipResult<LocalFileHeader> {
+        let data = self.files.get(file_number).ok_or(ZipError::FileNotFound)?;
+        let reader = &mut self.reader;
+        reader.seek(io::SeekFrom::Start(data.header_start))?;
+        if reader.read_u32::<LittleEndian>()? != spec::LOCAL_FILE_HEADER_SIGNATURE {
+            return Err(ZipError::InvalidArchive("Invalid local file header"));
+        }
+        let version_needed = reader.read_u16::<LittleEndian>()?;
+        let flags = reader.read_u16::<LittleEndian>()?;
+        let compression_method = CompressionMethod::from_u16(reader.read_u16::<LittleEndian>()?);
+        let last_mod_time = reader.read_u16::<LittleEndian>()?;
+        let last_mod_date = reader.read_u16::<LittleEndian>()?;
+        let crc32 = reader.read_u32::<LittleEndian>()?;
+        let compressed_size = reader.read_u32::<LittleEndian>()?;
+        let uncompressed_size = reader.read_u32::<LittleEndian>()?;
+        let file_name_length = reader.read_u16::<LittleEndian>()? as usize;
+        let extra_field_length = reader.read_u16::<LittleEndian>()? as usize;
+        let file_name_raw = ReadPodExt::read_exact(reader, file_name_length)?;
+        let extra_field = ReadPodExt::read_exact(reader, extra_field_length)?;
+
+        let magic_and_header = 4 + 22 + 2 + 2;
+        Ok(LocalFileHeader {
+            version_needed,
+            flags,
+            compression_method,
+            last_modified_time: DateTime::from_msdos(last_mod_date, last_mod_time),
+            crc32,
+            compressed_size,
+            uncompressed_size,
+            data_start: data.header_start
+                + magic_and_header
+                + file_name_length as u64
+                + extra_field_length as u64,
+            file_name_raw,
+            extra_field,
+        })
     }
-}
 
-fn central_header_to_zip_file<R: Read + io::Seek>(
-    reader: &mut R,
-    archive_offset: u64,
-) -> ZipResult<ZipFileData> {
-    // Parse central header
-    let signature = reader.read_u32::<LittleEndian>()?;
-    if signature != spec::CENTRAL_DIRECTORY_HEADER_SIGNATURE {
-        return Err(ZipError::InvalidArchive("Invalid Central Directory header"));
+    /// Get the range of bytes that a file occupies in the archive
+    ///
+    /// The range starts at the local header and ends after the compressed data, or after the data
+    /// descriptor if the file has one. Copying these bytes gives a complete local entry.
+    pub fn entry_extent(&mut self, file_number: usize) -> ZipResult<Range<u64>> {
+        let len = self.reader_len()?;
+        let data = self.files.get(file_number).ok_or(ZipError::FileNotFound)?;
+        let data = read_local_header(&mut self.reader, data)?;
+        self.reader.seek(io::SeekFrom::Start(
+            data.data_start.saturating_add(data.compressed_size),
+        ))?;
+        if data.using_data_descriptor {
+            read_data_descriptor(&mut self.reader, data.crc32, data.large_file)?;
+        }
+        let end = self.reader.stream_position()?;
+        if end > len {
+            return Err(ZipError::InvalidArchive("Entry data out of bounds"));
+        }
+        Ok(data.header_start..end)
     }
 
-    let version_made_by = reader.read_u16::<LittleEndian>()?;
-    let _version_to_extract = reader.read_u16::<LittleEndian>()?;
-    let flags = reader.read_u16::<LittleEndian>()?;
-    let encrypted = flags & 1 == 1;
-    let is_utf8 = flags & (1 << 11) != 0;
-    let compression_method = reader.read_u16::<LittleEndian>()?;
-    let last_mod_time = reader.read_u16::<LittleEndian>()?;
-    let last_mod_date = reader.read_u16::<LittleEndian>()?;
-    let crc32 = reader.read_u32::<LittleEndian>()?;
-    let compressed_size = reader.read_u32::<LittleEndian>()?;
-    let uncompressed_size = reader.read_u32::<LittleEndian>()?;
-    let file_name_length = reader.read_u16::<LittleEndian>()? as usize;
-    let extra_field_length = reader.read_u16::<LittleEndian>()? as usize;
-    let file_comment_length = reader.read_u16::<LittleEndian>()? as usize;
-    let _disk_number = reader.read_u16::<LittleEndian>()?;
-    let _internal_file_attributes = reader.read_u16::<LittleEndian>()?;
-    let external_file_attributes = reader.read_u32::<LittleEndian>()?;
-    let offset = reader.read_u32::<LittleEndian>()? as u64;
-    let file_name_raw = ReadPodExt::read_exact(reader, file_name_length)?;
-    let extra_field = ReadPodExt::read_exact(reader, extra_field_length)?;
-    let file_comment_raw = ReadPodExt::read_exact(reader, file_comment_length)?;
+    /// Get the length of the reader, seeking to its end unless it is known already
+    fn reader_len(&mut self) -> ZipResult<u64> {
+        match self.reader_len {
+            Some(len) => Ok(len),
+            None => Ok(self.reader.seek(io::SeekFrom::End(0))?),
+        }
+    }
 
-    let file_name = match is_utf8 {
-        true => String::from_utf8_lossy(&*file_name_raw).into_owned(),
-        false => file_name_raw.clone().from_cp437(),
-    };
-    let file_comment = match is_utf8 {
-        true => String::from_utf8_lossy(&*file_comment_raw).into_owned(),
-        false => file_comment_raw.from_cp437(),
-    };
+    /// Read the bytes between the end of a file and the header that follows it
+    ///
+    /// The end of a file is the end of its compressed data, or of its data descriptor if it has
+    /// one. The next header is the local header of the following file in the archive, or the
+    /// central directory for the last one. Well-formed archives have no bytes in between, so
+    /// anything returned here is unexpected and may be hidden or tampered data.
+    pub fn slack_after(&mut self, file_number: usize) -> ZipResult<Vec<u8>> {
+        if file_number >= self.files.len() {
+            return Err(ZipError::FileNotFound);
+        }
+        let header_start = self.files[file_number].header_start;
+        let next_header = self
+            .files
+            .iter()
+            .map(|file| file.header_start)
+            .filter(|&start| start > header_start)
+            .min()
+            .unwrap_or(self.directory_start)
+            .min(self.directory_start);
 
-    // Construct the result
-    let mut result = ZipFileData {
-        system: System::from_u8((version_made_by >> 8) as u8),
-        version_made_by: version_made_by as u8,
-        encrypted: encrypted,
-        compression_method: CompressionMethod::from_u16(compression_method),
-        last_modified_time: DateTime::from_msdos(last_mod_date, last_mod_time),
-        crc32: crc32,
-        compressed_size: compressed_size as u64,
-        uncompressed_size: uncompressed_size as u64,
-        file_name: file_name,
-        file_name_raw: file_name_raw,
-        file_comment: file_comment,
-        header_start: offset,
-        data_start: 0,
-        external_attributes: external_file_attributes,
-    };
+        let end = self.entry_extent(file_number)?.end;
+        if end > next_header {
+            return Err(ZipError::InvalidArchive(
+                "File data overlaps the next header",
+            ));
+        }
 
-    match parse_extra_field(&mut result, &*extra_field) {
-        Ok(..) | Err(ZipError::Io(..)) => {}
-        Err(e) => Err(e)?,
+        let mut slack = Vec::new();
+        (&mut self.reader)
+            .take(next_header - end)
+            .read_to_end(&mut slack)?;
+        Ok(slack)
     }
 
-    // Account for shifted zip offsets.
-    result.header_start += archive_offset;
-
-    Ok(result)
-}
+    /// Resolve the data location of every file in the archive
+    ///
+    /// This parses all local headers once, and returns the locations in the order of the central
+    /// directory.
+    pub fn index(&mut self) -> ZipResult<Vec<EntryLocation>> {
+        self.decode_names();
+        let mut locations = Vec::with_capacity(self.files.len());
+        for data in self.files.iter() {
+            let data = read_local_header(&mut self.reader, data)?;
+            locations.push(EntryLocation {
+                name: data.file_name.clone(),
+                data_start: data.data_start,
+                compressed_size: data.compressed_size,
+                compression_method: data.compression_method,
+                crc32: data.crc32,
+            });
+        }
+        Ok(locations)
+    }
 
-fn parse_extra_field(file: &mut ZipFileData, data: &[u8]) -> ZipResult<()> {
-    let mut reader = io::Cursor::new(data);
+    /// Open a file at a location previously returned by `index`
+    ///
+    /// The local header of the file is not parsed, its data is read from the stored location
+    /// directly. The location must describe a file of this archive.
+    pub fn open_from_location<'a>(
+        &'a mut self,
+        location: &EntryLocation,
+    ) -> ZipResult<ZipFile<'a>> {
+        self.decode_names();
+        let file_number = match self.names_map.get(&location.name) {
+            Some(&index) => index,
+            None => return Err(ZipError::FileNotFound),
+        };
+        let data = &self.files[file_number];
 
-    while (reader.position() as usize) < data.len() {
-        let kind = reader.read_u16::<LittleEndian>()?;
-        let len = reader.read_u16::<LittleEndian>()?;
-        let mut len_left = len as i64;
-        match kind {
-            // Zip64 extended information extra field
-            0x0001 => {
-                if file.uncompressed_size == 0xFFFFFFFF {
-                    file.uncompressed_size = reader.read_u64::<LittleEndian>()?;
-                    len_left -= 8;
-                }
-                if file.compressed_size == 0xFFFFFFFF {
-                    file.compressed_size = reader.read_u64::<LittleEndian>()?;
-                    len_left -= 8;
-                }
-                if file.header_start == 0xFFFFFFFF {
-                    file.header_start = reader.read_u64::<LittleEndian>()?;
-                    len_left -= 8;
-                }
-                // Unparsed fields:
-                // u32: disk start number
-            }
-            _ => {}
+        if data.compressed_size != location.compressed_size
+            || data.compression_method != location.compression_method
+            || data.crc32 != location.crc32
+        {
+            return Err(ZipError::InvalidArchive(
+                "Entry location does not match the archive",
+            ));
         }
+        check_decryption(data, &self.password)?;
 
-        // We could also check for < 0 to check for errors
-        if len_left > 0 {
-            reader.seek(io::SeekFrom::Current(len_left))?;
-        }
+        let mut data = data.clone();
+        data.data_start = location.data_start;
+        self.open_data(data, false, true)
     }
-    Ok(())
-}
 
-fn get_reader<'a>(reader: &'a mut ZipFileReader<'_>) -> &'a mut dyn Read {
-    match *reader {
-        ZipFileReader::NoReader => panic!("ZipFileReader was in an invalid state"),
-        ZipFileReader::Stored(ref mut r) => r as &mut dyn Read,
-        #[cfg(feature = "deflate")]
-        ZipFileReader::Deflated(ref mut r) => r as &mut dyn Read,
-        #[cfg(feature = "bzip2")]
-        ZipFileReader::Bzip2(ref mut r) => r as &mut dyn Read,
+    /// Read the contents of a file entry into a new buffer
+    ///
+    /// See `ZipFile::copy_to` for the checks that are performed in strict mode.
+    pub fn read_file(&mut self, name: &str) -> ZipResult<Vec<u8>> {
+        read_to_vec(self.by_name(name)?)
     }
-}
 
-/// Methods for retrieving information on zip files
-impl<'a> ZipFile<'a> {
-    fn get_reader(&mut self) -> &mut dyn Read {
-        get_reader(&mut self.reader)
+    /// Read the contents of a file entry, checking that it has the expected CRC32 hash
+    ///
+    /// Fails with `ZipError::InvalidArchive` if the CRC32 of the file in the archive, or that of
+    /// its actual contents, differs from `expected_crc32`. This catches both a file that was
+    /// replaced in the archive and one whose data was corrupted.
+    pub fn read_file_verified(&mut self, name: &str, expected_crc32: u32) -> ZipResult<Vec<u8>> {
+        let file = self.by_name(name)?;
+        if file.crc32() != expected_crc32 {
+            return Err(ZipError::InvalidArchive("CRC mismatch"));
+        }
+        // The contents are checked against the CRC32 of the file while reading
+        read_to_vec(file).map_err(|e| match e {
+            ZipError::Io(ref err) if crate::crc32::is_checksum_error(err) => {
+                ZipError::InvalidArchive("CRC mismatch")
+            }
+            e => e,
+        })
     }
-    /// Get the version of the file
-    pub fn version_made_by(&self) -> (u8, u8) {
-        (
-            self.data.version_made_by / 10,
-            self.data.version_made_by % 10,
-        )
+
+    /// Read the contents of a file entry, checking that it has the expected SHA-256 hash
+    ///
+    /// Fails with `ZipError::InvalidArchive` if the hash of the contents differs from
+    /// `expected_sha256`. The contents are also checked against the CRC32 of the file, as with
+    /// `read_file`.
+    #[cfg(feature = "sha2")]
+    pub fn read_file_verified_sha256(
+        &mut self,
+        name: &str,
+        expected_sha256: &[u8; 32],
+    ) -> ZipResult<Vec<u8>> {
+        use sha2::{Digest, Sha256};
+
+        let buffer = self.read_file(name)?;
+        if Sha256::digest(&buffer)[..] != expected_sha256[..] {
+            return Err(ZipError::InvalidArchive("SHA-256 mismatch"));
+        }
+        Ok(buffer)
     }
-    /// Get the name of the file
-    pub fn name(&self) -> &str {
-        &*self.data.file_name
+
+    /// Read up to `max_bytes` from the start of a file entry, if the archive contains it
+    ///
+    /// This is meant for detecting the format of an archive from marker entries, such as
+    /// `mimetype` in EPUB files or `META-INF/MANIFEST.MF` in JAR files. Returns `None` if there
+    /// is no file with the given name. The CRC32 of the file is only checked if it is read in
+    /// full.
+    pub fn sniff(&mut self, name: &str, max_bytes: u64) -> ZipResult<Option<Vec<u8>>> {
+        let file = match self.by_name(name) {
+            Ok(file) => file,
+            Err(ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut buffer = Vec::new();
+        file.take(max_bytes).read_to_end(&mut buffer)?;
+        Ok(Some(buffer))
     }
-    /// Get the name of the file, in the raw (internal) byte representation.
-    pub fn name_raw(&self) -> &[u8] {
-        &*self.data.file_name_raw
+
+    /// Read up to `len` bytes of the contents of a file entry, starting at offset `start`
+    ///
+    /// Fewer bytes are returned if the file ends before `start + len`. For files that are
+    /// stored without compression or encryption, the reader seeks straight to `start`, so this
+    /// costs about as much as reading `len` bytes. Compressed files can only be decompressed
+    /// from the beginning, so the `start` bytes before the range are decompressed and discarded,
+    /// and the cost grows with the offset. The CRC32 of the file is not checked, as the contents
+    /// are not read in full.
+    pub fn read_range(&mut self, file_number: usize, start: u64, len: u64) -> ZipResult<Vec<u8>> {
+        let data = self.files.get(file_number).ok_or(ZipError::FileNotFound)?;
+        let mut buffer = Vec::new();
+        if data.compression_method == CompressionMethod::Stored && !data.encrypted {
+            let data = read_local_header(&mut self.reader, data)?;
+            // Data that does not fit in the archive would otherwise read like a truncated file
+            let data_end = data.data_start.checked_add(data.compressed_size);
+            if !matches!(data_end, Some(end) if end <= self.reader_len()?) {
+                return Err(ZipError::InvalidArchive("Entry data out of bounds"));
+            }
+            let start = start.min(data.compressed_size);
+            let len = len.min(data.compressed_size - start);
+            self.reader
+                .seek(io::SeekFrom::Start(data.data_start + start))?;
+            (&mut self.reader).take(len).read_to_end(&mut buffer)?;
+        } else {
+            let mut file = self.by_index(file_number)?;
+            io::copy(&mut (&mut file).take(start), &mut io::sink())?;
+            file.take(len).read_to_end(&mut buffer)?;
+        }
+        Ok(buffer)
     }
-    /// Get the name of the file in a sanitized form. It truncates the name to the first NULL byte,
-    /// removes a leading '/' and removes '..' parts.
-    pub fn sanitized_name(&self) -> ::std::path::PathBuf {
-        self.data.file_name_sanitized()
+
+    /// Read the contents of a text file entry into a new string
+    ///
+    /// Fails with `ZipError::InvalidArchive` if the contents are not valid UTF-8.
+    pub fn read_file_to_string(&mut self, name: &str) -> ZipResult<String> {
+        let buffer = self.read_file(name)?;
+        String::from_utf8(buffer)
+            .map_err(|_| ZipError::InvalidArchive("File contents are not valid UTF-8"))
     }
-    /// Get the comment of the file
-    pub fn comment(&self) -> &str {
-        &*self.data.file_comment
+
+    /// Read the contents of every file into a map from name to contents
+    ///
+    /// Directories are skipped. The contents are fully decompressed and their checksum is
+    /// validated, and the first file that fails to be read is returned as an error.
+    pub fn read_all(&mut self) -> ZipResult<HashMap<String, Vec<u8>>> {
+        self.decode_names();
+        let mut contents = HashMap::with_capacity(self.files.len());
+        for file_number in 0..self.files.len() {
+            let name = self.files[file_number].file_name.clone();
+            if name.ends_with(&['/', '\\'][..]) {
+                continue;
+            }
+            contents.insert(name, read_to_vec(self.by_index(file_number)?)?);
+        }
+        Ok(contents)
     }
-    /// Get the compression method used to store the file
-    pub fn compression(&self) -> CompressionMethod {
-        self.data.compression_method
+
+    /// Consume the archive, and iterate over the name and contents of each file
+    ///
+    /// Files are yielded in the order of the central directory, and directories are skipped. The
+    /// contents are fully decompressed and their checksum is validated. A file that fails to be
+    /// read is yielded as an error, after which the iteration continues with the next file.
+    pub fn into_entries(mut self) -> impl Iterator<Item = ZipResult<(String, Vec<u8>)>> {
+        self.decode_names();
+        let mut file_number = 0;
+        ::std::iter::from_fn(move || {
+            while file_number < self.files.len() {
+                let index = file_number;
+                file_number += 1;
+                if self.files[index].file_name.ends_with(&['/', '\\'][..]) {
+                    continue;
+                }
+                let contents = self.by_index(index).and_then(read_to_vec);
+                let name = self.files[index].file_name.clone();
+                return Some(contents.map(|contents| (name, contents)));
+            }
+            None
+        })
     }
-    /// Get the size of the file in the archive
-    pub fn compressed_size(&self) -> u64 {
-        self.data.compressed_size
+
+    /// Call a closure with the index of every file and the file itself, in the order of the
+    /// central directory
+    ///
+    /// This is a shorter form of calling `by_index` for every index, as the returned files borrow
+    /// the archive and can not be yielded by an `Iterator`. Stops at the first file that fails to
+    /// be opened, or for which the closure returns an error, and returns that error.
+    pub fn for_each_file<F>(&mut self, mut f: F) -> ZipResult<()>
+    where
+        F: FnMut(usize, ZipFile) -> ZipResult<()>,
+    {
+        for file_number in 0..self.files.len() {
+            f(file_number, self.by_index(file_number)?)?;
+        }
+        Ok(())
     }
-    /// Get the size of the file when uncompressed
-    pub fn size(&self) -> u64 {
-        self.data.uncompressed_size
+
+    /// Extract the archive into a directory
+    ///
+    /// Paths are resolved with `ZipFile::enclosed_name`, so files can not be written outside of
+    /// `directory`. Extraction stops at the first entry that fails; see `extract_lossy` for a
+    /// variant that continues past failing entries.
+    pub fn extract<P: AsRef<Path>>(&mut self, directory: P) -> ZipResult<()> {
+        for i in 0..self.len() {
+            self.extract_file(i, directory.as_ref())?;
+        }
+        Ok(())
     }
-    /// Get the time the file was last modified
-    pub fn last_modified(&self) -> DateTime {
-        self.data.last_modified_time
+
+    /// Extract the archive into a directory, continuing past entries that fail
+    ///
+    /// Every entry is attempted, and the index and error of each entry that could not be
+    /// extracted (e.g. because of a checksum mismatch, an unsupported compression method or an
+    /// unsafe path) are returned. Files that fail part way through may be left partially written.
+    pub fn extract_lossy<P: AsRef<Path>>(
+        &mut self,
+        directory: P,
+    ) -> ZipResult<Vec<(usize, ZipError)>> {
+        fs::create_dir_all(directory.as_ref())?;
+        let mut failures = Vec::new();
+        for i in 0..self.len() {
+            if let Err(e) = self.extract_file(i, directory.as_ref()) {
+                failures.push((i, e));
+            }
+        }
+        Ok(failures)
     }
-    /// Returns whether the file is actually a directory
-    pub fn is_dir(&self) -> bool {
-        self.name()
-            .chars()
-            .rev()
-            .next()
-            .map_or(false, |c| c == '/' || c == '\\')
+
+    /// Extract the archive into a directory, choosing the path of each file with a callback
+    ///
+    /// The callback returns the path to extract a file to, relative to `directory`, or `None` to
+    /// skip the file. This allows e.g. stripping a leading folder or flattening the archive. The
+    /// returned paths must stay within `directory`, otherwise extraction fails with an error.
+    /// Extraction stops at the first entry that fails.
+    pub fn extract_with<P, F>(&mut self, directory: P, mut f: F) -> ZipResult<()>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&ZipFile) -> Option<PathBuf>,
+    {
+        for i in 0..self.len() {
+            let mut file = self.by_index(i)?;
+            let path = match f(&file) {
+                Some(path) => path,
+                None => continue,
+            };
+            if !is_enclosed(&path) {
+                return Err(ZipError::InvalidArchive("Invalid file path"));
+            }
+            extract_to(&mut file, &directory.as_ref().join(path))?;
+        }
+        Ok(())
     }
-    /// Returns whether the file is a regular file
-    pub fn is_file(&self) -> bool {
-        !self.is_dir()
+
+    fn extract_file(&mut self, file_number: usize, directory: &Path) -> ZipResult<()> {
+        let mut file = self.by_index(file_number)?;
+        let outpath = match file.enclosed_name() {
+            Some(path) => directory.join(path),
+            None => return Err(ZipError::InvalidArchive("Invalid file path")),
+        };
+        extract_to(&mut file, &outpath)
     }
-    /// Get unix mode for the file
-    pub fn unix_mode(&self) -> Option<u32> {
-        if self.data.external_attributes == 0 {
-            return None;
+
+    /// Copy the files whose name matches `predicate` into a new archive
+    ///
+    /// The compressed data is copied verbatim, so no file is decompressed or recompressed. The
+    /// new archive is returned as bytes.
+    pub fn subset<F: Fn(&str) -> bool>(&mut self, predicate: F) -> ZipResult<Vec<u8>> {
+        self.decode_names();
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for i in 0..self.len() {
+            if predicate(&self.files[i].file_name) {
+                writer.raw_copy_file(self, i)?;
+            }
         }
+        Ok(writer.finish()?.into_inner())
+    }
 
-        match self.data.system {
-            System::Unix => Some(self.data.external_attributes >> 16),
-            System::Dos => {
-                // Interpret MSDOS directory bit
-                let mut mode = if 0x10 == (self.data.external_attributes & 0x10) {
-                    ffi::S_IFDIR | 0o0775
-                } else {
-                    ffi::S_IFREG | 0o0664
-                };
-                if 0x01 == (self.data.external_attributes & 0x01) {
-                    // Read-only bit; strip write permissions
-                    mode &= 0o0555;
+    /// Copy the files whose name is accepted by `keep` into `writer`
+    ///
+    /// The files are copied in the order of their data in this archive, with `raw_copy_file`, so
+    /// they are not recompressed and keep their timestamps, comments and attributes.
+    pub fn repack_into<W, F>(&mut self, writer: &mut ZipWriter<W>, keep: F) -> ZipResult<()>
+    where
+        W: Write + io::Seek,
+        F: Fn(&str) -> bool,
+    {
+        self.decode_names();
+        for i in self.indices_by_offset() {
+            if keep(&self.files[i].file_name) {
+                writer.raw_copy_file(self, i)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compare the entries of this archive with those of another archive
+    ///
+    /// Entries are matched by name, and are compared by their size, CRC32, compression method and
+    /// modification time. Only the parsed central directories are used, no file data is read.
+    /// Removed and changed entries are reported in the order of this archive, followed by the
+    /// added entries in the order of the other archive.
+    pub fn diff<S: Read + io::Seek>(&self, other: &ZipArchive<S>) -> Vec<EntryDiff> {
+        let (files, names_map) = self.decoded_directory();
+        let (other_files, other_names_map) = other.decoded_directory();
+        let mut diffs = Vec::new();
+        for file in files.iter() {
+            match other_names_map.get(&file.file_name) {
+                None => diffs.push(EntryDiff::Removed(file.file_name.clone())),
+                Some(&index) => {
+                    let other_file = &other_files[index];
+                    if file.uncompressed_size != other_file.uncompressed_size
+                        || file.crc32 != other_file.crc32
+                        || file.compression_method != other_file.compression_method
+                        || file.last_modified_time.datepart()
+                            != other_file.last_modified_time.datepart()
+                        || file.last_modified_time.timepart()
+                            != other_file.last_modified_time.timepart()
+                    {
+                        diffs.push(EntryDiff::Changed(file.file_name.clone()));
+                    }
                 }
-                Some(mode)
             }
-            _ => None,
         }
+        for file in other_files.iter() {
+            if !names_map.contains_key(&file.file_name) {
+                diffs.push(EntryDiff::Added(file.file_name.clone()));
+            }
+        }
+        diffs
     }
-    /// Get the CRC32 hash of the original file
-    pub fn crc32(&self) -> u32 {
-        self.data.crc32
+
+    /// Decode the names that were deferred with `ZipArchiveOptions::lazy_names`
+    fn decode_names(&mut self) {
+        decode_deferred_names(&mut self.files, &mut self.names_map, &self.options);
     }
 
-    /// Get the starting offset of the data of the compressed file
-    pub fn data_start(&self) -> u64 {
-        self.data.data_start
+    /// The files and the map from names to files, with any deferred names decoded
+    pub(crate) fn decoded_directory(
+        &self,
+    ) -> (Cow<'_, [ZipFileData]>, Cow<'_, HashMap<String, usize>>) {
+        if !self.names_map.is_empty() || self.files.is_empty() {
+            return (Cow::Borrowed(&self.files), Cow::Borrowed(&self.names_map));
+        }
+        let mut files = self.files.clone();
+        let mut names_map = HashMap::new();
+        decode_deferred_names(&mut files, &mut names_map, &self.options);
+        (Cow::Owned(files), Cow::Owned(names_map))
+    }
+
+    /// Unwrap and return the inner reader object
+    ///
+    /// The position of the reader is undefined.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Split the archive into the inner reader and the parsed central directory
+    ///
+    /// The position of the reader is undefined.
+    pub fn into_parts(mut self) -> (R, ParsedDirectory) {
+        self.decode_names();
+        let directory = ParsedDirectory {
+            files: self.files,
+            names_map: self.names_map,
+            offset: self.offset,
+            directory_start: self.directory_start,
+            comment: self.comment,
+            comment_start: self.comment_start,
+            options: self.options,
+            warnings: self.warnings,
+        };
+        (self.reader, directory)
+    }
+
+    /// Create an archive from a reader and a central directory that was parsed before
+    ///
+    /// Nothing is read from the reader, so it must hold the same archive as the one the directory
+    /// was taken from. This is not checked; a different archive results in errors or wrong data
+    /// when files are read.
+    pub fn from_parts(reader: R, directory: ParsedDirectory) -> ZipArchive<R> {
+        ZipArchive {
+            reader,
+            files: directory.files,
+            names_map: directory.names_map,
+            offset: directory.offset,
+            directory_start: directory.directory_start,
+            comment: directory.comment,
+            comment_start: directory.comment_start,
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            options: directory.options,
+            warnings: directory.warnings,
+            entry_warnings: HashMap::new(),
+            reader_len: None,
+            password: None,
+        }
+    }
+}
+
+impl ZipArchive<fs::File> {
+    /// Open a ZIP archive from a file and parse its central directory
+    ///
+    /// The length of the file is taken from its metadata, so the bounds of the data of each file
+    /// are checked against it without seeking to the end of the file first.
+    pub fn new_file(file: fs::File) -> ZipResult<ZipArchive<fs::File>> {
+        let len = file.metadata()?.len();
+        let mut archive = Self::new(file)?;
+        archive.reader_len = Some(len);
+        Ok(archive)
+    }
+}
+
+impl ZipArchive<MultipartReader> {
+    /// Read a ZIP archive that is split over multiple parts, such as `archive.z01`, `archive.z02`
+    /// and `archive.zip`
+    ///
+    /// The parts are given in the order of their disk numbers, so the part with the central
+    /// directory end comes last, along with their sizes. They are read in place: every access is
+    /// dispatched to the part that holds it, and offsets that are relative to a disk are
+    /// translated to that part. This allows each part to be a separate object, e.g. in a remote
+    /// store, without joining them first.
+    pub fn new_multipart(
+        parts: Vec<Box<dyn ReadSeek>>,
+        sizes: Vec<u64>,
+    ) -> ZipResult<ZipArchive<MultipartReader>> {
+        let mut reader = MultipartReader::new(parts, sizes)?;
+        let (footer, cde_start_pos) = spec::CentralDirectoryEnd::find_and_parse(&mut reader)?;
+        if footer.disk_number as usize + 1 != reader.parts() {
+            return Err(ZipError::InvalidArchive(
+                "Number of parts does not match the archive",
+            ));
+        }
+
+        let zip64 = footer.has_zip64_markers();
+        let (disk_with_central_directory, directory_offset, number_of_files) = if zip64 {
+            let locator_start = cde_start_pos
+                .checked_sub(20)
+                .ok_or(ZipError::InvalidArchive(
+                    "File cannot contain ZIP64 central directory end",
+                ))?;
+            reader.seek(io::SeekFrom::Start(locator_start))?;
+            let locator = spec::Zip64CentralDirectoryEndLocator::parse(&mut reader)?;
+            let footer64_start = part_start(&reader, locator.disk_with_central_directory)?
+                + locator.end_of_central_directory_offset;
+            reader.seek(io::SeekFrom::Start(footer64_start))?;
+            let footer64 = spec::Zip64CentralDirectoryEnd::parse(&mut reader)?;
+            (
+                footer64.disk_with_central_directory,
+                footer64.central_directory_offset,
+                footer64.number_of_files as usize,
+            )
+        } else {
+            (
+                footer.disk_with_central_directory as u32,
+                footer.central_directory_offset as u64,
+                footer.number_of_files as usize,
+            )
+        };
+        let directory_start = part_start(&reader, disk_with_central_directory)? + directory_offset;
+        reader.seek(io::SeekFrom::Start(directory_start))?;
+
+        let options = ZipArchiveOptions::default();
+        let mut files = Vec::new();
+        let mut names_map = HashMap::new();
+        for _ in 0..number_of_files {
+            let (mut file, disk_number) =
+                central_header_to_zip_file_and_disk(&mut reader, 0, &options)?;
+            file.header_start += part_start(&reader, disk_number as u32)?;
+            names_map.insert(file.file_name.clone(), files.len());
+            files.push(file);
+        }
+
+        Ok(ZipArchive {
+            reader,
+            files,
+            names_map,
+            offset: 0,
+            directory_start,
+            comment: footer.zip_file_comment,
+            comment_start: cde_start_pos + spec::CENTRAL_DIRECTORY_END_SIZE,
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            options,
+            warnings: Vec::new(),
+            entry_warnings: HashMap::new(),
+            reader_len: None,
+            password: None,
+        })
+    }
+}
+
+/// Get the position of the part with a disk number in a split archive
+fn part_start(reader: &MultipartReader, disk_number: u32) -> ZipResult<u64> {
+    reader
+        .part_start(disk_number as usize)
+        .ok_or(ZipError::InvalidArchive(
+            "Disk number refers to a missing part",
+        ))
+}
+
+impl<T: AsRef<[u8]>> ZipArchive<io::Cursor<T>> {
+    /// Read a ZIP archive from an in-memory buffer, such as a memory map
+    ///
+    /// The buffer is owned by the archive, so e.g. a `memmap2::Mmap` can be passed in directly
+    /// without having to keep it alive separately. Stored entries can then be accessed without
+    /// copying through `entry_bytes`.
+    pub fn from_mmap(mmap: T) -> ZipResult<ZipArchive<io::Cursor<T>>> {
+        ZipArchive::new(io::Cursor::new(mmap))
+    }
+
+    /// Borrow the contents of a stored entry directly from the underlying buffer
+    ///
+    /// Only entries that are stored without compression or encryption can be accessed this way.
+    /// The CRC32 checksum of the returned data is not validated.
+    pub fn entry_bytes(&self, file_number: usize) -> ZipResult<&[u8]> {
+        let data = self.files.get(file_number).ok_or(ZipError::FileNotFound)?;
+
+        if data.encrypted {
+            return unsupported_zip_error("Encrypted files are not supported");
+        }
+        if data.compression_method != CompressionMethod::Stored {
+            return unsupported_zip_error("Only stored entries can be borrowed");
+        }
+
+        let bytes = self.reader.get_ref().as_ref();
+        let data_start = find_data_start(&mut io::Cursor::new(bytes), data)?;
+        entry_data(bytes, data_start, data.compressed_size)
+    }
+
+    /// Borrow the contents of a file directly from the underlying buffer, if possible
+    ///
+    /// Returns `None` when the file can not be borrowed, e.g. because it is compressed, in which
+    /// case it should be read through `by_index` instead. See `entry_bytes` for details.
+    pub fn try_entry_slice(&self, file_number: usize) -> Option<&[u8]> {
+        self.entry_bytes(file_number).ok()
+    }
+}
+
+impl<T: AsRef<[u8]> + Clone> ZipArchive<io::Cursor<T>> {
+    /// Create a view of this in-memory archive that can have multiple files open at once
+    ///
+    /// The buffer is cloned into the view, so this is cheap for buffers with shared ownership,
+    /// such as `Arc<[u8]>`. The local headers of all files are parsed up front.
+    pub fn shared(&self) -> ZipResult<SharedZipArchive<T>> {
+        let data = self.reader.get_ref().clone();
+        let (files, names_map) = self.decoded_directory();
+        let mut files = files.into_owned();
+        {
+            let mut reader = io::Cursor::new(data.as_ref());
+            for file in files.iter_mut() {
+                *file = read_local_header(&mut reader, file)?;
+            }
+        }
+
+        Ok(SharedZipArchive {
+            data,
+            files,
+            names_map: names_map.into_owned(),
+            options: self.options,
+        })
+    }
+}
+
+impl<T: AsRef<[u8]>> SharedZipArchive<T> {
+    /// Number of files contained in this zip.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Whether this zip contains no files
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Search for a file entry by name
+    pub fn by_name(&self, name: &str) -> ZipResult<ZipFile<'_>> {
+        match self.names_map.get(name) {
+            Some(index) => self.by_index(*index),
+            None => Err(ZipError::FileNotFound),
+        }
+    }
+
+    /// Get a contained file by index
+    ///
+    /// Unlike `ZipArchive::by_index`, any number of files may be open at the same time.
+    pub fn by_index(&self, file_number: usize) -> ZipResult<ZipFile<'_>> {
+        let data = self.files.get(file_number).ok_or(ZipError::FileNotFound)?;
+
+        if data.encrypted {
+            return unsupported_zip_error("Encrypted files are not supported");
+        }
+
+        let contents = entry_data(self.data.as_ref(), data.data_start, data.compressed_size)?;
+
+        Ok(ZipFile {
+            reader: make_reader(
+                data.compression_method,
+                Some(data.crc32),
+                data.uncompressed_size,
+                self.options.lenient_deflate,
+                Box::new(contents),
+            )?,
+            data: Cow::Borrowed(data),
+            strict: self.options.strict,
+            peeked: Vec::new(),
+            output_limit: None,
+            bytes_read: 0,
+            streamed: false,
+            descriptor_crc32: None,
+        })
+    }
+}
+
+/// Decode the name of a file, as flagged in its header
+fn decode_name(file_name_raw: &[u8], is_utf8: bool, options: &ZipArchiveOptions) -> String {
+    match is_utf8 {
+        true => String::from_utf8_lossy(file_name_raw).into_owned(),
+        false => decode_file_name(file_name_raw, options),
+    }
+}
+
+/// Decode the name of a file if that was deferred with `ZipArchiveOptions::lazy_names`
+fn decode_deferred_name(file: &mut ZipFileData, options: &ZipArchiveOptions) {
+    if file.file_name.is_empty() && !file.file_name_raw.is_empty() {
+        file.file_name = decode_name(&file.file_name_raw, file.is_utf8, options);
+    }
+}
+
+/// Decode all deferred names, and build the map from names to files that is skipped with
+/// `ZipArchiveOptions::lazy_names`
+fn decode_deferred_names(
+    files: &mut [ZipFileData],
+    names_map: &mut HashMap<String, usize>,
+    options: &ZipArchiveOptions,
+) {
+    if !names_map.is_empty() {
+        return;
+    }
+    for (index, file) in files.iter_mut().enumerate() {
+        decode_deferred_name(file, options);
+        names_map.insert(file.file_name.clone(), index);
+    }
+}
+
+/// The version of the specification that introduced the features a file uses
+///
+/// This depends on the compression method, the encryption and ZIP64 extra field of the file, and
+/// not on whether this crate was built with support for them. Version 2.0 is the lowest, as most
+/// tools declare it for every file.
+fn version_of_features(data: &ZipFileData) -> u16 {
+    let method_version = match data.compression_method.to_u16() {
+        // Deflate64
+        9 => 21,
+        // PKWARE DCL Implode
+        10 => 25,
+        12 => 46,
+        // LZMA, IBM TERSE, Zstandard, MP3, XZ, JPEG, WavPack and PPMd
+        14 | 18 | 19 | 93..=98 => 63,
+        // AE-x encryption marker
+        99 => 51,
+        _ => 20,
+    };
+    let format_version = if data.aes_mode.is_some() {
+        51
+    } else if data.large_file {
+        45
+    } else {
+        20
+    };
+    method_version.max(format_version)
+}
+
+/// Decode a file name that is not flagged as UTF-8
+#[cfg_attr(not(feature = "encoding-detection"), allow(unused_variables))]
+fn decode_file_name(file_name_raw: &[u8], options: &ZipArchiveOptions) -> String {
+    #[cfg(feature = "encoding-detection")]
+    {
+        if options.auto_detect_encoding && !file_name_raw.is_ascii() {
+            let mut detector = chardetng::EncodingDetector::new();
+            detector.feed(file_name_raw, true);
+            let (encoding, confident) = detector.guess_assess(None, true);
+            if confident {
+                let decoded =
+                    encoding.decode_without_bom_handling_and_without_replacement(file_name_raw);
+                if let Some(file_name) = decoded {
+                    return file_name.into_owned();
+                }
+            }
+        }
+    }
+    file_name_raw.from_cp437().into_owned()
+}
+
+/// Parse the local header of a file, and return a copy of its central directory information
+/// with the location of its data and the contents of its local extra field merged in
+///
+/// The local extra field can hold more than the central one, such as the access and creation
+/// time of the extended timestamp field. The central directory information itself is left
+/// untouched, so it does not depend on which files were opened before.
+fn read_local_header<R: Read + io::Seek>(
+    reader: &mut R,
+    central: &ZipFileData,
+) -> ZipResult<ZipFileData> {
+    let mut data = central.clone();
+    reader.seek(io::SeekFrom::Start(data.header_start))?;
+    let signature = reader.read_u32::<LittleEndian>()?;
+    if signature != spec::LOCAL_FILE_HEADER_SIGNATURE {
+        return Err(ZipError::InvalidArchive("Invalid local file header"));
+    }
+
+    reader.seek(io::SeekFrom::Current(22))?;
+    let file_name_length = reader.read_u16::<LittleEndian>()? as u64;
+    let extra_field_length = reader.read_u16::<LittleEndian>()? as usize;
+    reader.seek(io::SeekFrom::Current(file_name_length as i64))?;
+    let extra_field = ReadPodExt::read_exact(reader, extra_field_length)?;
+
+    let magic_and_header = 4 + 22 + 2 + 2;
+    data.data_start =
+        data.header_start + magic_and_header + file_name_length + extra_field_length as u64;
+
+    // Unlike in the central directory, the ZIP64 field of a local header always holds both
+    // sizes. Some writers only store the full sizes here, and leave the marker or a truncated
+    // value in the central directory.
+    if let Some((uncompressed_size, compressed_size)) = local_zip64_sizes(&extra_field) {
+        if data.uncompressed_size == spec::ZIP64_BYTES_THR
+            || uncompressed_size > spec::ZIP64_BYTES_THR
+        {
+            data.uncompressed_size = uncompressed_size;
+        }
+        if data.compressed_size == spec::ZIP64_BYTES_THR || compressed_size > spec::ZIP64_BYTES_THR
+        {
+            data.compressed_size = compressed_size;
+        }
+    }
+
+    match parse_extra_field(&mut data, &extra_field) {
+        Ok(..) | Err(ZipError::Io(..)) => Ok(data),
+        Err(e) => Err(e),
+    }
+}
+
+/// Read the remaining contents of a file into a new buffer
+fn read_to_vec(mut file: ZipFile<'_>) -> ZipResult<Vec<u8>> {
+    // The size is only a hint, so don't trust it for huge allocations
+    let capacity = ::std::cmp::min(file.size(), MAX_PREALLOCATION) as usize;
+    let mut buffer = Vec::with_capacity(capacity);
+    file.copy_to(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Find the uncompressed and compressed size in the ZIP64 field of a local extra field
+fn local_zip64_sizes(extra_field: &[u8]) -> Option<(u64, u64)> {
+    let mut reader = extra_field;
+    while reader.len() >= 4 {
+        let kind = reader.read_u16::<LittleEndian>().ok()?;
+        let len = reader.read_u16::<LittleEndian>().ok()? as usize;
+        if len > reader.len() {
+            return None;
+        }
+        let (mut field, rest) = reader.split_at(len);
+        if kind == 0x0001 && len >= 16 {
+            let uncompressed_size = field.read_u64::<LittleEndian>().ok()?;
+            let compressed_size = field.read_u64::<LittleEndian>().ok()?;
+            return Some((uncompressed_size, compressed_size));
+        }
+        reader = rest;
+    }
+    None
+}
+
+/// Slice the compressed data of a file out of an in-memory archive
+fn entry_data(bytes: &[u8], data_start: u64, compressed_size: u64) -> ZipResult<&[u8]> {
+    (data_start as usize)
+        .checked_add(compressed_size as usize)
+        .and_then(|end| bytes.get(data_start as usize..end))
+        .ok_or(ZipError::InvalidArchive("Entry data out of bounds"))
+}
+
+/// Read the CRC32 checksum from the local header of a file
+fn read_local_crc32<R: Read + io::Seek>(reader: &mut R, data: &ZipFileData) -> ZipResult<u32> {
+    reader.seek(io::SeekFrom::Start(data.header_start))?;
+    if reader.read_u32::<LittleEndian>()? != spec::LOCAL_FILE_HEADER_SIGNATURE {
+        return Err(ZipError::InvalidArchive("Invalid local file header"));
+    }
+    reader.seek(io::SeekFrom::Current(10))?;
+    Ok(reader.read_u32::<LittleEndian>()?)
+}
+
+/// Parse the local header of a file to find where its data starts
+fn find_data_start<R: Read + io::Seek>(reader: &mut R, data: &ZipFileData) -> ZipResult<u64> {
+    reader.seek(io::SeekFrom::Start(data.header_start))?;
+    let signature = reader.read_u32::<LittleEndian>()?;
+    if signature != spec::LOCAL_FILE_HEADER_SIGNATURE {
+        return Err(ZipError::InvalidArchive("Invalid local file header"));
+    }
+
+    reader.seek(io::SeekFrom::Current(22))?;
+    let file_name_length = reader.read_u16::<LittleEndian>()? as u64;
+    let extra_field_length = reader.read_u16::<LittleEndian>()? as u64;
+    let magic_and_header = 4 + 22 + 2 + 2;
+    Ok(data.header_start + magic_and_header + file_name_length + extra_field_length)
+}
+
+pub(crate) fn central_header_to_zip_file<R: Read + io::Seek>(
+    reader: &mut R,
+    archive_offset: u64,
+    options: &ZipArchiveOptions,
+) -> ZipResult<ZipFileData> {
+    central_header_to_zip_file_and_disk(reader, archive_offset, options).map(|(file, _)| file)
+}
+
+/// Parse a central header, and return the number of the disk on which the file starts along with
+/// it
+fn central_header_to_zip_file_and_disk<R: Read + io::Seek>(
+    reader: &mut R,
+    archive_offset: u64,
+    options: &ZipArchiveOptions,
+) -> ZipResult<(ZipFileData, u16)> {
+    // Parse central header
+    let signature = reader.read_u32::<LittleEndian>()?;
+    if signature != spec::CENTRAL_DIRECTORY_HEADER_SIGNATURE {
+        return Err(ZipError::InvalidArchive("Invalid Central Directory header"));
+    }
+
+    let version_made_by = reader.read_u16::<LittleEndian>()?;
+    let version_to_extract = reader.read_u16::<LittleEndian>()?;
+    let flags = reader.read_u16::<LittleEndian>()?;
+    let encrypted = flags & 1 == 1;
+    let using_data_descriptor = flags & (1 << 3) != 0;
+    let is_utf8 = flags & (1 << 11) != 0;
+    let compression_method = reader.read_u16::<LittleEndian>()?;
+    let last_mod_time = reader.read_u16::<LittleEndian>()?;
+    let last_mod_date = reader.read_u16::<LittleEndian>()?;
+    let crc32 = reader.read_u32::<LittleEndian>()?;
+    let compressed_size = reader.read_u32::<LittleEndian>()?;
+    let uncompressed_size = reader.read_u32::<LittleEndian>()?;
+    let file_name_length = reader.read_u16::<LittleEndian>()? as usize;
+    let extra_field_length = reader.read_u16::<LittleEndian>()? as usize;
+    let file_comment_length = reader.read_u16::<LittleEndian>()? as usize;
+    let disk_number = reader.read_u16::<LittleEndian>()?;
+    let _internal_file_attributes = reader.read_u16::<LittleEndian>()?;
+    let external_file_attributes = reader.read_u32::<LittleEndian>()?;
+    let offset = reader.read_u32::<LittleEndian>()? as u64;
+    let file_name_raw = ReadPodExt::read_exact(reader, file_name_length)?;
+    let extra_field = ReadPodExt::read_exact(reader, extra_field_length)?;
+    let file_comment_raw = ReadPodExt::read_exact(reader, file_comment_length)?;
+
+    let file_name = if options.lazy_names {
+        String::new()
+    } else {
+        decode_name(&file_name_raw, is_utf8, options)
+    };
+    // The raw comment is not kept, so its buffer is reused for the decoded comment when it can be
+    let file_comment = match is_utf8 {
+        true => String::from_utf8(file_comment_raw)
+            .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned()),
+        false => file_comment_raw.from_cp437(),
+    };
+
+    // Construct the result
+    let mut result = ZipFileData {
+        system: System::from_u8((version_made_by >> 8) as u8),
+        version_made_by: version_made_by as u8,
+        version_needed_to_extract: version_to_extract,
+        encrypted: encrypted,
+        using_data_descriptor,
+        is_utf8,
+        large_file: false,
+        aes_mode: None,
+        compression_method: CompressionMethod::from_u16(compression_method),
+        last_modified_time: DateTime::from_msdos(last_mod_date, last_mod_time),
+        crc32: crc32,
+        compressed_size: compressed_size as u64,
+        uncompressed_size: uncompressed_size as u64,
+        file_name: file_name,
+        file_name_raw: file_name_raw,
+        file_comment: file_comment,
+        header_start: offset,
+        data_start: 0,
+        external_attributes: external_file_attributes,
+        unix_mtime: None,
+        unix_atime: None,
+        unix_ctime: None,
+        unix_uid: None,
+        unix_gid: None,
+        ntfs_mtime: None,
+        ntfs_atime: None,
+        ntfs_ctime: None,
+        extra_field: Vec::new(),
+    };
+
+    match parse_extra_field(&mut result, &*extra_field) {
+        Ok(..) | Err(ZipError::Io(..)) => {}
+        Err(e) => Err(e)?,
     }
+    result.extra_field = extra_field;
+
+    // Account for shifted zip offsets.
+    result.header_start += archive_offset;
+
+    Ok((result, disk_number))
 }
 
-impl<'a> Read for ZipFile<'a> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.get_reader().read(buf)
+fn parse_extra_field(file: &mut ZipFileData, data: &[u8]) -> ZipResult<()> {
+    let mut reader = io::Cursor::new(data);
+
+    while (reader.position() as usize) < data.len() {
+        let kind = reader.read_u16::<LittleEndian>()?;
+        let len = reader.read_u16::<LittleEndian>()? as usize;
+        let start = reader.position() as usize;
+        // A field claiming more data than is left would make us interpret the remainder of its
+        // payload as field headers, so treat it as a truncated extra field instead.
+        if len > data.len() - start {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Extra field exceeds the available data",
+            )
+            .into());
+        }
+        // Every field is parsed from its own slice, so a field can never read into the next one.
+        let mut field = &data[start..start + len];
+        match kind {
+            // Zip64 extended information extra field
+            0x0001 => {
+                file.large_file = true;
+                if file.uncompressed_size == 0xFFFFFFFF {
+                    file.uncompressed_size = field.read_u64::<LittleEndian>()?;
+                }
+                if file.compressed_size == 0xFFFFFFFF {
+                    file.compressed_size = field.read_u64::<LittleEndian>()?;
+                }
+                if file.header_start == 0xFFFFFFFF {
+                    file.header_start = field.read_u64::<LittleEndian>()?;
+                }
+                // Unparsed fields:
+                // u32: disk start number
+            }
+            // NTFS, with the timestamps in attribute 1
+            0x000a => {
+                let _reserved = field.read_u32::<LittleEndian>()?;
+                while field.len() >= 4 {
+                    let tag = field.read_u16::<LittleEndian>()?;
+                    let size = field.read_u16::<LittleEndian>()? as usize;
+                    if size > field.len() {
+                        break;
+                    }
+                    let (mut attribute, rest) = field.split_at(size);
+                    field = rest;
+                    if tag == 0x0001 && size >= 24 {
+                        file.ntfs_mtime = Some(attribute.read_u64::<LittleEndian>()?);
+                        file.ntfs_atime = Some(attribute.read_u64::<LittleEndian>()?);
+                        file.ntfs_ctime = Some(attribute.read_u64::<LittleEndian>()?);
+                    }
+                }
+            }
+            // Extended timestamp
+            0x5455 => {
+                let flags = field.read_u8()?;
+                // The central header only stores the modification time, even when the flags
+                // announce the other timestamps, so each one is only read when it is present.
+                if flags & 0x01 != 0 && field.len() >= 4 {
+                    file.unix_mtime = Some(field.read_u32::<LittleEndian>()?);
+                }
+                if flags & 0x02 != 0 && field.len() >= 4 {
+                    file.unix_atime = Some(field.read_u32::<LittleEndian>()?);
+                }
+                if flags & 0x04 != 0 && field.len() >= 4 {
+                    file.unix_ctime = Some(field.read_u32::<LittleEndian>()?);
+                }
+            }
+            // WinZip AES, which holds the compression method of the data before it was encrypted
+            0x9901 => {
+                let _vendor_version = field.read_u16::<LittleEndian>()?;
+                let vendor_id = [field.read_u8()?, field.read_u8()?];
+                let mode = match field.read_u8()? {
+                    1 => Some(AesMode::Aes128),
+                    2 => Some(AesMode::Aes192),
+                    3 => Some(AesMode::Aes256),
+                    _ => None,
+                };
+                let compression_method = field.read_u16::<LittleEndian>()?;
+                if let (b"AE", Some(mode)) = (&vendor_id, mode) {
+                    file.aes_mode = Some(mode);
+                    file.compression_method = CompressionMethod::from_u16(compression_method);
+                }
+            }
+            // Info-ZIP Unix, current version
+            0x7875 => {
+                let version = field.read_u8()?;
+                if version == 1 {
+                    if let Some(uid) = read_unix_id(&mut field)? {
+                        file.unix_uid = Some(uid);
+                    }
+                    if let Some(gid) = read_unix_id(&mut field)? {
+                        file.unix_gid = Some(gid);
+                    }
+                }
+            }
+            // Info-ZIP Unix, previous version. The fields above supersede it, so its values are
+            // only used where they did not provide one.
+            0x5855 => {
+                let atime = field.read_u32::<LittleEndian>()?;
+                let mtime = field.read_u32::<LittleEndian>()?;
+                file.unix_atime = file.unix_atime.or(Some(atime));
+                file.unix_mtime = file.unix_mtime.or(Some(mtime));
+                // The uid and gid are only stored in the local header
+                if field.len() >= 4 {
+                    let uid = field.read_u16::<LittleEndian>()? as u32;
+                    let gid = field.read_u16::<LittleEndian>()? as u32;
+                    file.unix_uid = file.unix_uid.or(Some(uid));
+                    file.unix_gid = file.unix_gid.or(Some(gid));
+                }
+            }
+            // AV Info, reserved (PFS language encoding), OS/2 and OpenVMS fields. We do not use
+            // their contents, they are simply skipped.
+            0x0007 | 0x0008 | 0x0009 | 0x000c => {}
+            _ => {}
+        }
+
+        reader.set_position((start + len) as u64);
+    }
+    Ok(())
+}
+
+/// Read a variable sized uid or gid from an Info-ZIP Unix extra field
+///
+/// Ids that do not fit in 32 bits are skipped.
+fn read_unix_id(field: &mut &[u8]) -> ZipResult<Option<u32>> {
+    let size = field.read_u8()? as usize;
+    let bytes = ReadPodExt::read_exact(field, size)?;
+    if size > 4 {
+        return Ok(None);
+    }
+    Ok(Some(
+        bytes
+            .iter()
+            .rev()
+            .fold(0u32, |id, &byte| (id << 8) | byte as u32),
+    ))
+}
+
+fn get_reader<'a>(reader: &'a mut ZipFileReader<'_>) -> &'a mut dyn Read {
+    match *reader {
+        ZipFileReader::NoReader => panic!("ZipFileReader was in an invalid state"),
+        ZipFileReader::Raw(ref mut r) => r as &mut dyn Read,
+        ZipFileReader::Stored(ref mut r) => r as &mut dyn Read,
+        #[cfg(feature = "deflate")]
+        ZipFileReader::Deflated(ref mut r) => r as &mut dyn Read,
+        #[cfg(feature = "bzip2")]
+        ZipFileReader::Bzip2(ref mut r) => r as &mut dyn Read,
+        #[cfg(feature = "zstd")]
+        ZipFileReader::Zstd(ref mut r) => r as &mut dyn Read,
+        #[cfg(feature = "reduce")]
+        ZipFileReader::Reduced(ref mut r) => r as &mut dyn Read,
+    }
+}
+
+/// Methods for retrieving information on zip files
+impl<'a> ZipFile<'a> {
+    fn get_reader(&mut self) -> &mut dyn Read {
+        get_reader(&mut self.reader)
+    }
+    fn read_contents(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.peeked.is_empty() {
+            let count = ::std::cmp::min(buf.len(), self.peeked.len());
+            buf[..count].copy_from_slice(&self.peeked[..count]);
+            self.peeked.drain(..count);
+            return Ok(count);
+        }
+        self.get_reader().read(buf)
+    }
+    /// Get the version of the file
+    pub fn version_made_by(&self) -> (u8, u8) {
+        (
+            self.data.version_made_by / 10,
+            self.data.version_made_by % 10,
+        )
+    }
+    /// Get the host system that made the file, along with the major and minor version of the
+    /// specification it supports
+    pub fn made_by(&self) -> (System, u8, u8) {
+        let (major, minor) = self.version_made_by();
+        (self.data.system, major, minor)
+    }
+    /// Get the name of the file
+    pub fn name(&self) -> &str {
+        &*self.data.file_name
+    }
+    /// Get the name of the file, in the raw (internal) byte representation.
+    pub fn name_raw(&self) -> &[u8] {
+        &*self.data.file_name_raw
+    }
+    /// Get the name of the file in a sanitized form. It truncates the name to the first NULL byte,
+    /// removes a leading '/' and removes '..' parts.
+    pub fn sanitized_name(&self) -> ::std::path::PathBuf {
+        self.data.file_name_sanitized()
+    }
+    /// Get the name of the file, if it is a relative path that stays within the directory it is
+    /// extracted to
+    ///
+    /// Unlike `sanitized_name`, this does not try to repair the name: names that are absolute,
+    /// contain a NULL byte or escape the extraction directory through '..' return `None`.
+    pub fn enclosed_name(&self) -> Option<&::std::path::Path> {
+        self.data.enclosed_name()
+    }
+    /// Get the comment of the file
+    pub fn comment(&self) -> &str {
+        &*self.data.file_comment
+    }
+    /// Get the raw extra field of the file
+    ///
+    /// This is the extra field of the central directory header, or of the local header if the
+    /// file is read from a stream. See also `ZipArchive::entry_extra`.
+    pub fn extra_data(&self) -> &[u8] {
+        &self.data.extra_field
+    }
+    /// Get the compression method used to store the file
+    pub fn compression(&self) -> CompressionMethod {
+        self.data.compression_method
+    }
+    /// Returns whether the file is stored without compression
+    ///
+    /// The compressed data of such files is the file contents itself, so it can be copied
+    /// verbatim, e.g. through `ZipArchive::by_index_raw`.
+    pub fn is_stored(&self) -> bool {
+        self.data.compression_method == CompressionMethod::Stored
+    }
+    /// Get the size of the file in the archive
+    pub fn compressed_size(&self) -> u64 {
+        self.data.compressed_size
+    }
+    /// Get the size of the file when uncompressed
+    pub fn size(&self) -> u64 {
+        self.data.uncompressed_size
+    }
+    /// Get the time the file was last modified
+    pub fn last_modified(&self) -> DateTime {
+        self.data.last_modified_time
+    }
+    /// Returns whether the file is actually a directory
+    pub fn is_dir(&self) -> bool {
+        self.name()
+            .chars()
+            .rev()
+            .next()
+            .map_or(false, |c| c == '/' || c == '\\')
+    }
+    /// Returns whether the file is a regular file
+    pub fn is_file(&self) -> bool {
+        !self.is_dir()
+    }
+    /// Returns whether the file is a MacOS AppleDouble companion entry
+    ///
+    /// These are stored in a `__MACOSX/` directory, or next to the file they belong to with a
+    /// `._` prefix, and hold the resource fork and extended attributes of a file.
+    pub fn is_apple_double(&self) -> bool {
+        let name = self.name();
+        let basename = name.rsplit(&['/', '\\'][..]).next().unwrap_or(name);
+        name.starts_with("__MACOSX/") || basename.starts_with("._")
+    }
+    /// Get the unix modification time of the file, in seconds since the epoch
+    ///
+    /// This is read from the extended timestamp extra field, or the older Info-ZIP Unix extra
+    /// field, and has a 1 second precision.
+    pub fn unix_modified(&self) -> Option<u32> {
+        self.data.unix_mtime
+    }
+    /// Get the unix access time of the file, in seconds since the epoch
+    pub fn unix_accessed(&self) -> Option<u32> {
+        self.data.unix_atime
+    }
+    /// Get the unix creation time of the file, in seconds since the epoch
+    pub fn unix_created(&self) -> Option<u32> {
+        self.data.unix_ctime
+    }
+    /// Get the NTFS modification time of the file, in 100 nanosecond intervals since 1601-01-01
+    ///
+    /// This is read from the NTFS extra field, which Windows tools write.
+    pub fn ntfs_modified(&self) -> Option<u64> {
+        self.data.ntfs_mtime
+    }
+    /// Get the NTFS access time of the file, in 100 nanosecond intervals since 1601-01-01
+    pub fn ntfs_accessed(&self) -> Option<u64> {
+        self.data.ntfs_atime
+    }
+    /// Get the NTFS creation time of the file, in 100 nanosecond intervals since 1601-01-01
+    pub fn ntfs_created(&self) -> Option<u64> {
+        self.data.ntfs_ctime
+    }
+    /// Get the unix user id of the owner of the file
+    pub fn unix_uid(&self) -> Option<u32> {
+        self.data.unix_uid
+    }
+    /// Get the unix group id of the owner of the file
+    pub fn unix_gid(&self) -> Option<u32> {
+        self.data.unix_gid
+    }
+    /// Get the MSDOS attributes of the file (read-only, hidden, system, directory, archive)
+    ///
+    /// This is only available for files that were added on a DOS or Windows system.
+    pub fn dos_attributes(&self) -> Option<u8> {
+        match self.data.system {
+            System::Dos => Some(self.data.external_attributes as u8),
+            _ => None,
+        }
+    }
+    /// Get the raw external attributes of the file
+    ///
+    /// Their meaning depends on the system that made the file, see `dos_attributes` and
+    /// `unix_mode` for the interpreted values.
+    pub fn external_attributes(&self) -> u32 {
+        self.data.external_attributes
+    }
+    /// Get unix mode for the file
+    pub fn unix_mode(&self) -> Option<u32> {
+        if self.data.external_attributes == 0 {
+            return None;
+        }
+
+        match self.data.system {
+            System::Unix => Some(self.data.external_attributes >> 16),
+            System::Dos => {
+                // Interpret MSDOS directory bit
+                let mut mode = if 0x10 == (self.data.external_attributes & 0x10) {
+                    ffi::S_IFDIR | 0o0775
+                } else {
+                    ffi::S_IFREG | 0o0664
+                };
+                if 0x01 == (self.data.external_attributes & 0x01) {
+                    // Read-only bit; strip write permissions
+                    mode &= 0o0555;
+                }
+                Some(mode)
+            }
+            _ => None,
+        }
+    }
+    /// Get the CRC32 hash of the original file
+    pub fn crc32(&self) -> u32 {
+        self.data.crc32
+    }
+
+    /// Get the size and CRC32 hash of the original file, to find identical files cheaply
+    ///
+    /// Both values describe the uncompressed contents, so files with the same contents have the
+    /// same fingerprint regardless of how they are compressed. This only uses metadata: a shared
+    /// fingerprint is a strong hint, but not a proof, that the contents are identical.
+    pub fn content_fingerprint(&self) -> (u64, u32) {
+        (self.data.uncompressed_size, self.data.crc32)
+    }
+
+    /// Returns whether the CRC32 hash of the file is known
+    ///
+    /// This is `false` for a file that is read from a stream and whose checksum is stored in a
+    /// data descriptor after its data, as `crc32` then returns the placeholder of the local
+    /// header. Files that are opened from a `ZipArchive` have their checksum from the central
+    /// directory.
+    pub fn crc32_known(&self) -> bool {
+        !(self.streamed && self.data.using_data_descriptor)
+    }
+
+    /// Get the starting offset of the data of the compressed file
+    pub fn data_start(&self) -> u64 {
+        self.data.data_start
+    }
+
+    pub(crate) fn get_metadata(&self) -> &ZipFileData {
+        &self.data
+    }
+
+    /// Convert the file into a plain reader of its contents
+    ///
+    /// The returned reader still validates the CRC32 checksum once the end of the data is
+    /// reached, and borrows the archive just like the `ZipFile` itself. This makes it easy to
+    /// wrap the contents in other `Read` adapters without copying them first.
+    pub fn into_reader(self) -> impl Read + 'a {
+        self
+    }
+
+    /// Read the remaining contents of the file to verify its CRC32 checksum
+    ///
+    /// `progress` is called after every chunk with the number of bytes read so far and the
+    /// uncompressed size of the file. Returns whether the checksum matched. Files opened with
+    /// `ZipArchive::by_index_raw` are not checked, so they always pass.
+    pub fn verify_with_progress<F: FnMut(u64, u64)>(&mut self, mut progress: F) -> ZipResult<bool> {
+        let total = self.size();
+        let mut done = 0;
+        let mut buffer = [0; 1 << 16];
+        loop {
+            match self.read(&mut buffer) {
+                Ok(0) => return Ok(true),
+                Ok(count) => {
+                    done += count as u64;
+                    progress(done, total);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if crate::crc32::is_checksum_error(&e) => return Ok(false),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Read the contents of the file in chunks of `chunk_size` bytes, calling `f` with each one
+    ///
+    /// A single buffer is reused for all chunks, so memory use stays constant however large the
+    /// file is. Every chunk but the last is full. The checksum of the data is validated when the
+    /// end of the file is reached, so a mismatch is only reported after `f` was called with
+    /// earlier chunks. Stops at the first error, which is returned, including errors from `f`.
+    /// A chunk size of zero is treated as one.
+    pub fn read_chunks<F>(&mut self, chunk_size: usize, mut f: F) -> ZipResult<()>
+    where
+        F: FnMut(&[u8]) -> ZipResult<()>,
+    {
+        let mut buffer = vec![0; ::std::cmp::max(chunk_size, 1)];
+        loop {
+            let mut filled = 0;
+            let mut eof = false;
+            while filled < buffer.len() {
+                match self.read(&mut buffer[filled..]) {
+                    Ok(0) => {
+                        eof = true;
+                        break;
+                    }
+                    Ok(count) => filled += count,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if filled > 0 {
+                f(&buffer[..filled])?;
+            }
+            if eof {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Limit the number of bytes that can be read from the file from now on
+    ///
+    /// Reading fails with an `InvalidData` error as soon as the file produces more than
+    /// `max_output` bytes, whatever size its headers declare. This bounds the time spent on a
+    /// single file of an untrusted archive, such as a decompression bomb, independent of any
+    /// other file.
+    pub fn set_output_limit(&mut self, max_output: u64) {
+        self.output_limit = Some(max_output);
+    }
+
+    /// Decompress the contents of the file into a buffer of exactly its size
+    ///
+    /// The length of `dst` must equal `size()`. Returns an error if the file decompresses to a
+    /// different number of bytes, in which case the contents of `dst` are unspecified. The
+    /// checksum of the data is validated as usual.
+    pub fn read_exact_into(&mut self, dst: &mut [u8]) -> ZipResult<()> {
+        if dst.len() as u64 != self.size() {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "buffer length does not match the file size",
+            )));
+        }
+        match Read::read_exact(self, dst) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(ZipError::InvalidArchive("Uncompressed size mismatch"))
+            }
+            Err(e) => return Err(e.into()),
+        }
+        // Reading up to the end of the data also validates the checksum
+        let mut probe = [0; 1];
+        loop {
+            match self.read(&mut probe) {
+                Ok(0) => return Ok(()),
+                Ok(_) => return Err(ZipError::InvalidArchive("Uncompressed size mismatch")),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Returns whether the contents of the file start with a zip signature
+    ///
+    /// This peeks at the first four bytes of the decompressed contents, and checks for the
+    /// signature of a local file header, or of an end of central directory record for an empty
+    /// archive. The bytes are kept, so they are still returned by later reads. This can be used
+    /// to decide whether to recurse into nested archives; callers should limit the depth of the
+    /// recursion themselves.
+    pub fn looks_like_zip(&mut self) -> ZipResult<bool> {
+        let mut buffer = [0; 4];
+        while self.peeked.len() < buffer.len() {
+            let wanted = buffer.len() - self.peeked.len();
+            match self.get_reader().read(&mut buffer[..wanted]) {
+                Ok(0) => break,
+                Ok(count) => self.peeked.extend_from_slice(&buffer[..count]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if self.peeked.len() < 4 {
+            return Ok(false);
+        }
+        let signature = u32::from_le_bytes([
+            self.peeked[0],
+            self.peeked[1],
+            self.peeked[2],
+            self.peeked[3],
+        ]);
+        Ok(signature == spec::LOCAL_FILE_HEADER_SIGNATURE
+            || signature == spec::CENTRAL_DIRECTORY_END_SIGNATURE)
+    }
+
+    /// Copy the remaining contents of the file to a writer, and hash them along the way
+    ///
+    /// Returns the number of bytes that were copied, and the hash of those bytes. The hasher is
+    /// reset afterwards. The checksum of the data is validated as usual, and in strict mode the
+    /// size is checked as in `copy_to`.
+    #[cfg(feature = "digest")]
+    pub fn copy_to_hashed<W, H>(
+        &mut self,
+        writer: &mut W,
+        hasher: &mut H,
+    ) -> ZipResult<(u64, digest::Output<H>)>
+    where
+        W: Write + ?Sized,
+        H: digest::Digest + digest::FixedOutputReset,
+    {
+        let mut buffer = [0; 1 << 16];
+        let mut count = 0;
+        loop {
+            match self.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    digest::Digest::update(hasher, &buffer[..n]);
+                    writer.write_all(&buffer[..n])?;
+                    count += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if self.strict && self.bytes_read != self.size() {
+            return Err(ZipError::InvalidArchive("Uncompressed size mismatch"));
+        }
+        Ok((count, digest::Digest::finalize_reset(hasher)))
+    }
+
+    /// Get a buffered reader over the remaining contents of the file
+    ///
+    /// This allows line-oriented reading, e.g. with `BufRead::lines`. Data that was buffered
+    /// but not consumed is lost when the returned reader is dropped.
+    pub fn buf_reader(&mut self) -> io::BufReader<&mut ZipFile<'a>> {
+        io::BufReader::new(self)
+    }
+
+    /// Copy the remaining contents of the file to a writer
+    ///
+    /// Returns the number of bytes that were actually decompressed, which may differ from the
+    /// size reported in the header. In strict mode, the total number of bytes read from the file,
+    /// including those read before this call, is compared with that size, and a difference is
+    /// reported as an error.
+    pub fn copy_to<W: Write + ?Sized>(&mut self, writer: &mut W) -> ZipResult<u64> {
+        let count = io::copy(self, writer)?;
+        if self.strict && self.bytes_read != self.size() {
+            return Err(ZipError::InvalidArchive("Uncompressed size mismatch"));
+        }
+        Ok(count)
+    }
+}
+
+impl<'a> Read for ZipFile<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = match self.output_limit {
+            None => self.read_contents(buf)?,
+            Some(remaining) => {
+                // Ask for one byte more than allowed, so exceeding the limit is noticed without
+                // decompressing any further
+                let len = ::std::cmp::min(buf.len() as u64, remaining.saturating_add(1)) as usize;
+                let count = self.read_contents(&mut buf[..len])?;
+                if count as u64 > remaining {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "File exceeds the output limit",
+                    ));
+                }
+                self.output_limit = Some(remaining - count as u64);
+                count
+            }
+        };
+        self.bytes_read += count as u64;
+        if let Some((ref mut hasher, _)) = self.descriptor_crc32 {
+            hasher.update(&buf[..count]);
+        }
+        Ok(count)
+    }
+}
+
+impl<'a> Drop for ZipFile<'a> {
+    fn drop(&mut self) {
+        // This reader is constructed by a streaming reader.
+        // In this case, we want to exhaust the reader so that the next file is accessible.
+        if let Some((mut hasher, crc32)) = self.descriptor_crc32.take() {
+            // Only the compressed data itself tells where it ends, so the rest of the file is
+            // decompressed. An error leaves the checksum unknown, which the streaming reader
+            // reports when it reads the next entry.
+            let mut buffer = [0; 1 << 16];
+            loop {
+                match self.read_contents(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(count) => hasher.update(&buffer[..count]),
+                    Err(_) => return,
+                }
+            }
+            *crc32 = Some(hasher.finalize());
+        } else if self.streamed {
+            let mut buffer = [0; 1 << 16];
+
+            // Get the inner compressed data reader so all decompression and CRC calculation is
+            // skipped.
+            let innerreader = ::std::mem::replace(&mut self.reader, ZipFileReader::NoReader);
+            let mut reader = match innerreader {
+                ZipFileReader::NoReader => panic!("ZipFileReader was in an invalid state"),
+                ZipFileReader::Raw(reader) => reader,
+                ZipFileReader::Stored(crcreader) => crcreader.into_inner(),
+                #[cfg(feature = "deflate")]
+                ZipFileReader::Deflated(crcreader) => crcreader.into_inner().into_inner(),
+                #[cfg(feature = "bzip2")]
+                ZipFileReader::Bzip2(crcreader) => crcreader.into_inner().into_inner(),
+                #[cfg(feature = "zstd")]
+                ZipFileReader::Zstd(crcreader) => crcreader.into_inner().finish(),
+                #[cfg(feature = "reduce")]
+                ZipFileReader::Reduced(crcreader) => crcreader.into_inner().into_inner(),
+            };
+
+            loop {
+                match reader.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(_) => (),
+                    Err(e) => panic!(
+                        "Could not consume all of the output of the current ZipFile: {:?}",
+                        e
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Reads consecutive ZipFile structures from a non-seekable reader, keeping track of progress
+///
+/// This works like `read_zipfile_from_stream`, counting the entries that were read and
+/// remembering when the central directory has been reached. Unlike that function, it also reads
+/// files that are followed by a data descriptor. When their local header holds no sizes, as in
+/// the output of `ZipWriter::new_streaming`, the end of the data is found by decompressing it,
+/// which is only possible for deflated files and for empty stored files. The descriptor is
+/// checked against the contents and skipped before the next entry is read.
+#[derive(Debug)]
+pub struct ZipStreamReader<R: Read> {
+    reader: io::BufReader<R>,
+    entries_read: usize,
+    finished: bool,
+    /// Checksum and ZIP64 flag of the previous file, if a data descriptor follows it. The
+    /// checksum is `None` when it is only in the descriptor.
+    pending_descriptor: Option<(Option<u32>, bool)>,
+    /// Checksum of the contents of the previous file, once it was read to its end
+    streamed_crc32: Option<u32>,
+}
+
+impl<R: Read> ZipStreamReader<R> {
+    /// Start reading entries from a stream positioned at the first local file header
+    pub fn new(reader: R) -> ZipStreamReader<R> {
+        ZipStreamReader {
+            reader: io::BufReader::with_capacity(DEFAULT_READ_BUFFER_SIZE, reader),
+            entries_read: 0,
+            finished: false,
+            pending_descriptor: None,
+            streamed_crc32: None,
+        }
+    }
+
+    /// Read the next entry, or return `None` once the central directory is reached
+    ///
+    /// The same caveats as for `read_zipfile_from_stream` apply.
+    pub fn next_entry(&mut self) -> ZipResult<Option<ZipFile<'_>>> {
+        if self.finished {
+            return Ok(None);
+        }
+        if let Some((crc32, large_file)) = self.pending_descriptor.take() {
+            let crc32 = match crc32.or_else(|| self.streamed_crc32.take()) {
+                Some(crc32) => crc32,
+                None => {
+                    return Err(ZipError::InvalidArchive(
+                        "The previous file could not be read to its end",
+                    ))
+                }
+            };
+            read_data_descriptor(&mut self.reader, crc32, large_file)?;
+        }
+        let data = match read_local_file_header(&mut self.reader)? {
+            Some(data) => data,
+            None => {
+                self.finished = true;
+                return Ok(None);
+            }
+        };
+        self.entries_read += 1;
+
+        let sizes_known =
+            !data.using_data_descriptor || data.crc32 != 0 || data.compressed_size != 0;
+        if sizes_known {
+            if data.using_data_descriptor {
+                self.pending_descriptor = Some((Some(data.crc32), data.large_file));
+            }
+            let reader = (&mut self.reader).take(data.compressed_size);
+            return streamed_file(data, Box::new(reader), None).map(Some);
+        }
+
+        match data.compression_method {
+            #[cfg(feature = "deflate")]
+            CompressionMethod::Deflated => {
+                self.pending_descriptor = Some((None, data.large_file));
+                self.streamed_crc32 = None;
+                let crc32 = &mut self.streamed_crc32;
+                streamed_file(data, Box::new(&mut self.reader), Some(crc32)).map(Some)
+            }
+            CompressionMethod::Stored => {
+                // Without sizes, only an empty file can be told apart from its descriptor, which
+                // then directly follows the header. It starts with the signature, or else with
+                // the zero checksum and sizes of the empty file.
+                let next = self.reader.fill_buf()?;
+                let signature = spec::DATA_DESCRIPTOR_SIGNATURE.to_le_bytes();
+                if !next.starts_with(&signature) && !next.starts_with(&[0; 12]) {
+                    return unsupported_zip_error(
+                        "Stored files need the sizes in their local header to be read from a stream",
+                    );
+                }
+                self.pending_descriptor = Some((Some(0), data.large_file));
+                streamed_file(data, Box::new(io::empty()), None).map(Some)
+            }
+            _ => unsupported_zip_error(
+                "Only deflated files can be streamed without the sizes in their local header",
+            ),
+        }
+    }
+
+    /// Number of entries that were returned by `next_entry` so far
+    pub fn entries_read(&self) -> usize {
+        self.entries_read
+    }
+
+    /// Returns whether the central directory has been reached, i.e. all entries have been read
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Unwrap and return the inner reader object
+    ///
+    /// Data that was read ahead from the inner reader into the buffer of this reader is lost.
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+}
+
+/// Read ZipFile structures from a non-seekable reader.
+///
+/// This is an alternative method to read a zip file. If possible, use the ZipArchive functions
+/// as some information will be missing when reading this manner.
+///
+/// Reads a file header from the start of the stream. Will return `Ok(Some(..))` if a file is
+/// present at the start of the stream. Returns `Ok(None)` if the start of the central directory
+/// is encountered. No more files should be read after this.
+///
+/// The Drop implementation of ZipFile ensures that the reader will be correctly positioned after
+/// the structure is done.
+///
+/// Missing fields are:
+/// * `comment`: set to an empty string
+/// * `data_start`: set to 0
+/// * `external_attributes`: `unix_mode()`: will return None
+pub fn read_zipfile_from_stream<R: io::Read>(reader: &mut R) -> ZipResult<Option<ZipFile<'_>>> {
+    let data = match read_local_file_header(reader)? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    if data.using_data_descriptor {
+        return unsupported_zip_error("The file length is not available in the local header");
+    }
+
+    let limit_reader = (reader as &mut dyn io::Read).take(data.compressed_size);
+    let buf_reader = io::BufReader::with_capacity(DEFAULT_READ_BUFFER_SIZE, limit_reader);
+    streamed_file(data, Box::new(buf_reader), None).map(Some)
+}
+
+/// Read a local file header, or return `None` at the start of the central directory
+fn read_local_file_header<R: io::Read>(reader: &mut R) -> ZipResult<Option<ZipFileData>> {
+    let signature = reader.read_u32::<LittleEndian>()?;
+
+    match signature {
+        spec::LOCAL_FILE_HEADER_SIGNATURE => (),
+        spec::CENTRAL_DIRECTORY_HEADER_SIGNATURE => return Ok(None),
+        _ => return Err(ZipError::InvalidArchive("Invalid local file header")),
+    }
+
+    let version_made_by = reader.read_u16::<LittleEndian>()?;
+    let flags = reader.read_u16::<LittleEndian>()?;
+    let encrypted = flags & 1 == 1;
+    let is_utf8 = flags & (1 << 11) != 0;
+    let using_data_descriptor = flags & (1 << 3) != 0;
+    let compression_method = CompressionMethod::from_u16(reader.read_u16::<LittleEndian>()?);
+    let last_mod_time = reader.read_u16::<LittleEndian>()?;
+    let last_mod_date = reader.read_u16::<LittleEndian>()?;
+    let crc32 = reader.read_u32::<LittleEndian>()?;
+    let compressed_size = reader.read_u32::<LittleEndian>()?;
+    let uncompressed_size = reader.read_u32::<LittleEndian>()?;
+    let file_name_length = reader.read_u16::<LittleEndian>()? as usize;
+    let extra_field_length = reader.read_u16::<LittleEndian>()? as usize;
+
+    let file_name_raw = ReadPodExt::read_exact(reader, file_name_length)?;
+    let extra_field = ReadPodExt::read_exact(reader, extra_field_length)?;
+
+    let file_name = match is_utf8 {
+        true => String::from_utf8_lossy(&*file_name_raw).into_owned(),
+        false => file_name_raw[..].from_cp437().into_owned(),
+    };
+
+    let mut result = ZipFileData {
+        system: System::from_u8((version_made_by >> 8) as u8),
+        version_made_by: version_made_by as u8,
+        version_needed_to_extract: version_made_by,
+        encrypted: encrypted,
+        using_data_descriptor,
+        is_utf8,
+        large_file: false,
+        aes_mode: None,
+        compression_method: compression_method,
+        last_modified_time: DateTime::from_msdos(last_mod_date, last_mod_time),
+        crc32: crc32,
+        compressed_size: compressed_size as u64,
+        uncompressed_size: uncompressed_size as u64,
+        file_name: file_name,
+        file_name_raw: file_name_raw,
+        file_comment: String::new(), // file comment is only available in the central directory
+        // header_start and data start are not available, but also don't matter, since seeking is
+        // not available.
+        header_start: 0,
+        data_start: 0,
+        // The external_attributes field is only available in the central directory.
+        // We set this to zero, which should be valid as the docs state 'If input came
+        // from standard input, this field is set to zero.'
+        external_attributes: 0,
+        unix_mtime: None,
+        unix_atime: None,
+        unix_ctime: None,
+        unix_uid: None,
+        unix_gid: None,
+        ntfs_mtime: None,
+        ntfs_atime: None,
+        ntfs_ctime: None,
+        extra_field: Vec::new(),
+    };
+
+    match parse_extra_field(&mut result, &extra_field) {
+        Ok(..) | Err(ZipError::Io(..)) => {}
+        Err(e) => Err(e)?,
+    }
+    result.extra_field = extra_field;
+
+    if encrypted {
+        return unsupported_zip_error("Encrypted files are not supported");
+    }
+
+    Ok(Some(result))
+}
+
+/// Open the data of a file that is read from a stream
+///
+/// With `descriptor_crc32`, the checksum is only known from the data descriptor, so instead of
+/// being checked, the checksum of the contents is stored there once the file is dropped.
+fn streamed_file<'a>(
+    data: ZipFileData,
+    reader: Box<dyn BufRead + 'a>,
+    descriptor_crc32: Option<&'a mut Option<u32>>,
+) -> ZipResult<ZipFile<'a>> {
+    let crc32 = match descriptor_crc32 {
+        Some(_) => None,
+        None => Some(data.crc32),
+    };
+    Ok(ZipFile {
+        reader: make_reader(
+            data.compression_method,
+            crc32,
+            data.uncompressed_size,
+            false,
+            reader,
+        )?,
+        data: Cow::Owned(data),
+        strict: false,
+        peeked: Vec::new(),
+        output_limit: None,
+        bytes_read: 0,
+        streamed: true,
+        descriptor_crc32: descriptor_crc32.map(|crc32| (crc32fast::Hasher::new(), crc32)),
+    })
+}
+
+/// Read the data descriptor that follows the data of a file, and check its checksum
+///
+/// The descriptor may or may not start with a signature. When its first four bytes equal the
+/// signature but the file has a different checksum, they are the signature. A file whose
+/// checksum equals the signature is told apart by whether the next four bytes repeat it.
+fn read_data_descriptor<R: io::Read>(
+    reader: &mut R,
+    crc32: u32,
+    large_file: bool,
+) -> ZipResult<()> {
+    let mut sizes_length = if large_file { 16 } else { 8 };
+    let first = reader.read_u32::<LittleEndian>()?;
+    let descriptor_crc32 = if first != spec::DATA_DESCRIPTOR_SIGNATURE {
+        first
+    } else {
+        let second = reader.read_u32::<LittleEndian>()?;
+        if crc32 == spec::DATA_DESCRIPTOR_SIGNATURE && second != crc32 {
+            // There was no signature, and the second value is part of the sizes
+            sizes_length -= 4;
+            first
+        } else {
+            second
+        }
+    };
+    ReadPodExt::read_exact(reader, sizes_length)?;
+    if descriptor_crc32 != crc32 {
+        return Err(ZipError::InvalidArchive(
+            "Data descriptor checksum does not match the local header",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn invalid_offset() {
+        use super::ZipArchive;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/invalid_offset.zip"));
+        let reader = ZipArchive::new(io::Cursor::new(v));
+        assert!(reader.is_err());
+    }
+
+    #[test]
+    fn zip64_with_leading_junk() {
+        use super::ZipArchive;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/zip64_demo.zip"));
+        let reader = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert!(reader.len() == 1);
+    }
+
+    #[test]
+    fn zip64_without_locator() {
+        use super::ZipArchive;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/zip64_no_locator.zip"));
+        let mut reader = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert_eq!(reader.len(), 1);
+        assert_eq!(reader.offset(), 14);
+        assert_eq!(reader.by_index(0).unwrap().name(), "-");
+    }
+
+    #[test]
+    fn misleading_stub() {
+        use super::ZipArchive;
+        use std::io::{self, Read};
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/misleading_stub.zip"));
+        let mut reader = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert_eq!(reader.offset(), 0);
+        let mut contents = String::new();
+        reader
+            .by_name("mimetype")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "application/vnd.oasis.opendocument.text");
+    }
+
+    #[test]
+    fn no_central_directory_candidate() {
+        use super::ZipArchive;
+        use crate::result::ZipError;
+        use std::io;
+
+        let mut v = b"stub".to_vec();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        assert_eq!(
+            ZipArchive::new(io::Cursor::new(v.clone()))
+                .unwrap()
+                .offset(),
+            4
+        );
+
+        // Neither the computed nor the recorded offset points at a central directory header
+        let header = v.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        v[header] = 0;
+        match ZipArchive::new(io::Cursor::new(v)) {
+            Err(ZipError::InvalidArchive("Could not find the start of the central directory")) => {}
+            r => panic!("Unexpected result: {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn recover_malformed_central_directory() {
+        use super::{ParseWarning, ZipArchive, ZipArchiveOptions};
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/files_and_dirs.zip"));
+        // Corrupt the signature of the second central directory header
+        let second_header = v
+            .windows(4)
+            .enumerate()
+            .filter(|&(_, w)| w == b"PK\x01\x02")
+            .nth(1)
+            .unwrap()
+            .0;
+        v[second_header] = 0;
+
+        assert!(ZipArchive::new(io::Cursor::new(v.clone())).is_err());
+
+        let options = ZipArchiveOptions::default().recover(true);
+        let zip = ZipArchive::with_options(io::Cursor::new(v), options).unwrap();
+        assert_eq!(zip.len(), 1);
+        assert_eq!(
+            zip.parse_warnings(),
+            &[ParseWarning::MissingEntries {
+                declared: 3,
+                parsed: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn local_extended_timestamp() {
+        use super::ZipArchive;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/extended_timestamp.zip"));
+        let mut reader = ZipArchive::new(io::Cursor::new(v)).unwrap();
+
+        // The central header only holds the modification time
+        assert_eq!(reader.files[0].unix_mtime, Some(1600000000));
+        assert_eq!(reader.files[0].unix_atime, None);
+
+        let file = reader.by_index(0).unwrap();
+        assert_eq!(file.unix_modified(), Some(1600000000));
+        assert_eq!(file.unix_accessed(), Some(1600000100));
+        assert_eq!(file.unix_uid(), Some(0));
+        assert_eq!(file.unix_gid(), Some(0));
+        drop(file);
+
+        // Opening the file leaves the central directory information as it was
+        assert_eq!(reader.files[0].unix_atime, None);
+        assert_eq!(reader.files[0].data_start, 0);
+    }
+
+    #[test]
+    fn zip_comment() {
+        use super::ZipArchive;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        let reader = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert!(reader.comment == b"zip-rs");
+    }
+
+    #[test]
+    fn comment_location() {
+        use super::ZipArchive;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        let len = v.len();
+        let reader = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let (start, length) = reader.comment_location();
+        assert_eq!(length, 6);
+        assert_eq!(start as usize, len - length);
+        let v = reader.into_inner().into_inner();
+        assert_eq!(&v[start as usize..], b"zip-rs");
+    }
+
+    #[test]
+    fn from_parts() {
+        use super::ZipArchive;
+        use std::io::{self, Read};
+
+        let v = include_bytes!("../tests/data/files_and_dirs.zip").to_vec();
+        let zip = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        let (_, directory) = zip.into_parts();
+
+        for _ in 0..2 {
+            let reader = io::Cursor::new(v.clone());
+            let mut zip = ZipArchive::from_parts(reader, directory.clone());
+            assert_eq!(zip.len(), 3);
+            let mut file = zip.by_name("file0.txt").unwrap();
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents).unwrap();
+            assert_eq!(contents.len() as u64, file.size());
+        }
+    }
+
+    #[test]
+    fn small_read_buffer() {
+        use super::ZipArchive;
+        use std::io::{self, Read};
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        let mut reader = ZipArchive::new(io::Cursor::new(v)).unwrap();
+
+        let mut expected = String::new();
+        reader
+            .by_index(0)
+            .unwrap()
+            .read_to_string(&mut expected)
+            .unwrap();
+
+        reader.set_read_buffer_size(1);
+        let mut contents = String::new();
+        reader
+            .by_index(0)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, expected);
+    }
+
+    /// Build an archive with the given entries, written with the default options
+    fn archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        use crate::write::FileOptions;
+
+        archive_with(FileOptions::default(), entries)
+    }
+
+    /// Build an archive with the given entries, all written with `options`. Names that end with a
+    /// slash are added as directories.
+    fn archive_with(options: crate::write::FileOptions, entries: &[(&str, &[u8])]) -> Vec<u8> {
+        use crate::write::ZipWriter;
+        use std::io::{self, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for &(name, contents) in entries {
+            if name.ends_with('/') {
+                writer.add_directory(name, options.clone()).unwrap();
+                continue;
+            }
+            writer.start_file(name, options.clone()).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Flip the case of the first byte of `needle` in `buf`, e.g. to damage the data of a stored
+    /// entry so its checksum no longer matches
+    fn corrupt(buf: &mut [u8], needle: &[u8]) {
+        let pos = buf.windows(needle.len()).position(|w| w == needle).unwrap();
+        buf[pos] ^= 0x20;
+    }
+
+    fn empty_file_data() -> crate::types::ZipFileData {
+        use crate::compression::CompressionMethod;
+        use crate::types::{DateTime, System, ZipFileData};
+
+        ZipFileData {
+            system: System::Unix,
+            version_made_by: 0,
+            version_needed_to_extract: 0,
+            encrypted: false,
+            using_data_descriptor: false,
+            is_utf8: false,
+            large_file: false,
+            aes_mode: None,
+            compression_method: CompressionMethod::Stored,
+            last_modified_time: DateTime::default(),
+            crc32: 0,
+            compressed_size: 0,
+            uncompressed_size: 0,
+            file_name: String::new(),
+            file_name_raw: Vec::new(),
+            file_comment: String::new(),
+            header_start: 0,
+            data_start: 0,
+            external_attributes: 0,
+            unix_mtime: None,
+            unix_atime: None,
+            unix_ctime: None,
+            unix_uid: None,
+            unix_gid: None,
+            ntfs_mtime: None,
+            ntfs_atime: None,
+            ntfs_ctime: None,
+            extra_field: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn extra_field_info_zip_unix_old() {
+        use super::parse_extra_field;
+
+        // Central header layout: atime and mtime only
+        let central: &[u8] = &[
+            0x55, 0x58, 0x08, 0x00, // tag 0x5855, 8 bytes
+            0x10, 0x00, 0x00, 0x00, // atime
+            0x20, 0x00, 0x00, 0x00, // mtime
+        ];
+        let mut file = empty_file_data();
+        parse_extra_field(&mut file, central).unwrap();
+        assert_eq!(file.unix_atime, Some(0x10));
+        assert_eq!(file.unix_mtime, Some(0x20));
+        assert_eq!(file.unix_uid, None);
+        assert_eq!(file.unix_gid, None);
+
+        // Local header layout: atime, mtime, uid and gid
+        let local: &[u8] = &[
+            0x55, 0x58, 0x0c, 0x00, // tag 0x5855, 12 bytes
+            0x10, 0x00, 0x00, 0x00, // atime
+            0x20, 0x00, 0x00, 0x00, // mtime
+            0xe8, 0x03, 0xe9, 0x03, // uid 1000, gid 1001
+        ];
+        let mut file = empty_file_data();
+        parse_extra_field(&mut file, local).unwrap();
+        assert_eq!(file.unix_atime, Some(0x10));
+        assert_eq!(file.unix_mtime, Some(0x20));
+        assert_eq!(file.unix_uid, Some(1000));
+        assert_eq!(file.unix_gid, Some(1001));
+    }
+
+    #[test]
+    fn extra_field_info_zip_unix_precedence() {
+        use super::parse_extra_field;
+
+        // The newer fields win, whether they come before or after the old one
+        let old: &[u8] = &[
+            0x55, 0x58, 0x0c, 0x00, // tag 0x5855, 12 bytes
+            0x10, 0x00, 0x00, 0x00, // atime
+            0x20, 0x00, 0x00, 0x00, // mtime
+            0xe8, 0x03, 0xe9, 0x03, // uid 1000, gid 1001
+        ];
+        let new: &[u8] = &[
+            0x55, 0x54, 0x05, 0x00, // tag 0x5455, 5 bytes
+            0x01, 0x30, 0x00, 0x00, 0x00, // mtime only
+            0x75, 0x78, 0x0b, 0x00, // tag 0x7875, 11 bytes
+            0x01, 0x04, 0x00, 0x00, 0x00, 0x00, // version 1, uid 0
+            0x04, 0x00, 0x00, 0x00, 0x00, // gid 0
+        ];
+
+        for extra in &[[old, new].concat(), [new, old].concat()] {
+            let mut file = empty_file_data();
+            parse_extra_field(&mut file, extra).unwrap();
+            assert_eq!(file.unix_mtime, Some(0x30));
+            assert_eq!(file.unix_atime, Some(0x10));
+            assert_eq!(file.unix_uid, Some(0));
+            assert_eq!(file.unix_gid, Some(0));
+        }
+    }
+
+    #[test]
+    fn extra_field_stacked_tags() {
+        use super::parse_extra_field;
+
+        let extra: &[u8] = &[
+            0x07, 0x00, 0x03, 0x00, 0xaa, 0xbb, 0xcc, // AV info
+            0x09, 0x00, 0x04, 0x00, 0x01, 0x00, 0x02, 0x00, // OS/2
+            0x01, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, // Zip64
+            0x0c, 0x00, 0x02, 0x00, 0x01, 0x00, // OpenVMS
+            0x08, 0x00, 0x00, 0x00, // reserved, empty
+            0xfe, 0xca, 0x01, 0x00, 0xff, // unknown
+        ];
+        let mut data = empty_file_data();
+        data.uncompressed_size = 0xFFFFFFFF;
+        data.compressed_size = 10;
+        parse_extra_field(&mut data, extra).unwrap();
+        assert_eq!(data.uncompressed_size, 1 << 32);
+        assert_eq!(data.compressed_size, 10);
+    }
+
+    #[test]
+    fn extra_field_zip64_subsets() {
+        use super::parse_extra_field;
+
+        // Each value is only present when its header field is the 0xFFFFFFFF marker, in the order
+        // uncompressed size, compressed size, header offset, followed by the disk number.
+        let promoted = [(1 << 32) + 1, (1 << 32) + 2, (1 << 32) + 3];
+        for subset in 0..8 {
+            let present: Vec<bool> = (0..3).map(|i| subset & (1 << i) != 0).collect();
+            let mut payload = Vec::new();
+            for (i, value) in promoted.iter().enumerate() {
+                if present[i] {
+                    payload.extend_from_slice(&u64::to_le_bytes(*value));
+                }
+            }
+            payload.extend_from_slice(&[0, 0, 0, 0]);
+            let mut extra = vec![0x01, 0x00, payload.len() as u8, 0x00];
+            extra.extend_from_slice(&payload);
+
+            let base: Vec<u64> = (0..3)
+                .map(|i| {
+                    if present[i] {
+                        0xFFFFFFFF
+                    } else {
+                        100 + i as u64
+                    }
+                })
+                .collect();
+            let mut data = empty_file_data();
+            data.uncompressed_size = base[0];
+            data.compressed_size = base[1];
+            data.header_start = base[2];
+            parse_extra_field(&mut data, &extra).unwrap();
+
+            let expected: Vec<u64> = (0..3)
+                .map(|i| if present[i] { promoted[i] } else { base[i] })
+                .collect();
+            assert_eq!(data.uncompressed_size, expected[0], "subset {}", subset);
+            assert_eq!(data.compressed_size, expected[1], "subset {}", subset);
+            assert_eq!(data.header_start, expected[2], "subset {}", subset);
+        }
+    }
+
+    #[test]
+    fn extra_field_overlong() {
+        use super::parse_extra_field;
+        use crate::result::ZipError;
+        use std::io;
+
+        // The OS/2 field claims 8 bytes, but only 4 are present. Its payload happens to look like
+        // the start of a zip64 field, which must not be picked up.
+        let extra: &[u8] = &[0x09, 0x00, 0x08, 0x00, 0x01, 0x00, 0x08, 0x00];
+        let mut data = empty_file_data();
+        data.uncompressed_size = 0xFFFFFFFF;
+        match parse_extra_field(&mut data, extra) {
+            Err(ZipError::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        assert_eq!(data.uncompressed_size, 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn uncompressed_size_mismatch() {
+        use super::{ZipArchive, ZipArchiveOptions};
+        use crate::result::ZipError;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        // Let the central directory claim one more byte than the entry contains
+        v[101] += 1;
+
+        let mut reader = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        let contents = reader.read_file("mimetype").unwrap();
+        assert_eq!(contents, b"application/vnd.oasis.opendocument.text");
+
+        let options = ZipArchiveOptions::default().strict(true);
+        let mut reader = ZipArchive::with_options(io::Cursor::new(v), options).unwrap();
+        let mut sink = Vec::new();
+        match reader.by_name("mimetype").unwrap().copy_to(&mut sink) {
+            Err(ZipError::InvalidArchive("Uncompressed size mismatch")) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        assert!(reader.read_file("mimetype").is_err());
+    }
+
+    #[test]
+    fn strict_copy_after_partial_read() {
+        use super::{ZipArchive, ZipArchiveOptions};
+        use std::io::{self, Read};
+
+        let v = include_bytes!("../tests/data/mimetype.zip").to_vec();
+        let options = ZipArchiveOptions::default().strict(true);
+        let mut reader = ZipArchive::with_options(io::Cursor::new(v), options).unwrap();
+        let mut file = reader.by_name("mimetype").unwrap();
+        let mut start = [0; 12];
+        file.read_exact(&mut start).unwrap();
+        assert_eq!(&start, b"application/");
+
+        let mut rest = Vec::new();
+        assert_eq!(file.copy_to(&mut rest).unwrap(), 27);
+        assert_eq!(rest, b"vnd.oasis.opendocument.text");
+    }
+
+    #[test]
+    fn shared_archive() {
+        use super::ZipArchive;
+        use std::io::{self, Read};
+        use std::sync::Arc;
+
+        let bytes: Arc<[u8]> =
+            archive(&[("a.txt", &[b'a'; 1000]), ("b.txt", &[b'b'; 1000])]).into();
+
+        let archive = ZipArchive::new(io::Cursor::new(bytes)).unwrap();
+        let shared = archive.shared().unwrap();
+        assert_eq!(shared.len(), 2);
+
+        let mut a = shared.by_name("a.txt").unwrap();
+        let mut b = shared.by_index(1).unwrap();
+        let mut buf_a = Vec::new();
+        let mut buf_b = Vec::new();
+        let mut chunk = [0u8; 100];
+        for _ in 0..10 {
+            let n = a.read(&mut chunk).unwrap();
+            buf_a.extend_from_slice(&chunk[..n]);
+            let n = b.read(&mut chunk).unwrap();
+            buf_b.extend_from_slice(&chunk[..n]);
+        }
+        a.read_to_end(&mut buf_a).unwrap();
+        b.read_to_end(&mut buf_b).unwrap();
+        assert_eq!(buf_a, vec![b'a'; 1000]);
+        assert_eq!(buf_b, vec![b'b'; 1000]);
+    }
+
+    #[test]
+    fn by_header_offset() {
+        use super::ZipArchive;
+        use crate::result::ZipError;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/files_and_dirs.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+
+        for i in 0..zip.len() {
+            let offset = zip.files[i].header_start;
+            let name = zip.files[i].file_name.clone();
+            assert_eq!(zip.by_header_offset(offset).unwrap().name(), name);
+        }
+        match zip.by_header_offset(1) {
+            Err(ZipError::FileNotFound) => {}
+            _ => panic!("Expected FileNotFound"),
+        };
+    }
+
+    #[test]
+    fn raw_passthrough() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use std::io::{self, Read};
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        // Mark the file as JPEG compressed, in both the local and the central header
+        v[8] = 96;
+        v[87] = 96;
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert!(zip.by_index(0).is_err());
+
+        let mut file = zip.by_index_raw(0).unwrap();
+        assert_eq!(file.compression(), CompressionMethod::Jpeg);
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"application/vnd.oasis.opendocument.text");
+        drop(file);
+
+        zip.files[0].compression_method = CompressionMethod::from_u16(94);
+        assert!(zip.by_index(0).is_err());
+        let mut file = zip.by_index_raw(0).unwrap();
+        assert_eq!(file.compression(), CompressionMethod::Mp3);
+        assert_eq!(file.compression().to_string(), "Mp3");
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"application/vnd.oasis.opendocument.text");
+    }
+
+    #[test]
+    fn diff() {
+        use super::{EntryDiff, ZipArchive};
+        use crate::types::DateTime;
+        use crate::write::FileOptions;
+        use std::io;
+
+        let options = FileOptions::default().last_modified_time(DateTime::default());
+        let build = |entries: &[(&str, &[u8])]| {
+            let buf = archive_with(options.clone(), entries);
+            ZipArchive::new(io::Cursor::new(buf)).unwrap()
+        };
+
+        let old = build(&[("same", b"1"), ("changed", b"2"), ("removed", b"3")]);
+        let new = build(&[("added", b"4"), ("changed", b"5"), ("same", b"1")]);
+
+        assert_eq!(old.diff(&old), Vec::new());
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                EntryDiff::Changed("changed".to_string()),
+                EntryDiff::Removed("removed".to_string()),
+                EntryDiff::Added("added".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn entry_bytes() {
+        use super::ZipArchive;
+
+        let zip = ZipArchive::from_mmap(include_bytes!("../tests/data/mimetype.zip")).unwrap();
+        assert_eq!(
+            zip.entry_bytes(0).unwrap(),
+            b"application/vnd.oasis.opendocument.text"
+        );
+        assert!(zip.entry_bytes(1).is_err());
+        assert_eq!(
+            zip.try_entry_slice(0),
+            Some(&b"application/vnd.oasis.opendocument.text"[..])
+        );
+        assert_eq!(zip.try_entry_slice(1), None);
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn entry_bytes_compressed() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::write::FileOptions;
+
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        let buf = archive_with(options, &[("deflated", b"compressed contents")]);
+        let zip = ZipArchive::from_mmap(buf).unwrap();
+        assert!(zip.entry_bytes(0).is_err());
+        assert_eq!(zip.try_entry_slice(0), None);
+    }
+
+    #[test]
+    fn verify_with_progress() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::write::FileOptions;
+        use std::io;
+
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let mut buf = archive_with(
+            options,
+            &[("good", &[7; 200_000]), ("corrupt", b"corrupt contents")],
+        );
+
+        // Damage the data of "corrupt", so its checksum no longer matches
+        corrupt(&mut buf, b"corrupt contents");
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let mut calls = Vec::new();
+        let matched = zip
+            .by_name("good")
+            .unwrap()
+            .verify_with_progress(|done, total| calls.push((done, total)))
+            .unwrap();
+        assert!(matched);
+        assert!(calls.len() > 1);
+        assert!(calls.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(calls.last(), Some(&(200_000, 200_000)));
+
+        let matched = zip
+            .by_name("corrupt")
+            .unwrap()
+            .verify_with_progress(|_, _| {})
+            .unwrap();
+        assert!(!matched);
+    }
+
+    #[test]
+    fn extract_lossy() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::write::FileOptions;
+        use std::fs;
+        use std::io;
+
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let mut buf = archive_with(
+            options,
+            &[
+                ("dir/good", b"good contents"),
+                ("../escaped", b"escaped contents"),
+                ("corrupt", b"corrupt contents"),
+                ("last", b"last contents"),
+            ],
+        );
+
+        // Damage the data of "corrupt", so its checksum no longer matches
+        corrupt(&mut buf, b"corrupt contents");
+
+        let directory =
+            std::env::temp_dir().join(format!("zip-rs-extract-lossy-{}", std::process::id()));
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        assert!(zip.extract(&directory).is_err());
+
+        let failures = zip.extract_lossy(&directory).unwrap();
+        let failed: Vec<usize> = failures.iter().map(|&(i, _)| i).collect();
+        assert_eq!(failed, vec![1, 2]);
+        assert_eq!(
+            fs::read(directory.join("dir").join("good")).unwrap(),
+            b"good contents"
+        );
+        assert_eq!(fs::read(directory.join("last")).unwrap(), b"last contents");
+        assert!(!directory.join("..").join("escaped").exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn apple_double() {
+        use super::ZipArchive;
+        use std::io;
+
+        let buf = archive(&[
+            ("dir/file", b""),
+            ("__MACOSX/dir/._file", b""),
+            ("dir/._file", b""),
+            ("dir/file._", b""),
+        ]);
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let flags: Vec<bool> = (0..zip.len())
+            .map(|i| zip.by_index(i).unwrap().is_apple_double())
+            .collect();
+        assert_eq!(flags, vec![false, true, true, false]);
+    }
+
+    #[test]
+    fn zip_read_streaming() {
+        use super::read_zipfile_from_stream;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        let mut reader = io::Cursor::new(v);
+        while read_zipfile_from_stream(&mut reader).unwrap().is_some() {}
+    }
+
+    #[test]
+    fn zip_stream_reader() {
+        use super::ZipStreamReader;
+        use std::io::{self, Read};
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        let mut reader = ZipStreamReader::new(io::Cursor::new(v));
+        assert!(!reader.finished());
+        {
+            let mut file = reader.next_entry().unwrap().unwrap();
+            assert_eq!(file.name(), "mimetype");
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).unwrap();
+        }
+        assert_eq!(reader.entries_read(), 1);
+        assert!(!reader.finished());
+
+        assert!(reader.next_entry().unwrap().is_none());
+        assert!(reader.finished());
+        assert!(reader.next_entry().unwrap().is_none());
+        assert_eq!(reader.entries_read(), 1);
+    }
+
+    #[test]
+    fn zip_stream_reader_data_descriptor() {
+        use super::{read_zipfile_from_stream, ZipArchive, ZipStreamReader};
+        use std::io::{self, Read};
+
+        let fixtures: [&[u8]; 2] = [
+            include_bytes!("../tests/data/data_descriptor.zip"),
+            include_bytes!("../tests/data/data_descriptor_no_signature.zip"),
+        ];
+        for fixture in fixtures.iter() {
+            let mut reader = ZipStreamReader::new(io::Cursor::new(*fixture));
+            let mut contents = String::new();
+            let mut file = reader.next_entry().unwrap().unwrap();
+            assert!(!file.crc32_known());
+            file.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, "data followed by a descriptor\n");
+            drop(file);
+            let mut file = reader.next_entry().unwrap().unwrap();
+            assert_eq!(file.name(), "second.txt");
+            assert!(file.crc32_known());
+            contents.clear();
+            file.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, "plain entry\n");
+            drop(file);
+            assert!(reader.next_entry().unwrap().is_none());
+            assert_eq!(reader.entries_read(), 2);
+
+            let mut stream = io::Cursor::new(*fixture);
+            assert!(read_zipfile_from_stream(&mut stream).is_err());
+
+            let mut zip = ZipArchive::new(io::Cursor::new(*fixture)).unwrap();
+            assert!(zip.by_index(0).unwrap().crc32_known());
+        }
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn zip_stream_reader_streaming_writer() {
+        use super::ZipStreamReader;
+        use crate::write::{FileOptions, ZipWriter};
+        use crate::CompressionMethod;
+        use std::io::{self, Read, Write};
+
+        let large: Vec<u8> = (0..100_000u64).map(|i| (i * i % 251) as u8).collect();
+        let mut writer = ZipWriter::new_streaming(Vec::new());
+        writer.start_file("large", FileOptions::default()).unwrap();
+        writer.write_all(&large).unwrap();
+        writer
+            .start_file("skipped", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"never read").unwrap();
+        writer.add_directory("dir", FileOptions::default()).unwrap();
+        writer.start_file("empty", FileOptions::default()).unwrap();
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file("stored", stored).unwrap();
+        writer.write_all(b"stored contents").unwrap();
+        let result = writer.finish().unwrap().into_inner();
+
+        let mut reader = ZipStreamReader::new(io::Cursor::new(&result[..]));
+        let mut contents = Vec::new();
+        reader
+            .next_entry()
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert!(contents == large);
+        assert_eq!(reader.next_entry().unwrap().unwrap().name(), "skipped");
+        assert!(reader.next_entry().unwrap().unwrap().is_dir());
+        for (name, expected) in &[("empty", &b""[..]), ("stored", b"stored contents")] {
+            let mut file = reader.next_entry().unwrap().unwrap();
+            assert_eq!(file.name(), *name);
+            contents.clear();
+            file.read_to_end(&mut contents).unwrap();
+            assert_eq!(contents, *expected);
+        }
+        assert!(reader.next_entry().unwrap().is_none());
+        assert_eq!(reader.entries_read(), 5);
+
+        // The checksum in the descriptor is compared with the contents
+        let mut corrupted = result.clone();
+        let descriptor = corrupted
+            .windows(4)
+            .position(|window| window == b"PK\x07\x08")
+            .unwrap();
+        corrupted[descriptor + 4] ^= 1;
+        let mut reader = ZipStreamReader::new(io::Cursor::new(corrupted));
+        reader.next_entry().unwrap().unwrap();
+        assert!(reader.next_entry().is_err());
+    }
+
+    #[test]
+    fn zip_stream_reader_empty_data_descriptor() {
+        use super::ZipStreamReader;
+        use std::io::{self, Read};
+
+        let local_header = |name: &[u8]| {
+            let mut header = b"PK\x03\x04\x14\x00\x08\x00\x00\x00".to_vec();
+            header.extend_from_slice(&[0; 16]);
+            header.extend_from_slice(&[name.len() as u8, 0, 0, 0]);
+            header.extend_from_slice(name);
+            header
+        };
+        let descriptors: [&[u8]; 2] = [b"PK\x07\x08", b""];
+        for descriptor in descriptors.iter() {
+            let mut stream = local_header(b"empty");
+            stream.extend_from_slice(descriptor);
+            stream.extend_from_slice(&[0; 12]);
+            stream.extend_from_slice(&local_header(b"data"));
+            stream.extend_from_slice(b"contents");
+
+            // Stored data of unknown length can not be told apart from what follows it
+            let mut reader = ZipStreamReader::new(io::Cursor::new(stream));
+            let mut file = reader.next_entry().unwrap().unwrap();
+            assert_eq!(file.name(), "empty");
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents).unwrap();
+            assert!(contents.is_empty());
+            drop(file);
+            assert!(reader.next_entry().is_err());
+        }
+    }
+
+    #[test]
+    fn archive_size() {
+        use super::ZipArchive;
+        use std::io;
+
+        let v = include_bytes!("../tests/data/mimetype.zip").to_vec();
+        let len = v.len() as u64;
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert_eq!(zip.archive_size().unwrap(), len);
+    }
+
+    #[test]
+    fn into_reader() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::write::FileOptions;
+        use std::io::{self, BufRead, Read};
+
+        let v = include_bytes!("../tests/data/mimetype.zip").to_vec();
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let reader = io::BufReader::new(zip.by_index(0).unwrap().into_reader());
+        let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["application/vnd.oasis.opendocument.text"]);
+
+        /// An adapter that turns its input into upper case
+        struct Upper<R>(R);
+
+        impl<R: Read> Read for Upper<R> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let count = self.0.read(buf)?;
+                buf[..count].make_ascii_uppercase();
+                Ok(count)
+            }
+        }
+
+        // The checksum is still validated underneath the adapter
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let mut buf = archive_with(options, &[("file", b"checked contents")]);
+        corrupt(&mut buf, b"checked contents");
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let mut contents = String::new();
+        let mut reader = Upper(zip.by_index(0).unwrap().into_reader());
+        let err = reader.read_to_string(&mut contents).unwrap_err();
+        assert!(crate::crc32::is_checksum_error(&err));
+    }
+
+    #[test]
+    fn indices_by_offset() {
+        use super::ZipArchive;
+        use std::io;
+
+        let v = include_bytes!("../tests/data/files_and_dirs.zip").to_vec();
+        let zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let indices = zip.indices_by_offset();
+        assert_eq!(indices.len(), zip.len());
+        assert!(indices
+            .windows(2)
+            .all(|w| zip.files[w[0]].header_start < zip.files[w[1]].header_start));
+    }
+
+    #[test]
+    fn is_stored() {
+        use super::ZipArchive;
+        use std::io;
+
+        let v = include_bytes!("../tests/data/mimetype.zip").to_vec();
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert!(zip.by_index(0).unwrap().is_stored());
+    }
+
+    #[test]
+    fn local_crc_mismatch() {
+        use super::{ZipArchive, ZipArchiveOptions};
+        use crate::result::ZipError;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        // Corrupt the CRC32 in the local header
+        v[14] ^= 0xFF;
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        assert!(zip.by_index(0).is_ok());
+
+        let options = ZipArchiveOptions::default().strict(true);
+        let mut zip = ZipArchive::with_options(io::Cursor::new(v), options).unwrap();
+        match zip.by_index(0) {
+            Err(ZipError::InvalidArchive("Local/central CRC mismatch")) => (),
+            _ => panic!("expected a CRC mismatch"),
+        };
+    }
+
+    #[test]
+    fn subset() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for i in 0..6 {
+            let options = match i % 2 {
+                0 => FileOptions::default(),
+                _ => FileOptions::default().compression_method(CompressionMethod::Stored),
+            };
+            writer.start_file(format!("{}.txt", i), options).unwrap();
+            for _ in 0..100 {
+                writeln!(writer, "contents of file {}", i).unwrap();
+            }
+        }
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let keep = ["1.txt", "2.txt", "5.txt"];
+        let subset = zip.subset(|name| keep.contains(&name)).unwrap();
+
+        let mut subset = ZipArchive::new(io::Cursor::new(subset)).unwrap();
+        assert_eq!(subset.len(), keep.len());
+        for name in keep.iter() {
+            assert_eq!(
+                subset.read_file(name).unwrap(),
+                zip.read_file(name).unwrap()
+            );
+            let original = zip.by_name(name).unwrap();
+            let copy = subset.by_name(name).unwrap();
+            assert_eq!(copy.compression(), original.compression());
+            assert_eq!(copy.compressed_size(), original.compressed_size());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn repack_into() {
+        use super::ZipArchive;
+        use crate::write::ZipWriter;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/repack.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        zip.repack_into(&mut writer, |name| name.starts_with("keep/"))
+            .unwrap();
+        let mut repacked = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(repacked.len(), 2);
+        for name in &["keep/readme.txt", "keep/run.sh"] {
+            assert_eq!(
+                repacked.read_file(name).unwrap(),
+                zip.read_file(name).unwrap()
+            );
+            let original = zip.by_name(name).unwrap();
+            let copy = repacked.by_name(name).unwrap();
+            assert_eq!(copy.compression(), original.compression());
+            assert_eq!(copy.comment(), original.comment());
+            assert_eq!(copy.unix_mode(), original.unix_mode());
+            assert_eq!(
+                copy.last_modified().datepart(),
+                original.last_modified().datepart()
+            );
+            assert_eq!(
+                copy.last_modified().timepart(),
+                original.last_modified().timepart()
+            );
+        }
+        assert_eq!(
+            repacked.by_name("keep/run.sh").unwrap().comment(),
+            "the script"
+        );
+        assert_eq!(
+            repacked.by_name("keep/run.sh").unwrap().unix_mode(),
+            Some(0o100755)
+        );
+    }
+
+    #[test]
+    fn read_file_to_string() {
+        use super::ZipArchive;
+        use std::io;
+
+        let buf = archive(&[
+            ("text", "key = \"välue\"\n".as_bytes()),
+            ("binary", &[0xff, 0xfe, 0x00]),
+        ]);
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        assert_eq!(
+            zip.read_file_to_string("text").unwrap(),
+            "key = \"välue\"\n"
+        );
+        assert!(zip.read_file_to_string("binary").is_err());
+    }
+
+    #[test]
+    fn index_locations() {
+        use super::ZipArchive;
+        use std::io::{self, Read};
+
+        let buf = archive(&[
+            ("0.txt", b"contents of file 0"),
+            ("1.txt", b"contents of file 1"),
+            ("2.txt", b"contents of file 2"),
+        ]);
+
+        let locations = ZipArchive::new(io::Cursor::new(buf.clone()))
+            .unwrap()
+            .index()
+            .unwrap();
+        assert_eq!(locations.len(), 3);
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        for (i, location) in locations.iter().enumerate().rev() {
+            let mut contents = String::new();
+            zip.open_from_location(location)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            assert_eq!(contents, format!("contents of file {}", i));
+        }
+
+        let mut wrong = locations[0].clone();
+        wrong.crc32 ^= 1;
+        assert!(zip.open_from_location(&wrong).is_err());
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn bzip2_multistream() {
+        use super::ZipArchive;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/bzip2_multistream.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let contents = zip.read_file("multistream.txt").unwrap();
+        assert_eq!(
+            contents,
+            [
+                "first stream\n".repeat(20).as_bytes(),
+                "second stream\n".repeat(20).as_bytes(),
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn dos_attributes() {
+        use super::ZipArchive;
+        use crate::types::System;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        zip.files[0].system = System::Dos;
+        zip.files[0].external_attributes = 0x21; // archive, read-only
+        assert_eq!(zip.by_index(0).unwrap().dos_attributes(), Some(0x21));
+
+        zip.files[0].system = System::Unix;
+        assert_eq!(zip.by_index(0).unwrap().dos_attributes(), None);
+        assert_eq!(zip.by_index(0).unwrap().external_attributes(), 0x21);
+    }
+
+    #[test]
+    fn zip_clone() {
+        use super::ZipArchive;
+        use std::io::{self, Read};
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        let mut reader1 = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let mut reader2 = reader1.clone();
+
+        let mut file1 = reader1.by_index(0).unwrap();
+        let mut file2 = reader2.by_index(0).unwrap();
+
+        let t = file1.last_modified();
+        assert_eq!(
+            (
+                t.year(),
+                t.month(),
+                t.day(),
+                t.hour(),
+                t.minute(),
+                t.second()
+            ),
+            (1980, 1, 1, 0, 0, 0)
+        );
+
+        let mut buf1 = [0; 5];
+        let mut buf2 = [0; 5];
+        let mut buf3 = [0; 5];
+        let mut buf4 = [0; 5];
+
+        file1.read_exact(&mut buf1).unwrap();
+        file2.read_exact(&mut buf2).unwrap();
+        file1.read_exact(&mut buf3).unwrap();
+        file2.read_exact(&mut buf4).unwrap();
+
+        assert_eq!(buf1, buf2);
+        assert_eq!(buf3, buf4);
+        assert!(buf1 != buf3);
+    }
+
+    #[test]
+    fn file_and_dir_predicates() {
+        use super::ZipArchive;
+        use std::io;
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/files_and_dirs.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+
+        for i in 0..zip.len() {
+            let zip_file = zip.by_index(i).unwrap();
+            let full_name = zip_file.sanitized_name();
+            let file_name = full_name.file_name().unwrap().to_str().unwrap();
+            assert!(
+                (file_name.starts_with("dir") && zip_file.is_dir())
+                    || (file_name.starts_with("file") && zip_file.is_file())
+            );
+        }
+    }
+
+    #[test]
+    fn by_index_no_crc() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::write::FileOptions;
+        use std::io::{self, Read};
+
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let mut buf = archive_with(options, &[("corrupt", b"corrupt contents")]);
+
+        corrupt(&mut buf, b"corrupt contents");
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let mut contents = Vec::new();
+        assert!(zip.by_index(0).unwrap().read_to_end(&mut contents).is_err());
+
+        contents.clear();
+        zip.by_index_no_crc(0)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, b"Corrupt contents");
+    }
+
+    #[test]
+    fn read_exact_into() {
+        use super::ZipArchive;
+        use crate::result::ZipError;
+        use std::io;
+
+        let buf = archive(&[("data", &[3; 5000])]);
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let mut arena = vec![0; 6000];
+        zip.by_name("data")
+            .unwrap()
+            .read_exact_into(&mut arena[..5000])
+            .unwrap();
+        assert!(arena[..5000].iter().all(|&b| b == 3));
+        assert!(arena[5000..].iter().all(|&b| b == 0));
+
+        for len in &[6000, 4999] {
+            let result = zip
+                .by_name("data")
+                .unwrap()
+                .read_exact_into(&mut arena[..*len]);
+            match result {
+                Err(ZipError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+                _ => panic!("expected an error for a buffer of the wrong size"),
+            }
+        }
+    }
+
+    #[test]
+    fn entry_warnings() {
+        use super::{EntryWarning, ZipArchive};
+        use std::io;
+
+        let mut buf = archive(&[("old", b"old"), ("new", b"new")]);
+
+        // Declare in the central header of "new" that version 6.3 is needed to extract it
+        let central = buf
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| w == b"PK\x01\x02")
+            .nth(1)
+            .unwrap()
+            .0;
+        buf[central + 6] = 63;
+
+        // The host system that some tools store in the upper byte is ignored
+        let central = buf.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        buf[central + 7] = 3;
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        assert!(zip.entry_warnings(1).is_empty());
+        zip.by_index(0).unwrap();
+        zip.by_index(1).unwrap();
+        assert!(zip.entry_warnings(0).is_empty());
+        assert_eq!(
+            zip.entry_warnings(1),
+            &[EntryWarning::VersionNeeded {
+                needed: 63,
+                supported: 20,
+            }]
+        );
+
+        // Versions that match the features of a file are not reported, also when this crate was
+        // built without support for them
+        let fixture = include_bytes!("../tests/data/aes_archive.zip");
+        let mut zip = ZipArchive::new(io::Cursor::new(&fixture[..])).unwrap();
+        zip.by_index_raw(0).unwrap();
+        assert!(zip.entry_warnings(0).is_empty());
+
+        let options = crate::write::FileOptions::default().large_file(true);
+        let mut zip =
+            ZipArchive::new(io::Cursor::new(archive_with(options, &[("big", b"")]))).unwrap();
+        zip.by_index(0).unwrap();
+        assert!(zip.entry_warnings(0).is_empty());
+    }
+
+    #[test]
+    fn into_entries() {
+        use super::ZipArchive;
+        use std::io;
+
+        let buf = archive(&[("dir/", b""), ("dir/b", b"second"), ("a", b"first")]);
+
+        let zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let entries = zip.into_entries().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ("dir/b".to_string(), b"second".to_vec()),
+                ("a".to_string(), b"first".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn looks_like_zip() {
+        use super::ZipArchive;
+        use crate::write::FileOptions;
+        use std::io::{self, Read};
+
+        let options = FileOptions::default();
+        let inner = archive_with(options.clone(), &[("inner.txt", b"nested")]);
+        let empty = archive_with(options.clone(), &[]);
+
+        let buf = archive_with(
+            options,
+            &[
+                ("inner.zip", &inner),
+                ("empty.zip", &empty),
+                ("text", b"PK, but no zip"),
+                ("short", b"PK"),
+            ],
+        );
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let mut file = zip.by_name("inner.zip").unwrap();
+        assert!(file.looks_like_zip().unwrap());
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, inner);
+        drop(file);
+        let mut nested = ZipArchive::new(io::Cursor::new(contents)).unwrap();
+        assert_eq!(nested.read_file("inner.txt").unwrap(), b"nested");
+
+        assert!(zip.by_name("empty.zip").unwrap().looks_like_zip().unwrap());
+        assert!(!zip.by_name("text").unwrap().looks_like_zip().unwrap());
+        let mut file = zip.by_name("short").unwrap();
+        assert!(!file.looks_like_zip().unwrap());
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"PK");
+    }
+
+    #[test]
+    fn local_header() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::write::FileOptions;
+        use crate::DateTime;
+        use std::io;
+
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .last_modified_time(DateTime::from_date_and_time(2020, 5, 6, 7, 8, 10).unwrap());
+        let buf = archive_with(
+            options,
+            &[("first", b"contents"), ("second", b"more contents")],
+        );
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let header = zip.local_header(1).unwrap();
+        let file = zip.by_index(1).unwrap();
+        assert_eq!(header.version_needed, 20);
+        assert_eq!(header.flags, 0);
+        assert_eq!(header.compression_method, CompressionMethod::Stored);
+        assert_eq!(header.last_modified_time.year(), 2020);
+        assert_eq!(header.last_modified_time.second(), 10);
+        assert_eq!(header.crc32, file.crc32());
+        assert_eq!(header.compressed_size, 13);
+        assert_eq!(header.uncompressed_size, 13);
+        assert_eq!(header.file_name_raw, b"second");
+        assert_eq!(&header.extra_field[..2], b"UT");
+        assert_eq!(header.data_start, file.data_start());
+        drop(file);
+
+        assert!(zip.local_header(2).is_err());
+    }
+
+    #[test]
+    fn more_than_65535_files() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::result::ZipError;
+        use crate::write::FileOptions;
+        use std::io;
+
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let names: Vec<String> = (0..65536).map(|i| i.to_string()).collect();
+        let entries: Vec<(&str, &[u8])> = names.iter().map(|name| (&name[..], &b""[..])).collect();
+        let buf = archive_with(options, &entries);
+        let zip = ZipArchive::new(io::Cursor::new(buf.clone())).unwrap();
+        assert_eq!(zip.len(), 65536);
+
+        // Drop the ZIP64 central directory end and its locator, which precede the standard end
+        // record and its comment
+        let end = buf.len() - 22 - b"zip-rs".len();
+        let mut truncated = buf[..end - 56 - 20].to_vec();
+        truncated.extend_from_slice(&buf[end..]);
+        match ZipArchive::new(io::Cursor::new(truncated)) {
+            Err(ZipError::InvalidArchive(msg)) => assert!(msg.contains("ZIP64")),
+            _ => panic!("expected a missing ZIP64 central directory end to be an error"),
+        }
+    }
+
+    #[test]
+    fn buf_reader() {
+        use super::ZipArchive;
+        use std::io::{self, BufRead};
+
+        let buf = archive(&[("log", b"first\nsecond\r\nthird")]);
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let mut file = zip.by_name("log").unwrap();
+        let lines = file
+            .buf_reader()
+            .lines()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(lines, vec!["first", "second", "third"]);
+    }
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn zip64_sizes_in_local_header_only() {
+        use super::ZipArchive;
+        use std::io::{self, Read};
+
+        // The central directory has a 32-bit uncompressed size and the marker for the compressed
+        // size, without a ZIP64 field. The local ZIP64 field holds both sizes.
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/zip64_local_only.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let mut file = zip.by_index(0).unwrap();
+        assert_eq!(file.size(), 740);
+        assert_eq!(file.compressed_size(), 47);
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(
+            contents,
+            "zip64 sizes in the local header only\n".repeat(20)
+        );
+    }
+
+    #[test]
+    fn slack_after() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::write::FileOptions;
+        use std::io;
+
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let buf = archive_with(
+            options,
+            &[("first", b"first contents"), ("second", b"second contents")],
+        );
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf.clone())).unwrap();
+        assert!(zip.slack_after(0).unwrap().is_empty());
+        assert!(zip.slack_after(1).unwrap().is_empty());
+        assert!(zip.slack_after(2).is_err());
+
+        // Hide data in front of the second local header, and shift the offsets that follow it
+        let hidden = b"hidden";
+        let second = buf
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| w == b"PK\x03\x04")
+            .nth(1)
+            .unwrap()
+            .0;
+        let mut tampered = buf[..second].to_vec();
+        tampered.extend_from_slice(hidden);
+        tampered.extend_from_slice(&buf[second..]);
+        let shift = |bytes: &mut [u8]| {
+            let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            bytes.copy_from_slice(&(value + hidden.len() as u32).to_le_bytes());
+        };
+        let central = tampered
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| w == b"PK\x01\x02")
+            .nth(1)
+            .unwrap()
+            .0;
+        shift(&mut tampered[central + 42..central + 46]);
+        let end = tampered.len() - 22 - b"zip-rs".len();
+        shift(&mut tampered[end + 16..end + 20]);
+        let mut zip = ZipArchive::new(io::Cursor::new(tampered)).unwrap();
+        assert_eq!(zip.slack_after(0).unwrap(), hidden);
+        assert!(zip.slack_after(1).unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "encoding-detection")]
+    fn auto_detect_encoding() {
+        use super::{ZipArchive, ZipArchiveOptions};
+        use std::io;
+
+        let options = ZipArchiveOptions::default().auto_detect_encoding(true);
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/shift_jis_names.zip"));
+        let mut zip = ZipArchive::with_options(io::Cursor::new(v.clone()), options).unwrap();
+        assert_eq!(zip.by_index(0).unwrap().name(), "日本語のファイル.txt");
+        assert_eq!(zip.by_index(1).unwrap().name(), "readme.txt");
+        assert_eq!(
+            zip.read_file("日本語のファイル.txt").unwrap(),
+            b"shift-jis\n"
+        );
+
+        // Without detection, the names are decoded as cp437
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        assert_eq!(zip.by_index(0).unwrap().name(), "ô·û{îΩé╠âtâ@âCâï.txt");
+
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/cp866_names.zip"));
+        let mut zip = ZipArchive::with_options(io::Cursor::new(v), options).unwrap();
+        assert_eq!(zip.by_index(0).unwrap().name(), "Документы/отчёт.txt");
+    }
+
+    #[test]
+    #[cfg(feature = "digest")]
+    fn copy_to_hashed() {
+        use super::ZipArchive;
+        use sha2::{Digest, Sha256};
+        use std::io;
+
+        let contents = b"content-addressed contents".repeat(1000);
+        let buf = archive(&[("data", &contents)]);
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let mut hasher = Sha256::new();
+        let mut copy = Vec::new();
+        let (count, hash) = zip
+            .by_name("data")
+            .unwrap()
+            .copy_to_hashed(&mut copy, &mut hasher)
+            .unwrap();
+        assert_eq!(count, contents.len() as u64);
+        assert_eq!(copy, contents);
+        assert_eq!(hash, Sha256::digest(&contents));
+    }
+
+    #[test]
+    fn new_multipart() {
+        use super::{ReadSeek, ZipArchive};
+        use std::io;
+
+        let first: &[u8] = include_bytes!("../tests/data/multipart.z01");
+        let last: &[u8] = include_bytes!("../tests/data/multipart.zip");
+        let sizes = vec![first.len() as u64, last.len() as u64];
+        let parts: Vec<Box<dyn ReadSeek>> = vec![
+            Box::new(io::Cursor::new(first)),
+            Box::new(io::Cursor::new(last)),
+        ];
+        let mut zip = ZipArchive::new_multipart(parts, sizes).unwrap();
+        assert_eq!(zip.len(), 3);
+
+        // "first.txt" starts on the first part, "second.txt" spans both parts, and "third.txt"
+        // starts on the last part
+        let first = zip.read_file("first.txt").unwrap();
+        assert_eq!(first.len(), 50820);
+        assert!(first.starts_with(b"part 0\n"));
+        assert_eq!(
+            zip.read_file("second.txt").unwrap(),
+            b"the second file\n".repeat(3000)
+        );
+        assert_eq!(zip.read_file("third.txt").unwrap(), b"the third file\n");
+
+        let parts: Vec<Box<dyn ReadSeek>> = vec![Box::new(io::Cursor::new(last))];
+        assert!(ZipArchive::new_multipart(parts, vec![last.len() as u64]).is_err());
+    }
+
+    #[test]
+    fn read_all() {
+        use super::ZipArchive;
+        use std::collections::HashMap;
+        use std::io;
+
+        let buf = archive(&[
+            ("icons/", b""),
+            ("icons/app.svg", b"<svg/>"),
+            ("config.toml", b"name = \"app\""),
+        ]);
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let mut expected = HashMap::new();
+        expected.insert("icons/app.svg".to_string(), b"<svg/>".to_vec());
+        expected.insert("config.toml".to_string(), b"name = \"app\"".to_vec());
+        assert_eq!(zip.read_all().unwrap(), expected);
+    }
+
+    #[cfg(feature = "reduce")]
+    #[test]
+    fn reduced() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use std::io::{self, Read};
+
+        let mut expected = vec![0u8; 16];
+        for i in 0..100 {
+            expected.extend_from_slice(format!("line {} of the reduced file\n", i).as_bytes());
+            if i % 10 == 0 {
+                expected.push(0x90);
+            }
+        }
+        expected.extend_from_slice(&[b'='; 300]);
+
+        let archives: [&[u8]; 4] = [
+            include_bytes!("../tests/data/reduce_1.zip"),
+            include_bytes!("../tests/data/reduce_2.zip"),
+            include_bytes!("../tests/data/reduce_3.zip"),
+            include_bytes!("../tests/data/reduce_4.zip"),
+        ];
+        for (factor, archive) in (1..=4).zip(archives.iter()) {
+            let mut zip = ZipArchive::new(io::Cursor::new(*archive)).unwrap();
+            let mut file = zip.by_index(0).unwrap();
+            assert_eq!(file.compression(), CompressionMethod::Reduced(factor));
+            assert_eq!(file.crc32(), 0x5299809d);
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents).unwrap();
+            assert_eq!(contents, expected);
+        }
+    }
+
+    #[test]
+    fn can_read() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::result::ZipError;
+        use crate::spec;
+        use std::io;
+
+        let buf = archive(&[
+            ("plain", b"contents"),
+            ("encrypted", b"contents"),
+            ("jpeg", b"contents"),
+            ("sentinel", b"contents"),
+        ]);
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        zip.files[1].encrypted = true;
+        zip.files[2].compression_method = CompressionMethod::Jpeg;
+        zip.files[3].uncompressed_size = spec::ZIP64_BYTES_THR;
+
+        assert!(zip.can_read(0).is_ok());
+        match zip.can_read(1) {
+            Err(ZipError::UnsupportedArchive("Encrypted files are not supported")) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match zip.can_read(2) {
+            Err(ZipError::UnsupportedArchive("Compression method not supported")) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match zip.can_read(3) {
+            Err(ZipError::InvalidArchive(_)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match zip.can_read(4) {
+            Err(ZipError::FileNotFound) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        zip.by_index(0).unwrap();
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn lenient_deflate() {
+        use super::{ZipArchive, ZipArchiveOptions};
+        use std::io::{self, Read};
+
+        // The deflate stream ends with a sync flush instead of a block marked as final
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/deflate_no_final_block.zip"));
+        let mut expected = String::new();
+        for i in 0..200 {
+            expected.push_str(&format!("entry {} without a final deflate block\n", i));
+        }
+
+        let mut zip = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        let mut contents = String::new();
+        assert!(zip
+            .by_index(0)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .is_err());
+
+        let options = ZipArchiveOptions::default().lenient_deflate(true);
+        let mut zip = ZipArchive::with_options(io::Cursor::new(v), options).unwrap();
+        let mut contents = String::new();
+        zip.by_index(0)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, expected);
+
+        // The contents are still checked against the CRC32
+        zip.files[0].crc32 ^= 1;
+        let mut contents = String::new();
+        let result = zip.by_index(0).unwrap().read_to_string(&mut contents);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn extract_with() {
+        use super::ZipArchive;
+        use crate::result::ZipError;
+        use std::fs;
+        use std::io;
+        use std::path::{Path, PathBuf};
+
+        let buf = archive(&[
+            ("project/", b""),
+            ("project/README", b"readme"),
+            ("project/src/main.rs", b"fn main() {}"),
+            ("unrelated", b"unrelated"),
+        ]);
+
+        // Strip the top-level folder, and skip everything outside of it
+        let strip = |file: &super::ZipFile| {
+            let path = file.enclosed_name()?.strip_prefix("project").ok()?;
+            Some(path.to_path_buf())
+        };
+        let directory =
+            std::env::temp_dir().join(format!("zip-rs-extract-with-{}", std::process::id()));
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        zip.extract_with(&directory, strip).unwrap();
+        assert_eq!(fs::read(directory.join("README")).unwrap(), b"readme");
+        assert_eq!(
+            fs::read(directory.join("src").join("main.rs")).unwrap(),
+            b"fn main() {}"
+        );
+        assert!(!directory.join("unrelated").exists());
+        assert!(!directory.join("project").exists());
+
+        let escape = |_: &super::ZipFile| Some(Path::new("..").join("escaped"));
+        match zip.extract_with(&directory, escape) {
+            Err(ZipError::InvalidArchive("Invalid file path")) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        let absolute = |_: &super::ZipFile| Some(PathBuf::from("/absolute"));
+        assert!(zip.extract_with(&directory, absolute).is_err());
+        assert!(!directory.join("..").join("escaped").exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn recover_truncated_central_directory() {
+        use super::{ParseWarning, ZipArchive, ZipArchiveOptions};
+        use crate::result::ZipError;
+        use std::io::{self, Read};
+
+        // The end of central directory record declares three files, but the directory ends in
+        // the middle of the name of the third one
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/truncated_directory.zip"));
+        match ZipArchive::new(io::Cursor::new(v.clone())) {
+            Err(ZipError::Io(_)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("truncated archive should not open"),
+        }
+
+        let options = ZipArchiveOptions::default().recover(true);
+        let mut zip = ZipArchive::with_options(io::Cursor::new(v), options).unwrap();
+        assert_eq!(zip.len(), 2);
+        assert_eq!(
+            zip.parse_warnings(),
+            &[ParseWarning::MissingEntries {
+                declared: 3,
+                parsed: 2
+            }]
+        );
+        let mut contents = String::new();
+        zip.by_name("second.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "second file\n");
+    }
+
+    #[test]
+    fn content_fingerprint() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let options = FileOptions::default();
+        let stored = options
+            .clone()
+            .compression_method(CompressionMethod::Stored);
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.start_file("stored", stored.clone()).unwrap();
+        writer.write_all(b"shared contents").unwrap();
+        writer.start_file("compressed", options).unwrap();
+        writer.write_all(b"shared contents").unwrap();
+        writer.start_file("other", stored).unwrap();
+        writer.write_all(b"other contents").unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let fingerprints: Vec<(u64, u32)> = (0..zip.len())
+            .map(|i| zip.by_index(i).unwrap().content_fingerprint())
+            .collect();
+        assert_eq!(fingerprints[0], (15, 0xcec4ec50));
+        assert_eq!(fingerprints[0], fingerprints[1]);
+        assert_ne!(fingerprints[0], fingerprints[2]);
+    }
+
+    #[test]
+    fn max_entries() {
+        use super::{ZipArchive, ZipArchiveOptions};
+        use crate::result::ZipError;
+        use std::io;
+
+        let names: Vec<String> = (0..10).map(|i| i.to_string()).collect();
+        let entries: Vec<(&str, &[u8])> = names.iter().map(|name| (&name[..], &b""[..])).collect();
+        let buf = archive(&entries);
+
+        let options = ZipArchiveOptions::default().max_entries(10);
+        let zip = ZipArchive::with_options(io::Cursor::new(buf.clone()), options).unwrap();
+        assert_eq!(zip.len(), 10);
+
+        let options = ZipArchiveOptions::default().max_entries(9);
+        match ZipArchive::with_options(io::Cursor::new(buf), options) {
+            Err(ZipError::InvalidArchive("Too many entries")) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("archive with too many entries should not open"),
+        }
+    }
+
+    #[test]
+    fn read_file_verified() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::result::ZipError;
+        use crate::write::FileOptions;
+        use std::io;
+
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let mut buf = archive_with(options, &[("release.tar", b"release contents")]);
+
+        let crc32 = crc32fast::hash(b"release contents");
+        let mut zip = ZipArchive::new(io::Cursor::new(buf.clone())).unwrap();
+        assert_eq!(
+            zip.read_file_verified("release.tar", crc32).unwrap(),
+            b"release contents"
+        );
+        match zip.read_file_verified("release.tar", crc32 ^ 1) {
+            Err(ZipError::InvalidArchive("CRC mismatch")) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // Damage the data, so it no longer matches the CRC32 in the headers
+        corrupt(&mut buf, b"release contents");
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        match zip.read_file_verified("release.tar", crc32) {
+            Err(ZipError::InvalidArchive("CRC mismatch")) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
     }
-}
 
-impl<'a> Drop for ZipFile<'a> {
-    fn drop(&mut self) {
-        // self.data is Owned, this reader is constructed by a streaming reader.
-        // In this case, we want to exhaust the reader so that the next file is accessible.
-        if let Cow::Owned(_) = self.data {
-            let mut buffer = [0; 1 << 16];
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn read_file_verified_sha256() {
+        use super::ZipArchive;
+        use crate::result::ZipError;
+        use sha2::{Digest, Sha256};
+        use std::io;
 
-            // Get the inner `Take` reader so all decompression and CRC calculation is skipped.
-            let innerreader = ::std::mem::replace(&mut self.reader, ZipFileReader::NoReader);
-            let mut reader = match innerreader {
-                ZipFileReader::NoReader => panic!("ZipFileReader was in an invalid state"),
-                ZipFileReader::Stored(crcreader) => crcreader.into_inner(),
-                #[cfg(feature = "deflate")]
-                ZipFileReader::Deflated(crcreader) => crcreader.into_inner().into_inner(),
-                #[cfg(feature = "bzip2")]
-                ZipFileReader::Bzip2(crcreader) => crcreader.into_inner().into_inner(),
-            };
+        let buf = archive(&[("release.tar", b"release contents")]);
 
-            loop {
-                match reader.read(&mut buffer) {
-                    Ok(0) => break,
-                    Ok(_) => (),
-                    Err(e) => panic!(
-                        "Could not consume all of the output of the current ZipFile: {:?}",
-                        e
-                    ),
-                }
-            }
+        let mut expected = [0u8; 32];
+        expected.copy_from_slice(&Sha256::digest(b"release contents"));
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        assert_eq!(
+            zip.read_file_verified_sha256("release.tar", &expected)
+                .unwrap(),
+            b"release contents"
+        );
+        expected[0] ^= 1;
+        match zip.read_file_verified_sha256("release.tar", &expected) {
+            Err(ZipError::InvalidArchive("SHA-256 mismatch")) => (),
+            result => panic!("unexpected result: {:?}", result),
         }
     }
-}
 
-/// Read ZipFile structures from a non-seekable reader.
-///
-/// This is an alternative method to read a zip file. If possible, use the ZipArchive functions
-/// as some information will be missing when reading this manner.
-///
-/// Reads a file header from the start of the stream. Will return `Ok(Some(..))` if a file is
-/// present at the start of the stream. Returns `Ok(None)` if the start of the central directory
-/// is encountered. No more files should be read after this.
-///
-/// The Drop implementation of ZipFile ensures that the reader will be correctly positioned after
-/// the structure is done.
-///
-/// Missing fields are:
-/// * `comment`: set to an empty string
-/// * `data_start`: set to 0
-/// * `external_attributes`: `unix_mode()`: will return None
-pub fn read_zipfile_from_stream<'a, R: io::Read>(
-    reader: &'a mut R,
-) -> ZipResult<Option<ZipFile<'_>>> {
-    let signature = reader.read_u32::<LittleEndian>()?;
+    #[test]
+    fn lazy_names() {
+        use super::{ZipArchive, ZipArchiveOptions};
+        use std::io;
 
-    match signature {
-        spec::LOCAL_FILE_HEADER_SIGNATURE => (),
-        spec::CENTRAL_DIRECTORY_HEADER_SIGNATURE => return Ok(None),
-        _ => return Err(ZipError::InvalidArchive("Invalid local file header")),
+        let buf = archive(&[("first", b"first contents"), ("sëcond", b"second contents")]);
+
+        let options = ZipArchiveOptions::default().lazy_names(true);
+        let mut zip = ZipArchive::with_options(io::Cursor::new(buf.clone()), options).unwrap();
+        let raw_names: Vec<&[u8]> = zip.raw_names().collect();
+        assert_eq!(raw_names, vec![&b"first"[..], "sëcond".as_bytes()]);
+        assert!(zip.files.iter().all(|file| file.file_name.is_empty()));
+
+        // Opening a file only decodes its own name
+        assert_eq!(zip.by_index(1).unwrap().name(), "sëcond");
+        assert_eq!(zip.files[0].file_name, "");
+
+        let eager = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        assert!(zip.diff(&eager).is_empty());
+        assert_eq!(zip.read_file_to_string("first").unwrap(), "first contents");
+        assert_eq!(zip.files[0].file_name, "first");
+
+        // Names that are not flagged as UTF-8 are decoded like when opening eagerly
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/shift_jis_names.zip"));
+        let eager = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        let mut lazy = ZipArchive::with_options(io::Cursor::new(v), options).unwrap();
+        let name = eager.files[0].file_name.clone();
+        assert_eq!(lazy.by_name(&name).unwrap().name(), name);
     }
 
-    let version_made_by = reader.read_u16::<LittleEndian>()?;
-    let flags = reader.read_u16::<LittleEndian>()?;
-    let encrypted = flags & 1 == 1;
-    let is_utf8 = flags & (1 << 11) != 0;
-    let using_data_descriptor = flags & (1 << 3) != 0;
-    let compression_method = CompressionMethod::from_u16(reader.read_u16::<LittleEndian>()?);
-    let last_mod_time = reader.read_u16::<LittleEndian>()?;
-    let last_mod_date = reader.read_u16::<LittleEndian>()?;
-    let crc32 = reader.read_u32::<LittleEndian>()?;
-    let compressed_size = reader.read_u32::<LittleEndian>()?;
-    let uncompressed_size = reader.read_u32::<LittleEndian>()?;
-    let file_name_length = reader.read_u16::<LittleEndian>()? as usize;
-    let extra_field_length = reader.read_u16::<LittleEndian>()? as usize;
+    #[test]
+    fn concatenated_archives() {
+        use super::ZipArchive;
+        use std::io::{self, Read};
 
-    let file_name_raw = ReadPodExt::read_exact(reader, file_name_length)?;
-    let extra_field = ReadPodExt::read_exact(reader, extra_field_length)?;
+        let mut v = archive(&[("first.txt", b"first archive")]);
+        let first_len = v.len() as u64;
+        v.extend_from_slice(&archive(&[("second.txt", b"second archive")]));
 
-    let file_name = match is_utf8 {
-        true => String::from_utf8_lossy(&*file_name_raw).into_owned(),
-        false => file_name_raw.clone().from_cp437(),
-    };
+        let mut zip = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        assert_eq!(zip.len(), 1);
+        assert_eq!(zip.offset(), first_len);
+        assert_eq!(zip.read_file("second.txt").unwrap(), b"second archive");
+        assert_eq!(zip.inner_archives().unwrap(), vec![0..first_len]);
 
-    let mut result = ZipFileData {
-        system: System::from_u8((version_made_by >> 8) as u8),
-        version_made_by: version_made_by as u8,
-        encrypted: encrypted,
-        compression_method: compression_method,
-        last_modified_time: DateTime::from_msdos(last_mod_date, last_mod_time),
-        crc32: crc32,
-        compressed_size: compressed_size as u64,
-        uncompressed_size: uncompressed_size as u64,
-        file_name: file_name,
-        file_name_raw: file_name_raw,
-        file_comment: String::new(), // file comment is only available in the central directory
-        // header_start and data start are not available, but also don't matter, since seeking is
-        // not available.
-        header_start: 0,
-        data_start: 0,
-        // The external_attributes field is only available in the central directory.
-        // We set this to zero, which should be valid as the docs state 'If input came
-        // from standard input, this field is set to zero.'
-        external_attributes: 0,
-    };
+        // Both archives have ZIP64 records, and the forward search from the recorded offset of
+        // the record of the outer archive finds the record of the inner archive
+        let mut v = Vec::new();
+        v.extend_from_slice(include_bytes!("../tests/data/concatenated_zip64.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v.clone())).unwrap();
+        assert_eq!(zip.len(), 1);
+        let mut contents = String::new();
+        zip.by_name("outer.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "the outer archive\n");
 
-    match parse_extra_field(&mut result, &extra_field) {
-        Ok(..) | Err(ZipError::Io(..)) => {}
-        Err(e) => Err(e)?,
+        let inner = zip.inner_archives().unwrap();
+        assert_eq!(inner, vec![0..552]);
+        let inner_bytes = v[inner[0].start as usize..inner[0].end as usize].to_vec();
+        let mut inner_zip = ZipArchive::new(io::Cursor::new(inner_bytes)).unwrap();
+        assert_eq!(
+            inner_zip.read_file("inner.txt").unwrap(),
+            b"the inner archive\n".repeat(20)
+        );
     }
 
-    if encrypted {
-        return unsupported_zip_error("Encrypted files are not supported");
-    }
-    if using_data_descriptor {
-        return unsupported_zip_error("The file length is not available in the local header");
+    #[test]
+    fn entry_extent() {
+        use super::ZipArchive;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
+
+        let buf = archive(&[("first", b"first contents")]);
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let extent = zip.entry_extent(0).unwrap();
+        assert_eq!(extent.start, 0);
+        assert_eq!(extent.end, zip.directory_start);
+        assert!(zip.entry_extent(1).is_err());
+
+        // Streamed files end with a data descriptor
+        let mut writer = ZipWriter::new_streaming(Vec::new());
+        writer.start_file("first", FileOptions::default()).unwrap();
+        writer.write_all(b"first contents").unwrap();
+        writer.start_file("second", FileOptions::default()).unwrap();
+        writer.write_all(b"second contents").unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let first = zip.entry_extent(0).unwrap();
+        let second = zip.entry_extent(1).unwrap();
+        assert_eq!(first.start, 0);
+        assert_eq!(first.end, second.start);
+        assert_eq!(second.end, zip.directory_start);
     }
 
-    let limit_reader = (reader as &'a mut dyn io::Read).take(result.compressed_size as u64);
+    #[test]
+    fn read_range() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::result::ZipError;
+        use crate::write::{FileOptions, ZipWriter};
+        use std::io::{self, Write};
 
-    let result_crc32 = result.crc32;
-    let result_compression_method = result.compression_method;
-    Ok(Some(ZipFile {
-        data: Cow::Owned(result),
-        reader: make_reader(result_compression_method, result_crc32, limit_reader)?,
-    }))
-}
+        let contents: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        let stored = options
+            .clone()
+            .compression_method(CompressionMethod::Stored);
+        writer.start_file("stored", stored).unwrap();
+        writer.write_all(&contents).unwrap();
+        #[cfg(feature = "deflate")]
+        {
+            writer.start_file("deflated", options).unwrap();
+            writer.write_all(&contents).unwrap();
+        }
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        for index in 0..zip.len() {
+            let mut range = |start, len| zip.read_range(index, start, len).unwrap();
+            assert_eq!(range(1000, 300), &contents[1000..1300]);
+            assert_eq!(range(4900, 300), &contents[4900..]);
+            assert!(range(6000, 10).is_empty());
+        }
+        assert!(zip.read_range(2, 0, 10).is_err());
+
+        // A stored file whose data does not fit in the archive is rejected rather than cut short
+        zip.files[0].compressed_size += 1 << 20;
+        match zip.read_range(0, 4900, 300) {
+            Err(ZipError::InvalidArchive("Entry data out of bounds")) => (),
+            _ => panic!("data beyond the end of the archive should be rejected"),
+        }
+    }
 
-#[cfg(test)]
-mod test {
     #[test]
-    fn invalid_offset() {
+    fn sniff() {
         use super::ZipArchive;
         use std::io;
 
         let mut v = Vec::new();
-        v.extend_from_slice(include_bytes!("../tests/data/invalid_offset.zip"));
-        let reader = ZipArchive::new(io::Cursor::new(v));
-        assert!(reader.is_err());
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let mimetype = zip.sniff("mimetype", 11).unwrap().unwrap();
+        assert_eq!(mimetype, b"application");
+        let mimetype = zip.sniff("mimetype", 1000).unwrap().unwrap();
+        assert_eq!(mimetype, b"application/vnd.oasis.opendocument.text");
+        assert_eq!(zip.sniff("META-INF/MANIFEST.MF", 1000).unwrap(), None);
     }
 
     #[test]
-    fn zip64_with_leading_junk() {
+    fn entry_extra() {
         use super::ZipArchive;
         use std::io;
 
+        let buf = archive(&[("file", b"contents")]);
+        let zip = ZipArchive::new(io::Cursor::new(buf.clone())).unwrap();
+        let known = zip.entry_extra(0).to_vec();
+
+        // Append an unknown vendor field to the extra field of the central directory header
+        let vendor = b"\xfe\xca\x06\x00vendor";
+        let central = buf.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        let extra_length = u16::from_le_bytes([buf[central + 30], buf[central + 31]]);
+        let extra_end = central + 46 + b"file".len() + extra_length as usize;
+        let mut tampered = buf[..extra_end].to_vec();
+        tampered.extend_from_slice(vendor);
+        tampered.extend_from_slice(&buf[extra_end..]);
+        let extra_length = extra_length + vendor.len() as u16;
+        tampered[central + 30..central + 32].copy_from_slice(&extra_length.to_le_bytes());
+        let end = tampered.len() - 22 - b"zip-rs".len();
+        let directory_size = buf.len() - central - 22 - b"zip-rs".len() + vendor.len();
+        tampered[end + 12..end + 16].copy_from_slice(&(directory_size as u32).to_le_bytes());
+
+        let zip = ZipArchive::new(io::Cursor::new(tampered)).unwrap();
+        let mut expected = known;
+        expected.extend_from_slice(vendor);
+        assert_eq!(zip.entry_extra(0), &expected[..]);
+        assert!(zip.entry_extra(1).is_empty());
+    }
+
+    #[test]
+    fn empty_entries() {
+        use super::{read_zipfile_from_stream, ZipArchive};
+        use crate::compression::CompressionMethod;
+        use crate::write::FileOptions;
+        use std::io::{self, Read};
+
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let buf = archive_with(
+            stored,
+            &[
+                ("empty", b""),
+                ("dir/", b""),
+                ("", b""),
+                ("last", b"contents"),
+            ],
+        );
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf.clone())).unwrap();
+        for index in 0..3 {
+            let mut file = zip.by_index(index).unwrap();
+            assert_eq!((file.size(), file.compressed_size()), (0, 0));
+            assert_eq!(file.crc32(), 0);
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents).unwrap();
+            assert!(contents.is_empty());
+            drop(file);
+            // The data of an empty file ends where the next local header starts
+            let extent = zip.entry_extent(index).unwrap();
+            assert_eq!(extent.end, zip.files[index + 1].header_start);
+        }
+
+        // Reading into an empty buffer is not mistaken for the end of a file
+        let mut file = zip.by_name("last").unwrap();
+        assert_eq!(file.read(&mut []).unwrap(), 0);
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"contents");
+        drop(file);
+
+        // Empty files that are dropped unread leave the stream at the next file
+        let mut reader = io::Cursor::new(buf);
+        let mut names = Vec::new();
+        while let Some(file) = read_zipfile_from_stream(&mut reader).unwrap() {
+            names.push(file.name().to_owned());
+        }
+        assert_eq!(names, ["empty", "dir/", "", "last"]);
+    }
+
+    #[test]
+    fn for_each_file() {
+        use super::ZipArchive;
+        use crate::result::ZipError;
+        use std::io::{self, Read};
+
         let mut v = Vec::new();
-        v.extend_from_slice(include_bytes!("../tests/data/zip64_demo.zip"));
-        let reader = ZipArchive::new(io::Cursor::new(v)).unwrap();
-        assert!(reader.len() == 1);
+        v.extend_from_slice(include_bytes!("../tests/data/files_and_dirs.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let mut names = Vec::new();
+        zip.for_each_file(|index, mut file| {
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents)?;
+            names.push((index, file.name().to_owned(), contents.len() as u64));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(names.len(), zip.len());
+        for (index, name, size) in names {
+            assert_eq!(zip.by_index(index).unwrap().name(), name);
+            assert_eq!(zip.by_index(index).unwrap().size(), size);
+        }
+
+        let mut calls = 0;
+        let result = zip.for_each_file(|_, _| {
+            calls += 1;
+            Err(ZipError::FileNotFound)
+        });
+        assert!(matches!(result, Err(ZipError::FileNotFound)));
+        assert_eq!(calls, 1);
     }
 
     #[test]
-    fn zip_comment() {
+    fn total_sizes() {
         use super::ZipArchive;
         use std::io;
 
         let mut v = Vec::new();
-        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
-        let reader = ZipArchive::new(io::Cursor::new(v)).unwrap();
-        assert!(reader.comment == b"zip-rs");
+        v.extend_from_slice(include_bytes!("../tests/data/files_and_dirs.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let (mut compressed, mut decompressed) = (0, 0);
+        for i in 0..zip.len() {
+            let file = zip.by_index_raw(i).unwrap();
+            compressed += file.compressed_size();
+            decompressed += file.size();
+        }
+        assert_eq!(zip.compressed_size(), compressed);
+        assert_eq!(zip.decompressed_size(), decompressed);
+
+        zip.files[0].uncompressed_size = u64::MAX;
+        assert_eq!(zip.decompressed_size(), u64::MAX);
     }
 
     #[test]
-    fn zip_read_streaming() {
-        use super::read_zipfile_from_stream;
+    fn data_out_of_bounds() {
+        use super::ZipArchive;
+        use crate::result::ZipError;
         use std::io;
 
+        // The compressed size of the file runs past the end of the archive
         let mut v = Vec::new();
-        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
-        let mut reader = io::Cursor::new(v);
-        loop {
-            match read_zipfile_from_stream(&mut reader).unwrap() {
-                None => break,
-                _ => (),
-            }
+        v.extend_from_slice(include_bytes!("../tests/data/data_out_of_bounds.zip"));
+        let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        match zip.by_index(0) {
+            Err(ZipError::InvalidArchive("Entry data out of bounds")) => {}
+            _ => panic!("Expected the data to be out of bounds"),
         }
+        assert!(zip.by_index_raw(0).is_err());
     }
 
     #[test]
-    fn zip_clone() {
+    fn new_file() {
         use super::ZipArchive;
-        use std::io::{self, Read};
+        use std::fs;
+        use std::io::Read;
 
-        let mut v = Vec::new();
-        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
-        let mut reader1 = ZipArchive::new(io::Cursor::new(v)).unwrap();
-        let mut reader2 = reader1.clone();
+        let data = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+        let open = |name| ZipArchive::new_file(fs::File::open(data.join(name)).unwrap());
+        let mut zip = open("mimetype.zip").unwrap();
+        assert_eq!(zip.reader_len, Some(159));
+        let mut contents = String::new();
+        zip.by_index(0)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "application/vnd.oasis.opendocument.text");
+        assert_eq!(zip.entry_extent(0).unwrap(), 0..77);
 
-        let mut file1 = reader1.by_index(0).unwrap();
-        let mut file2 = reader2.by_index(0).unwrap();
+        let mut zip = open("data_out_of_bounds.zip").unwrap();
+        assert!(zip.by_index(0).is_err());
+        assert!(zip.entry_extent(0).is_err());
+    }
 
-        let t = file1.last_modified();
-        assert_eq!(
-            (
-                t.year(),
-                t.month(),
-                t.day(),
-                t.hour(),
-                t.minute(),
-                t.second()
-            ),
-            (1980, 1, 1, 0, 0, 0)
-        );
+    #[test]
+    fn output_limit() {
+        use super::ZipArchive;
+        use std::io::{self, Read};
 
-        let mut buf1 = [0; 5];
-        let mut buf2 = [0; 5];
-        let mut buf3 = [0; 5];
-        let mut buf4 = [0; 5];
+        let buf = archive(&[("zeros", &[0; 100_000])]);
 
-        file1.read(&mut buf1).unwrap();
-        file2.read(&mut buf2).unwrap();
-        file1.read(&mut buf3).unwrap();
-        file2.read(&mut buf4).unwrap();
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let mut file = zip.by_index(0).unwrap();
+        file.set_output_limit(1000);
+        let mut contents = Vec::new();
+        let err = file.read_to_end(&mut contents).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(contents.len() <= 1000);
+        drop(file);
 
-        assert_eq!(buf1, buf2);
-        assert_eq!(buf3, buf4);
-        assert!(buf1 != buf3);
+        let mut file = zip.by_index(0).unwrap();
+        file.set_output_limit(100_000);
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents.len(), 100_000);
     }
 
     #[test]
-    fn file_and_dir_predicates() {
+    fn made_by() {
         use super::ZipArchive;
+        use crate::types::System;
         use std::io;
 
         let mut v = Vec::new();
-        v.extend_from_slice(include_bytes!("../tests/data/files_and_dirs.zip"));
+        v.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
         let mut zip = ZipArchive::new(io::Cursor::new(v)).unwrap();
+        let (major, minor) = zip.by_index(0).unwrap().version_made_by();
+        let system = zip.files[0].system;
+        assert_eq!(zip.by_index(0).unwrap().made_by(), (system, major, minor));
 
-        for i in 0..zip.len() {
-            let zip_file = zip.by_index(i).unwrap();
-            let full_name = zip_file.sanitized_name();
-            let file_name = full_name.file_name().unwrap().to_str().unwrap();
-            assert!(
-                (file_name.starts_with("dir") && zip_file.is_dir())
-                    || (file_name.starts_with("file") && zip_file.is_file())
-            );
+        zip.files[0].system = System::Unix;
+        zip.files[0].version_made_by = 63;
+        assert_eq!(zip.by_index(0).unwrap().made_by(), (System::Unix, 6, 3));
+    }
+
+    #[test]
+    fn read_chunks() {
+        use super::ZipArchive;
+        use crate::result::ZipError;
+        use std::io;
+
+        let contents: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let buf = archive(&[("file", &contents)]);
+
+        let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+        let mut chunks = Vec::new();
+        zip.by_index(0)
+            .unwrap()
+            .read_chunks(4096, |chunk| {
+                chunks.push(chunk.to_vec());
+                Ok(())
+            })
+            .unwrap();
+        let sizes: Vec<usize> = chunks.iter().map(Vec::len).collect();
+        assert_eq!(sizes, [4096, 4096, 1808]);
+        assert_eq!(chunks.concat(), contents);
+
+        // The checksum is validated at the end of the file, which is found with the last chunk
+        zip.files[0].crc32 ^= 1;
+        let mut calls = 0;
+        let result = zip.by_index(0).unwrap().read_chunks(4096, |_| {
+            calls += 1;
+            Ok(())
+        });
+        assert!(matches!(result, Err(ZipError::Io(_))));
+        assert_eq!(calls, 2);
+
+        let result = zip
+            .by_index(0)
+            .unwrap()
+            .read_chunks(4096, |_| Err(ZipError::FileNotFound));
+        assert!(matches!(result, Err(ZipError::FileNotFound)));
+    }
+
+    #[cfg(feature = "aes")]
+    #[test]
+    fn aes_by_name() {
+        use super::ZipArchive;
+        use crate::compression::CompressionMethod;
+        use crate::result::ZipError;
+        use crate::types::AesMode;
+        use crate::write::FileOptions;
+        use std::io::{self, Read};
+
+        let contents = b"The secret contents of an encrypted file\n".repeat(50);
+        let mut methods = vec![CompressionMethod::Stored];
+        #[cfg(feature = "deflate")]
+        methods.push(CompressionMethod::Deflated);
+        for method in methods {
+            let options = FileOptions::default()
+                .compression_method(method)
+                .encrypt_aes("password", AesMode::Aes256);
+            let mut buf = archive_with(options, &[("secret.txt", &contents)]);
+
+            let mut zip = ZipArchive::new(io::Cursor::new(buf.clone())).unwrap();
+            let needed =
+                ZipError::UnsupportedArchive("A password is needed to read encrypted files");
+            assert_eq!(zip.can_read(0).unwrap_err().to_string(), needed.to_string());
+            assert!(matches!(
+                zip.by_name("secret.txt"),
+                Err(ZipError::UnsupportedArchive(_))
+            ));
+
+            zip.set_password(Some(b"password"));
+            zip.can_read(0).unwrap();
+            let mut file = zip.by_name("secret.txt").unwrap();
+            assert_eq!(file.compression(), method);
+            let mut read = Vec::new();
+            file.read_to_end(&mut read).unwrap();
+            assert!(read == contents);
+
+            // Damaged data no longer matches the authentication code
+            let data_start = 30 + 10 + u16::from_le_bytes([buf[28], buf[29]]) as usize;
+            buf[data_start + AesMode::Aes256.salt_length() + 2] ^= 1;
+            let mut zip = ZipArchive::new(io::Cursor::new(buf)).unwrap();
+            zip.set_password(Some(b"password"));
+            let mut file = zip.by_name("secret.txt").unwrap();
+            let err = file.read_to_end(&mut Vec::new()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[cfg(feature = "aes")]
+    #[test]
+    fn aes_known_archive() {
+        use super::ZipArchive;
+        use crate::result::ZipError;
+        use std::io::{self, Read};
+
+        // Written with OpenSSL rather than this crate, with the password "helloworld"
+        let fixture = include_bytes!("../tests/data/aes_archive.zip");
+        let mut zip = ZipArchive::new(io::Cursor::new(&fixture[..])).unwrap();
+        zip.set_password(Some(b"hello world"));
+        match zip.by_name("secret_128.txt") {
+            Err(ZipError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+            _ => panic!("a wrong password should be rejected"),
+        }
+
+        zip.set_password(Some(b"helloworld"));
+        let line = b"Lorem ipsum dolor sit amet\n";
+        let long: Vec<u8> = (0..200)
+            .flat_map(|i| format!("line {:04} of the secret file\n", i).into_bytes())
+            .collect();
+        let expected = [
+            ("secret_128.txt", line.to_vec()),
+            ("secret_192.txt", line.repeat(3)),
+            ("secret_256.txt", long),
+        ];
+        for (name, contents) in expected.iter() {
+            let mut read = Vec::new();
+            zip.by_name(name).unwrap().read_to_end(&mut read).unwrap();
+            assert!(read == *contents);
         }
     }
 }
@@ -0,0 +1,173 @@
+//! Decompression of the Reduce methods used by early versions of PKZIP
+//!
+//! The data starts with a follower set for every byte value, which lists the bytes that are
+//! likely to follow it. Every byte is then coded either as an index into the follower set of the
+//! previous byte, or as a literal. The resulting bytes form an LZ77 stream in which `DLE` starts a
+//! back reference. The compression factor (1 to 4) is the number of bits of the reference that
+//! go to the distance instead of the length.
+
+use std::io;
+use std::io::prelude::*;
+
+/// The byte that introduces a back reference
+const DLE: u8 = 0x90;
+/// The largest distance of a back reference, which is reached with compression factor 4
+const WINDOW_SIZE: usize = 1 << 12;
+/// The largest number of bytes in a follower set
+const MAX_FOLLOWERS: u8 = 32;
+
+/// A reader that expands the data of a file compressed with one of the Reduce methods
+pub struct ReduceDecoder<R: Read> {
+    inner: R,
+    factor: u8,
+    /// The number of bytes that remain to be produced
+    remaining: u64,
+    bit_buffer: u32,
+    bit_count: u8,
+    /// The follower set of every byte, read before the first byte is expanded
+    followers: Option<Vec<Vec<u8>>>,
+    /// The previous byte of the LZ77 stream, used to select a follower set
+    previous: u8,
+    window: Vec<u8>,
+    position: usize,
+    /// The distance and number of bytes that remain of the current back reference
+    copy: (usize, usize),
+}
+
+impl<R: Read> ReduceDecoder<R> {
+    /// Expand `uncompressed_size` bytes that were compressed with a factor between 1 and 4
+    pub fn new(inner: R, factor: u8, uncompressed_size: u64) -> ReduceDecoder<R> {
+        debug_assert!((1..=4).contains(&factor));
+        ReduceDecoder {
+            inner,
+            factor,
+            remaining: uncompressed_size,
+            bit_buffer: 0,
+            bit_count: 0,
+            followers: None,
+            previous: 0,
+            window: vec![0; WINDOW_SIZE],
+            position: 0,
+            copy: (0, 0),
+        }
+    }
+
+    /// Return the reader of the compressed data
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read up to 8 bits, starting with the least significant bit of every byte
+    fn read_bits(&mut self, count: u8) -> io::Result<u8> {
+        while self.bit_count < count {
+            let mut byte = [0u8];
+            if self.inner.read(&mut byte)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Reduce data ended early",
+                ));
+            }
+            self.bit_buffer |= (byte[0] as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let bits = self.bit_buffer & ((1 << count) - 1);
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(bits as u8)
+    }
+
+    fn read_followers(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let mut followers = vec![Vec::new(); 256];
+        for set in followers.iter_mut().rev() {
+            let count = self.read_bits(6)?;
+            if count > MAX_FOLLOWERS {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid Reduce follower set",
+                ));
+            }
+            for _ in 0..count {
+                set.push(self.read_bits(8)?);
+            }
+        }
+        Ok(followers)
+    }
+
+    /// Read the next byte of the LZ77 stream
+    fn next_byte(&mut self) -> io::Result<u8> {
+        let followers = match self.followers.take() {
+            Some(followers) => followers,
+            None => self.read_followers()?,
+        };
+        let result = self.next_byte_from(&followers[self.previous as usize]);
+        self.followers = Some(followers);
+        let byte = result?;
+        self.previous = byte;
+        Ok(byte)
+    }
+
+    fn next_byte_from(&mut self, set: &[u8]) -> io::Result<u8> {
+        if set.is_empty() || self.read_bits(1)? == 1 {
+            return self.read_bits(8);
+        }
+        let index_bits = match set.len() {
+            1 => 1,
+            len => 8 - ((len - 1) as u8).leading_zeros() as u8,
+        };
+        let index = self.read_bits(index_bits)?;
+        set.get(index as usize).cloned().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "invalid Reduce follower index")
+        })
+    }
+
+    /// Read the next literal or back reference, and start copying the latter
+    fn next_token(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.next_byte()?;
+        if byte != DLE {
+            return Ok(Some(byte));
+        }
+        let v = self.next_byte()?;
+        if v == 0 {
+            return Ok(Some(DLE));
+        }
+        let length_bits = 8 - self.factor;
+        let length_mask = (1u8 << length_bits) - 1;
+        let mut length = (v & length_mask) as usize;
+        if length == length_mask as usize {
+            length += self.next_byte()? as usize;
+        }
+        let w = self.next_byte()?;
+        let distance = (((v >> length_bits) as usize) << 8) + w as usize + 1;
+        self.copy = (distance, length + 3);
+        Ok(None)
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.window[self.position] = byte;
+        self.position = (self.position + 1) % WINDOW_SIZE;
+    }
+}
+
+impl<R: Read> Read for ReduceDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut count = 0;
+        while count < buf.len() && self.remaining > 0 {
+            let byte = if self.copy.1 > 0 {
+                // The window starts out zeroed, so references before the start produce zeros
+                let (distance, length) = self.copy;
+                self.copy.1 = length - 1;
+                self.window[(self.position + WINDOW_SIZE - distance) % WINDOW_SIZE]
+            } else {
+                match self.next_token()? {
+                    Some(byte) => byte,
+                    None => continue,
+                }
+            };
+            self.push(byte);
+            buf[count] = byte;
+            count += 1;
+            self.remaining -= 1;
+        }
+        Ok(count)
+    }
+}
@@ -1,25 +1,62 @@
+//! Records at the end of a ZIP archive, which locate its central directory
+//!
+//! Each record can be parsed from and written to a stream, for building or repairing archives
+//! by hand. The writer uses the same functions. The signatures of the other headers are defined
+//! here as well.
+
 use crate::result::{ZipError, ZipResult};
 use podio::{LittleEndian, ReadPodExt, WritePodExt};
 use std::io;
 use std::io::prelude::*;
 
+/// Signature of a local file header
 pub const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+/// Signature of a central directory header
 pub const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x02014b50;
-const CENTRAL_DIRECTORY_END_SIGNATURE: u32 = 0x06054b50;
+/// Signature of a data descriptor, which writers may leave out
+pub const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+/// Signature of the end of central directory record
+pub const CENTRAL_DIRECTORY_END_SIGNATURE: u32 = 0x06054b50;
+/// Signature of the ZIP64 end of central directory record
 pub const ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE: u32 = 0x06064b50;
-const ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+/// Signature of the ZIP64 end of central directory locator
+pub const ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+
+/// Size of the end of central directory record, without the comment
+pub const CENTRAL_DIRECTORY_END_SIZE: u64 = 22;
+/// Size of the ZIP64 end of central directory record, without the extensible data sector
+pub const ZIP64_CENTRAL_DIRECTORY_END_SIZE: u64 = 56;
+/// Size of the ZIP64 end of central directory locator
+pub const ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIZE: u64 = 20;
+/// Sizes and offsets from this value on do not fit the 32-bit fields and are stored as ZIP64
+pub const ZIP64_BYTES_THR: u64 = u32::MAX as u64;
+/// Entry counts from this value on do not fit the 16-bit fields and are stored as ZIP64
+pub const ZIP64_ENTRY_THR: usize = u16::MAX as usize;
 
+/// The end of central directory record, which ends every archive
+///
+/// Fields that do not fit are set to their maximum value, and a ZIP64 record holds them
+/// instead.
+#[derive(Clone, Debug, PartialEq)]
 pub struct CentralDirectoryEnd {
+    /// Number of the disk with this record
     pub disk_number: u16,
+    /// Number of the disk on which the central directory starts
     pub disk_with_central_directory: u16,
+    /// Number of files in the central directory on this disk
     pub number_of_files_on_this_disk: u16,
+    /// Number of files in the central directory
     pub number_of_files: u16,
+    /// Size of the central directory
     pub central_directory_size: u32,
+    /// Offset of the central directory from the start of the first disk
     pub central_directory_offset: u32,
+    /// Comment of the archive, which can be at most 65535 bytes long
     pub zip_file_comment: Vec<u8>,
 }
 
 impl CentralDirectoryEnd {
+    /// Parse the record at the current position of the reader
     pub fn parse<T: Read>(reader: &mut T) -> ZipResult<CentralDirectoryEnd> {
         let magic = reader.read_u32::<LittleEndian>()?;
         if magic != CENTRAL_DIRECTORY_END_SIGNATURE {
@@ -45,10 +82,21 @@ impl CentralDirectoryEnd {
         })
     }
 
+    /// Whether any of the fields are set to the marker value that defers to the ZIP64 record
+    pub fn has_zip64_markers(&self) -> bool {
+        self.number_of_files_on_this_disk == 0xFFFF
+            || self.number_of_files == 0xFFFF
+            || self.central_directory_size == 0xFFFFFFFF
+            || self.central_directory_offset == 0xFFFFFFFF
+    }
+
+    /// Search the end of the reader for the record and parse it
+    ///
+    /// Returns the record and the position it was found at.
     pub fn find_and_parse<T: Read + io::Seek>(
         reader: &mut T,
     ) -> ZipResult<(CentralDirectoryEnd, u64)> {
-        const HEADER_SIZE: u64 = 22;
+        const HEADER_SIZE: u64 = CENTRAL_DIRECTORY_END_SIZE;
         const BYTES_BETWEEN_MAGIC_AND_COMMENT_SIZE: u64 = HEADER_SIZE - 6;
         let file_length = reader.seek(io::SeekFrom::End(0))?;
 
@@ -83,7 +131,14 @@ impl CentralDirectoryEnd {
         ))
     }
 
+    /// Write the record, in the format that `parse` reads
     pub fn write<T: Write>(&self, writer: &mut T) -> ZipResult<()> {
+        if self.zip_file_comment.len() > u16::MAX as usize {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Archive comment is longer than 65535 bytes",
+            )));
+        }
         writer.write_u32::<LittleEndian>(CENTRAL_DIRECTORY_END_SIGNATURE)?;
         writer.write_u16::<LittleEndian>(self.disk_number)?;
         writer.write_u16::<LittleEndian>(self.disk_with_central_directory)?;
@@ -97,13 +152,20 @@ impl CentralDirectoryEnd {
     }
 }
 
+/// The ZIP64 end of central directory locator, which directly precedes the end of central
+/// directory record of archives with a ZIP64 record
+#[derive(Clone, Debug, PartialEq)]
 pub struct Zip64CentralDirectoryEndLocator {
+    /// Number of the disk with the ZIP64 end of central directory record
     pub disk_with_central_directory: u32,
+    /// Offset of the ZIP64 end of central directory record from the start of the first disk
     pub end_of_central_directory_offset: u64,
+    /// Total number of disks
     pub number_of_disks: u32,
 }
 
 impl Zip64CentralDirectoryEndLocator {
+    /// Parse the locator at the current position of the reader
     pub fn parse<T: Read>(reader: &mut T) -> ZipResult<Zip64CentralDirectoryEndLocator> {
         let magic = reader.read_u32::<LittleEndian>()?;
         if magic != ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE {
@@ -121,22 +183,123 @@ impl Zip64CentralDirectoryEndLocator {
             number_of_disks: number_of_disks,
         })
     }
+
+    /// Write the locator, in the format that `parse` reads
+    pub fn write<T: Write>(&self, writer: &mut T) -> ZipResult<()> {
+        writer.write_u32::<LittleEndian>(ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIGNATURE)?;
+        writer.write_u32::<LittleEndian>(self.disk_with_central_directory)?;
+        writer.write_u64::<LittleEndian>(self.end_of_central_directory_offset)?;
+        writer.write_u32::<LittleEndian>(self.number_of_disks)?;
+        Ok(())
+    }
 }
 
+/// The ZIP64 end of central directory record, which holds the values that do not fit in the
+/// end of central directory record
+///
+/// The extensible data sector that can follow the record is skipped when parsing, and not
+/// written.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Zip64CentralDirectoryEnd {
+    /// Version of the specification of the tool that made the archive, and its host system
     pub version_made_by: u16,
+    /// Version of the specification needed to extract the archive
     pub version_needed_to_extract: u16,
+    /// Number of the disk with this record
     pub disk_number: u32,
+    /// Number of the disk on which the central directory starts
     pub disk_with_central_directory: u32,
+    /// Number of files in the central directory on this disk
     pub number_of_files_on_this_disk: u64,
+    /// Number of files in the central directory
     pub number_of_files: u64,
+    /// Size of the central directory
     pub central_directory_size: u64,
+    /// Offset of the central directory from the start of the first disk
     pub central_directory_offset: u64,
     //pub extensible_data_sector: Vec<u8>, <-- We don't do anything with this at the moment.
 }
 
 impl Zip64CentralDirectoryEnd {
-    pub fn find_and_parse<T: Read + io::Seek>(
+    /// Parse the record at the current position of the reader
+    pub fn parse<T: Read>(reader: &mut T) -> ZipResult<Zip64CentralDirectoryEnd> {
+        let magic = reader.read_u32::<LittleEndian>()?;
+        if magic != ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE {
+            return Err(ZipError::InvalidArchive(
+                "Invalid zip64 central directory end signature",
+            ));
+        }
+
+        let _record_size = reader.read_u64::<LittleEndian>()?;
+        // We would use this value if we did anything with the "zip64 extensible data sector".
+
+        let version_made_by = reader.read_u16::<LittleEndian>()?;
+        let version_needed_to_extract = reader.read_u16::<LittleEndian>()?;
+        let disk_number = reader.read_u32::<LittleEndian>()?;
+        let disk_with_central_directory = reader.read_u32::<LittleEndian>()?;
+        let number_of_files_on_this_disk = reader.read_u64::<LittleEndian>()?;
+        let number_of_files = reader.read_u64::<LittleEndian>()?;
+        let central_directory_size = reader.read_u64::<LittleEndian>()?;
+        let central_directory_offset = reader.read_u64::<LittleEndian>()?;
+
+        Ok(Zip64CentralDirectoryEnd {
+            version_made_by: version_made_by,
+            version_needed_to_extract: version_needed_to_extract,
+            disk_number: disk_number,
+            disk_with_central_directory: disk_with_central_directory,
+            number_of_files_on_this_disk: number_of_files_on_this_disk,
+            number_of_files: number_of_files,
+            central_directory_size: central_directory_size,
+            central_directory_offset: central_directory_offset,
+        })
+    }
+
+    /// Parse the record that directly precedes the locator at `locator_pos`, if there is one.
+    /// Returns the record and the archive offset implied by its position.
+    ///
+    /// The record may be followed by an extensible data sector, so it is recognized by its size
+    /// field, which must make it end where the locator starts.
+    pub(crate) fn parse_before_locator<T: Read + io::Seek>(
+        reader: &mut T,
+        nominal_offset: u64,
+        locator_pos: u64,
+    ) -> ZipResult<Option<(Zip64CentralDirectoryEnd, u64)>> {
+        let search_upper_bound = match locator_pos.checked_sub(ZIP64_CENTRAL_DIRECTORY_END_SIZE) {
+            Some(pos) if pos >= nominal_offset => pos,
+            _ => return Ok(None),
+        };
+        // Leave room for an extensible data sector, like `find_and_parse_backwards`
+        let search_lower_bound = ::std::cmp::max(
+            nominal_offset,
+            search_upper_bound.saturating_sub(u16::MAX as u64),
+        );
+
+        reader.seek(io::SeekFrom::Start(search_lower_bound))?;
+        let window = ReadPodExt::read_exact(
+            reader,
+            (search_upper_bound - search_lower_bound) as usize + 12,
+        )?;
+        let signature = ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE.to_le_bytes();
+        for start in (0..=(search_upper_bound - search_lower_bound) as usize).rev() {
+            if window[start..start + 4] != signature {
+                continue;
+            }
+            // The size of the record does not count the signature and the size field itself
+            let mut record_size = [0u8; 8];
+            record_size.copy_from_slice(&window[start + 4..start + 12]);
+            let pos = search_lower_bound + start as u64;
+            if (pos + 12).checked_add(u64::from_le_bytes(record_size)) == Some(locator_pos) {
+                reader.seek(io::SeekFrom::Start(pos))?;
+                return Ok(Some((
+                    Zip64CentralDirectoryEnd::parse(reader)?,
+                    pos - nominal_offset,
+                )));
+            }
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn find_and_parse<T: Read + io::Seek>(
         reader: &mut T,
         nominal_offset: u64,
         search_upper_bound: u64,
@@ -148,32 +311,8 @@ impl Zip64CentralDirectoryEnd {
 
             if reader.read_u32::<LittleEndian>()? == ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE {
                 let archive_offset = pos - nominal_offset;
-
-                let _record_size = reader.read_u64::<LittleEndian>()?;
-                // We would use this value if we did anything with the "zip64 extensible data sector".
-
-                let version_made_by = reader.read_u16::<LittleEndian>()?;
-                let version_needed_to_extract = reader.read_u16::<LittleEndian>()?;
-                let disk_number = reader.read_u32::<LittleEndian>()?;
-                let disk_with_central_directory = reader.read_u32::<LittleEndian>()?;
-                let number_of_files_on_this_disk = reader.read_u64::<LittleEndian>()?;
-                let number_of_files = reader.read_u64::<LittleEndian>()?;
-                let central_directory_size = reader.read_u64::<LittleEndian>()?;
-                let central_directory_offset = reader.read_u64::<LittleEndian>()?;
-
-                return Ok((
-                    Zip64CentralDirectoryEnd {
-                        version_made_by: version_made_by,
-                        version_needed_to_extract: version_needed_to_extract,
-                        disk_number: disk_number,
-                        disk_with_central_directory: disk_with_central_directory,
-                        number_of_files_on_this_disk: number_of_files_on_this_disk,
-                        number_of_files: number_of_files,
-                        central_directory_size: central_directory_size,
-                        central_directory_offset: central_directory_offset,
-                    },
-                    archive_offset,
-                ));
+                reader.seek(io::SeekFrom::Start(pos))?;
+                return Ok((Zip64CentralDirectoryEnd::parse(reader)?, archive_offset));
             }
 
             pos += 1;
@@ -183,4 +322,164 @@ impl Zip64CentralDirectoryEnd {
             "Could not find ZIP64 central directory end",
         ))
     }
+
+    /// Search backwards from `search_upper_bound` for the record, for archives that lack the
+    /// locator. Returns the record and the position it was found at.
+    pub(crate) fn find_and_parse_backwards<T: Read + io::Seek>(
+        reader: &mut T,
+        search_upper_bound: u64,
+    ) -> ZipResult<(Zip64CentralDirectoryEnd, u64)> {
+        // The record normally directly precedes the end of central directory record, but
+        // leave room for an extensible data sector.
+        const SEARCH_LENGTH: u64 = u16::MAX as u64;
+        let search_lower_bound = search_upper_bound.saturating_sub(SEARCH_LENGTH);
+
+        reader.seek(io::SeekFrom::Start(search_lower_bound))?;
+        let window = ReadPodExt::read_exact(
+            reader,
+            (search_upper_bound - search_lower_bound) as usize + 4,
+        )?;
+        let signature = ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE.to_le_bytes();
+        let pos = match window.windows(4).rposition(|w| w == signature) {
+            Some(pos) => search_lower_bound + pos as u64,
+            None => {
+                return Err(ZipError::InvalidArchive(
+                    "Could not find ZIP64 central directory end",
+                ))
+            }
+        };
+
+        reader.seek(io::SeekFrom::Start(pos))?;
+        Ok((Zip64CentralDirectoryEnd::parse(reader)?, pos))
+    }
+
+    /// Write the record, in the format that `parse` reads
+    pub fn write<T: Write>(&self, writer: &mut T) -> ZipResult<()> {
+        // Size of the record, excluding the signature and this field
+        const RECORD_SIZE: u64 = 44;
+        writer.write_u32::<LittleEndian>(ZIP64_CENTRAL_DIRECTORY_END_SIGNATURE)?;
+        writer.write_u64::<LittleEndian>(RECORD_SIZE)?;
+        writer.write_u16::<LittleEndian>(self.version_made_by)?;
+        writer.write_u16::<LittleEndian>(self.version_needed_to_extract)?;
+        writer.write_u32::<LittleEndian>(self.disk_number)?;
+        writer.write_u32::<LittleEndian>(self.disk_with_central_directory)?;
+        writer.write_u64::<LittleEndian>(self.number_of_files_on_this_disk)?;
+        writer.write_u64::<LittleEndian>(self.number_of_files)?;
+        writer.write_u64::<LittleEndian>(self.central_directory_size)?;
+        writer.write_u64::<LittleEndian>(self.central_directory_offset)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CentralDirectoryEnd, Zip64CentralDirectoryEnd, Zip64CentralDirectoryEndLocator};
+    use std::io;
+
+    #[test]
+    fn central_directory_end_round_trip() {
+        let record = CentralDirectoryEnd {
+            disk_number: 1,
+            disk_with_central_directory: 1,
+            number_of_files_on_this_disk: 2,
+            number_of_files: 3,
+            central_directory_size: 0x1234,
+            central_directory_offset: 0xFFFFFFFF,
+            zip_file_comment: b"comment".to_vec(),
+        };
+        let mut buf = Vec::new();
+        record.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), 22 + 7);
+        assert_eq!(CentralDirectoryEnd::parse(&mut &buf[..]).unwrap(), record);
+        let (found, pos) = CentralDirectoryEnd::find_and_parse(&mut io::Cursor::new(buf)).unwrap();
+        assert_eq!(pos, 0);
+        assert_eq!(found, record);
+
+        let long_comment = CentralDirectoryEnd {
+            zip_file_comment: vec![0; 0x10000],
+            ..record
+        };
+        assert!(long_comment.write(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn zip64_central_directory_end_round_trip() {
+        let record = Zip64CentralDirectoryEnd {
+            version_made_by: 0x031e,
+            version_needed_to_extract: 45,
+            disk_number: 0,
+            disk_with_central_directory: 0,
+            number_of_files_on_this_disk: 0x10000,
+            number_of_files: 0x10000,
+            central_directory_size: 0x1_0000_0000,
+            central_directory_offset: 0x2_0000_0000,
+        };
+        let mut buf = Vec::new();
+        record.write(&mut buf).unwrap();
+        assert_eq!(buf.len() as u64, super::ZIP64_CENTRAL_DIRECTORY_END_SIZE);
+        assert_eq!(
+            Zip64CentralDirectoryEnd::parse(&mut &buf[..]).unwrap(),
+            record
+        );
+    }
+
+    #[test]
+    fn zip64_central_directory_end_before_locator() {
+        let record = Zip64CentralDirectoryEnd {
+            version_made_by: 0x031e,
+            version_needed_to_extract: 45,
+            disk_number: 0,
+            disk_with_central_directory: 0,
+            number_of_files_on_this_disk: 1,
+            number_of_files: 1,
+            central_directory_size: 46,
+            central_directory_offset: 100,
+        };
+        let mut buf = vec![0; 10];
+        record.write(&mut buf).unwrap();
+        let locator_pos = buf.len() as u64;
+        let found = Zip64CentralDirectoryEnd::parse_before_locator(
+            &mut io::Cursor::new(&buf),
+            4,
+            locator_pos,
+        );
+        assert_eq!(found.unwrap(), Some((record.clone(), 6)));
+
+        // A record with an extensible data sector is found from its size field
+        buf[14..22].copy_from_slice(&(44u64 + 30).to_le_bytes());
+        buf.extend_from_slice(&[0x42; 30]);
+        let locator_pos = buf.len() as u64;
+        let found = Zip64CentralDirectoryEnd::parse_before_locator(
+            &mut io::Cursor::new(&buf),
+            4,
+            locator_pos,
+        );
+        assert_eq!(found.unwrap(), Some((record, 6)));
+
+        // A size that does not reach the locator does not match
+        buf[14..22].copy_from_slice(&44u64.to_le_bytes());
+        let found = Zip64CentralDirectoryEnd::parse_before_locator(
+            &mut io::Cursor::new(&buf),
+            4,
+            locator_pos,
+        );
+        assert_eq!(found.unwrap(), None);
+    }
+
+    #[test]
+    fn zip64_locator_round_trip() {
+        let locator = Zip64CentralDirectoryEndLocator {
+            disk_with_central_directory: 0,
+            end_of_central_directory_offset: 0x3_0000_0000,
+            number_of_disks: 1,
+        };
+        let mut buf = Vec::new();
+        locator.write(&mut buf).unwrap();
+        assert_eq!(
+            buf.len() as u64,
+            super::ZIP64_CENTRAL_DIRECTORY_END_LOCATOR_SIZE
+        );
+        let parsed = Zip64CentralDirectoryEndLocator::parse(&mut &buf[..]).unwrap();
+        assert_eq!(parsed, locator);
+    }
 }
@@ -1,15 +1,19 @@
 //! Types that specify what is contained in a ZIP.
 
+/// The host system that made a file, which determines how its attributes are interpreted
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum System {
+    /// MS-DOS and other FAT file systems
     Dos = 0,
+    /// Unix
     Unix = 3,
+    /// Any other system
     Unknown,
-    #[doc(hidden)]
-    __Nonexhaustive,
 }
 
 impl System {
+    /// Convert the upper byte of the version made by field of a header
     pub fn from_u8(system: u8) -> System {
         use self::System::*;
 
@@ -115,12 +119,18 @@ impl DateTime {
     #[cfg(feature = "time")]
     /// Converts a ::time::Tm object to a DateTime
     ///
-    /// Returns `Err` when this object is out of bounds
+    /// The msdos format can only represent the years 1980 to 2107, so earlier times are clamped
+    /// to 1980-01-01 00:00:00 and later times to 2107-12-31 23:59:58. Returns `Err` when any
+    /// other field of this object is out of bounds.
     pub fn from_time(tm: ::time::Tm) -> Result<DateTime, ()> {
-        if tm.tm_year >= 80
-            && tm.tm_year <= 207
-            && tm.tm_mon >= 1
-            && tm.tm_mon <= 31
+        if tm.tm_year < 80 {
+            return Ok(DateTime::default());
+        }
+        if tm.tm_year > 207 {
+            return DateTime::from_date_and_time(2107, 12, 31, 23, 59, 58);
+        }
+        if tm.tm_mon >= 0
+            && tm.tm_mon <= 11
             && tm.tm_mday >= 1
             && tm.tm_mday <= 31
             && tm.tm_hour >= 0
@@ -153,6 +163,18 @@ impl DateTime {
         (self.day as u16) | ((self.month as u16) << 5) | ((self.year - 1980) << 9)
     }
 
+    /// Seconds since the Unix epoch, taking the datetime to be in UTC
+    pub(crate) fn unix_timestamp(&self) -> i64 {
+        // Days since the epoch of the civil date, counted in 400 year eras that start on March 1st
+        let year = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * ((self.month as i64 + 9) % 12) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+        days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+
     #[cfg(feature = "time")]
     /// Converts the datetime to a Tm structure
     ///
@@ -203,6 +225,42 @@ impl DateTime {
 
 pub const DEFAULT_VERSION: u8 = 46;
 
+/// Key strength of WinZip AES encryption
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AesMode {
+    /// AES-128, with a 16 byte key
+    Aes128,
+    /// AES-192, with a 24 byte key
+    Aes192,
+    /// AES-256, with a 32 byte key
+    Aes256,
+}
+
+impl AesMode {
+    /// The length of the salt that precedes the encrypted data
+    pub fn salt_length(&self) -> usize {
+        self.key_length() / 2
+    }
+
+    /// The length of the encryption key
+    pub fn key_length(&self) -> usize {
+        match *self {
+            AesMode::Aes128 => 16,
+            AesMode::Aes192 => 24,
+            AesMode::Aes256 => 32,
+        }
+    }
+
+    /// The strength value that is stored in the AES extra field
+    pub fn strength(&self) -> u8 {
+        match *self {
+            AesMode::Aes128 => 1,
+            AesMode::Aes192 => 2,
+            AesMode::Aes256 => 3,
+        }
+    }
+}
+
 /// Structure representing a ZIP file.
 #[derive(Debug, Clone)]
 pub struct ZipFileData {
@@ -210,8 +268,18 @@ pub struct ZipFileData {
     pub system: System,
     /// Specification version
     pub version_made_by: u8,
+    /// Version of the specification needed to extract the file, as read from the archive
+    pub version_needed_to_extract: u16,
     /// True if the file is encrypted.
     pub encrypted: bool,
+    /// True if the checksum and sizes are stored in a data descriptor after the file data
+    pub using_data_descriptor: bool,
+    /// True if the name and comment are flagged as encoded in UTF-8
+    pub is_utf8: bool,
+    /// True if the sizes of the file are stored in a ZIP64 extra field
+    pub large_file: bool,
+    /// The key strength if the file is encrypted with WinZip AES
+    pub aes_mode: Option<AesMode>,
     /// Compression method used to store the file
     pub compression_method: crate::compression::CompressionMethod,
     /// Last modified time. This will only have a 2 second precision.
@@ -234,6 +302,26 @@ pub struct ZipFileData {
     pub data_start: u64,
     /// External file attributes
     pub external_attributes: u32,
+    /// Unix last modification time, in seconds since the epoch
+    pub unix_mtime: Option<u32>,
+    /// Unix last access time, in seconds since the epoch
+    pub unix_atime: Option<u32>,
+    /// Unix creation time, in seconds since the epoch
+    pub unix_ctime: Option<u32>,
+    /// Unix user id of the owner
+    pub unix_uid: Option<u32>,
+    /// Unix group id of the owner
+    pub unix_gid: Option<u32>,
+    /// NTFS last modification time, in 100 nanosecond intervals since 1601-01-01
+    pub ntfs_mtime: Option<u64>,
+    /// NTFS last access time, in 100 nanosecond intervals since 1601-01-01
+    pub ntfs_atime: Option<u64>,
+    /// NTFS creation time, in 100 nanosecond intervals since 1601-01-01
+    pub ntfs_ctime: Option<u64>,
+    /// Raw extra field of the central directory header, or of the local header for files that
+    /// are read from a stream. The writer writes the fields in here that it does not build
+    /// itself.
+    pub extra_field: Vec<u8>,
 }
 
 impl ZipFileData {
@@ -267,13 +355,54 @@ impl ZipFileData {
             })
     }
 
+    pub fn enclosed_name(&self) -> Option<&::std::path::Path> {
+        if self.file_name.contains('\0') {
+            return None;
+        }
+        let path = ::std::path::Path::new(&*self.file_name);
+        if is_enclosed(path) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
     pub fn version_needed(&self) -> u16 {
-        match self.compression_method {
+        let method_version = match self.compression_method {
             #[cfg(feature = "bzip2")]
             crate::compression::CompressionMethod::Bzip2 => 46,
+            #[cfg(feature = "zstd")]
+            crate::compression::CompressionMethod::Zstd => 63,
             _ => 20,
+        };
+        let format_version = if self.aes_mode.is_some() {
+            // AES encryption
+            51
+        } else if self.large_file || self.header_start >= crate::spec::ZIP64_BYTES_THR {
+            // ZIP64 format extensions
+            45
+        } else {
+            20
+        };
+        method_version.max(format_version)
+    }
+}
+
+/// Returns whether a relative path stays within the directory it is joined to
+pub fn is_enclosed(path: &::std::path::Path) -> bool {
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            ::std::path::Component::Prefix(_) | ::std::path::Component::RootDir => return false,
+            ::std::path::Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return false,
+            },
+            ::std::path::Component::Normal(_) => depth += 1,
+            ::std::path::Component::CurDir => (),
         }
     }
+    true
 }
 
 #[cfg(test)]
@@ -294,7 +423,12 @@ mod test {
         let data = ZipFileData {
             system: System::Dos,
             version_made_by: 0,
+            version_needed_to_extract: 0,
             encrypted: false,
+            using_data_descriptor: false,
+            is_utf8: false,
+            large_file: false,
+            aes_mode: None,
             compression_method: crate::compression::CompressionMethod::Stored,
             last_modified_time: DateTime::default(),
             crc32: 0,
@@ -306,6 +440,15 @@ mod test {
             header_start: 0,
             data_start: 0,
             external_attributes: 0,
+            unix_mtime: None,
+            unix_atime: None,
+            unix_ctime: None,
+            unix_uid: None,
+            unix_gid: None,
+            ntfs_mtime: None,
+            ntfs_atime: None,
+            ntfs_ctime: None,
+            extra_field: Vec::new(),
         };
         assert_eq!(
             data.file_name_sanitized(),
@@ -314,6 +457,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::unusual_byte_groupings)]
     fn datetime_default() {
         use super::DateTime;
         let dt = DateTime::default();
@@ -322,6 +466,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::unusual_byte_groupings)]
     fn datetime_max() {
         use super::DateTime;
         let dt = DateTime::from_date_and_time(2107, 12, 31, 23, 59, 60).unwrap();
@@ -366,6 +511,28 @@ mod test {
         );
     }
 
+    #[cfg(feature = "time")]
+    #[test]
+    fn time_clamped() {
+        use super::DateTime;
+        let tm = ::time::at_utc(::time::Timespec::new(0, 0));
+        let dt = DateTime::from_time(tm).unwrap();
+        assert_eq!(dt.datepart(), DateTime::default().datepart());
+        assert_eq!(dt.timepart(), DateTime::default().timepart());
+
+        // 2200-01-01 00:00:00
+        let tm = ::time::at_utc(::time::Timespec::new(7_258_118_400, 0));
+        let dt = DateTime::from_time(tm).unwrap();
+        assert_eq!((dt.year(), dt.month(), dt.day()), (2107, 12, 31));
+        assert_eq!((dt.hour(), dt.minute(), dt.second()), (23, 59, 58));
+
+        // 2019-01-15 12:30:46, in january to check the zero-based month
+        let tm = ::time::at_utc(::time::Timespec::new(1_547_555_446, 0));
+        let dt = DateTime::from_time(tm).unwrap();
+        assert_eq!((dt.year(), dt.month(), dt.day()), (2019, 1, 15));
+        assert_eq!((dt.hour(), dt.minute(), dt.second()), (12, 30, 46));
+    }
+
     #[test]
     fn time_out_of_bounds() {
         use super::DateTime;
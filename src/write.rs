@@ -1,11 +1,15 @@
 //! Structs for creating a new zip archive
 
 use crate::compression::CompressionMethod;
+use crate::cp437::ToCp437;
+use crate::read::{central_header_to_zip_file, ZipArchive, ZipFile};
 use crate::result::{ZipError, ZipResult};
 use crate::spec;
-use crate::types::{DateTime, System, ZipFileData, DEFAULT_VERSION};
+use crate::types::{AesMode, DateTime, System, ZipFileData, DEFAULT_VERSION};
 use crc32fast::Hasher;
 use podio::{LittleEndian, WritePodExt};
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::default::Default;
 use std::io;
 use std::io::prelude::*;
@@ -17,17 +21,54 @@ use flate2::write::DeflateEncoder;
 #[cfg(feature = "bzip2")]
 use bzip2::write::BzEncoder;
 
+#[cfg(feature = "zstd")]
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+#[cfg(feature = "aes")]
+use crate::aes::AesWriter;
+
+/// The writer that the compressed data of a file goes to
+enum MaybeEncrypted<W: Write> {
+    Unencrypted(W),
+    #[cfg(feature = "aes")]
+    Aes(AesWriter<W>),
+}
+
+impl<W: Write> Write for MaybeEncrypted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            MaybeEncrypted::Unencrypted(ref mut w) => w.write(buf),
+            #[cfg(feature = "aes")]
+            MaybeEncrypted::Aes(ref mut w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            MaybeEncrypted::Unencrypted(ref mut w) => w.flush(),
+            #[cfg(feature = "aes")]
+            MaybeEncrypted::Aes(ref mut w) => w.flush(),
+        }
+    }
+}
+
 enum GenericZipWriter<W: Write + io::Seek> {
     Closed,
-    Storer(W),
+    Storer(MaybeEncrypted<W>),
     #[cfg(feature = "deflate")]
-    Deflater(DeflateEncoder<W>),
+    Deflater(DeflateEncoder<MaybeEncrypted<W>>),
     #[cfg(feature = "bzip2")]
-    Bzip2(BzEncoder<W>),
+    Bzip2(BzEncoder<MaybeEncrypted<W>>),
+    #[cfg(feature = "zstd")]
+    Zstd(ZstdEncoder<'static, MaybeEncrypted<W>>),
 }
 
 /// Generator for ZIP files.
 ///
+/// The data of a file is written incrementally through the `Write` implementation after
+/// `start_file`. It is compressed and checksummed as it comes in, and the sizes and checksum are
+/// filled in when the file is finished, so files of any size can be added with bounded memory.
+///
 /// ```
 /// fn doit() -> zip::result::ZipResult<()>
 /// {
@@ -55,6 +96,89 @@ pub struct ZipWriter<W: Write + io::Seek> {
     files: Vec<ZipFileData>,
     stats: ZipWriterStats,
     writing_to_file: bool,
+    last_file_finished: bool,
+    streaming: bool,
+    deterministic_time: Option<DateTime>,
+    alignment: u16,
+    merge_collision: MergeCollision,
+    comment: Vec<u8>,
+    sync_on_finish: Option<fn(&mut W) -> io::Result<()>>,
+    min_compress_size: u64,
+    /// The data of a file that is held back until it is known whether it reaches
+    /// `min_compress_size`, or until a stored file is finished or reaches
+    /// `MAX_HELD_BACK_STORED` when streaming
+    pending: Option<PendingData>,
+}
+
+/// What `ZipWriter::merge_archive` does with a file whose name is already in the archive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeCollision {
+    /// Keep the file that was written first, and leave out the merged one
+    Skip,
+    /// Replace the file that was written first with the merged one. Its data is left in the
+    /// archive, but it is removed from the central directory.
+    Overwrite,
+    /// Fail without merging anything. This is the default.
+    Error,
+}
+
+/// A writer that can wait until the data written to it is durably stored
+///
+/// This is used by `ZipWriter::set_flush_on_finish`. The default `sync_all` does nothing, so
+/// writers that are not backed by storage can implement this trait without any methods.
+pub trait SyncAll {
+    /// Wait until all data that was written is durably stored
+    fn sync_all(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SyncAll for std::fs::File {
+    fn sync_all(&mut self) -> io::Result<()> {
+        std::fs::File::sync_all(self)
+    }
+}
+
+impl<T> SyncAll for io::Cursor<T> {}
+
+impl<W: Write + SyncAll> SyncAll for io::BufWriter<W> {
+    fn sync_all(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.get_mut().sync_all()
+    }
+}
+
+impl<W: SyncAll + ?Sized> SyncAll for &mut W {
+    fn sync_all(&mut self) -> io::Result<()> {
+        (**self).sync_all()
+    }
+}
+
+impl<W: Write + SyncAll> SyncAll for StreamWriter<W> {
+    fn sync_all(&mut self) -> io::Result<()> {
+        self.inner.sync_all()
+    }
+}
+
+/// An adapter that lets a `ZipWriter` write to a sink that can not seek, such as a socket
+///
+/// The adapter only supports querying the current position. Create a `ZipWriter` for it with
+/// `ZipWriter::new_streaming`.
+pub struct StreamWriter<W: Write> {
+    inner: W,
+    bytes_written: u64,
+}
+
+/// The largest stored file that is held back when streaming, so its sizes can go in the local
+/// header. Larger files are written with a data descriptor instead.
+const MAX_HELD_BACK_STORED: u64 = 1 << 20;
+
+struct PendingData {
+    compression_level: Option<i32>,
+    buffer: Vec<u8>,
+    /// The size at which the data is written with the compression method of the file and a
+    /// data descriptor when streaming
+    write_at: u64,
 }
 
 #[derive(Default)]
@@ -65,13 +189,32 @@ struct ZipWriterStats {
 }
 
 /// Metadata for a file to be written
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct FileOptions {
     compression_method: CompressionMethod,
+    compression_level: Option<i32>,
     last_modified_time: DateTime,
     permissions: Option<u32>,
+    large_file: bool,
+    aes_encryption: Option<(AesMode, String)>,
+    stored_extensions: Option<Vec<String>>,
+    file_comment: String,
+    ntfs_times: Option<(u64, u64, u64)>,
+    /// The unix modification time derived from an explicitly set `last_modified_time`
+    last_modified_timestamp: Option<u32>,
+    unix_mtime: Option<u32>,
+    unix_atime: Option<u32>,
+    unix_ctime: Option<u32>,
+    extra_fields: Vec<(u16, Vec<u8>)>,
 }
 
+/// Extensions of files that are usually compressed already
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "apk", "avi", "bz2", "docx", "flac", "gif", "gz", "jar", "jpeg", "jpg", "m4a", "mkv",
+    "mov", "mp3", "mp4", "odt", "ogg", "png", "pptx", "rar", "tgz", "webm", "webp", "xlsx", "xz",
+    "zip", "zst",
+];
+
 impl FileOptions {
     /// Construct a new FileOptions object
     pub fn default() -> FileOptions {
@@ -80,11 +223,22 @@ impl FileOptions {
             compression_method: CompressionMethod::Deflated,
             #[cfg(not(feature = "deflate"))]
             compression_method: CompressionMethod::Stored,
+            compression_level: None,
             #[cfg(feature = "time")]
             last_modified_time: DateTime::from_time(time::now()).unwrap_or(DateTime::default()),
             #[cfg(not(feature = "time"))]
             last_modified_time: DateTime::default(),
             permissions: None,
+            large_file: false,
+            aes_encryption: None,
+            stored_extensions: None,
+            file_comment: String::new(),
+            ntfs_times: None,
+            last_modified_timestamp: None,
+            unix_mtime: None,
+            unix_atime: None,
+            unix_ctime: None,
+            extra_fields: Vec::new(),
         }
     }
 
@@ -98,12 +252,56 @@ impl FileOptions {
         self
     }
 
+    /// Set the compression level for the new file
+    ///
+    /// `None` selects the default level of the compression method. Valid levels are 0 to 9 for
+    /// `CompressionMethod::Deflated` and 1 to 22 for `CompressionMethod::Zstd`; the level is
+    /// ignored for other methods.
+    pub fn compression_level(mut self, level: Option<i32>) -> FileOptions {
+        self.compression_level = level;
+        self
+    }
+
     /// Set the last modified time
     ///
-    /// The default is the current timestamp if the 'time' feature is enabled, and 1980-01-01
-    /// otherwise
+    /// The time is stored in the msdos format, so it has a precision of 2 seconds and odd seconds
+    /// are rounded down. `DateTime::from_time` clamps times outside of the years 1980 to 2107 that
+    /// the format supports. The default is the current timestamp if the 'time' feature is enabled,
+    /// and 1980-01-01 otherwise
+    ///
+    /// The time is also stored to the second in an extended timestamp extra field, which takes it
+    /// to be in UTC. Times after 2106-02-07 do not fit that field and are left out of it.
     pub fn last_modified_time(mut self, mod_time: DateTime) -> FileOptions {
         self.last_modified_time = mod_time;
+        self.last_modified_timestamp = Some(mod_time.unix_timestamp())
+            .filter(|&timestamp| timestamp <= u32::MAX as i64)
+            .map(|timestamp| timestamp as u32);
+        self
+    }
+
+    /// Set the unix modification time of the new file, in seconds since the epoch
+    ///
+    /// It is stored in the extended timestamp extra fields of both the local and central header,
+    /// in place of the time derived from `last_modified_time`. This keeps the precision of one
+    /// second, and does not depend on the time zone the msdos time is taken to be in.
+    pub fn unix_modification_time(mut self, timestamp: u32) -> FileOptions {
+        self.unix_mtime = Some(timestamp);
+        self
+    }
+
+    /// Set the unix access time of the new file, in seconds since the epoch
+    ///
+    /// It is stored in the local header's extended timestamp extra field.
+    pub fn unix_access_time(mut self, timestamp: u32) -> FileOptions {
+        self.unix_atime = Some(timestamp);
+        self
+    }
+
+    /// Set the unix creation time of the new file, in seconds since the epoch
+    ///
+    /// It is stored in the local header's extended timestamp extra field.
+    pub fn unix_creation_time(mut self, timestamp: u32) -> FileOptions {
+        self.unix_ctime = Some(timestamp);
         self
     }
 
@@ -116,6 +314,77 @@ impl FileOptions {
         self.permissions = Some(mode & 0o777);
         self
     }
+
+    /// Set whether the new file may be larger than 4 GiB
+    ///
+    /// The sizes of such a file are stored in a ZIP64 extra field, for which room has to be made
+    /// in the local header before the data is written. As the size of the data is not known when
+    /// the file is started, this is not done automatically, and writing 4 GiB or more to a file
+    /// without this option fails instead of producing a broken archive. `add_path` sets it for
+    /// files that are that large on disk. Offsets and the central directory are switched to ZIP64
+    /// automatically when needed. The default is `false`.
+    pub fn large_file(mut self, large: bool) -> FileOptions {
+        self.large_file = large;
+        self
+    }
+
+    /// Store files that are usually compressed already instead of compressing them again
+    ///
+    /// Files with an extension such as `.png`, `.jpg`, `.mp4`, `.zip` or `.gz` are stored, and
+    /// other files are compressed with the compression method of these options. The extension is
+    /// matched case insensitively.
+    pub fn auto_compression(self) -> FileOptions {
+        self.auto_compression_with(COMPRESSED_EXTENSIONS)
+    }
+
+    /// Store files with one of the given extensions, and compress other files
+    ///
+    /// This works like `auto_compression`, with a custom list of extensions. They are given
+    /// without the leading dot.
+    pub fn auto_compression_with(mut self, stored_extensions: &[&str]) -> FileOptions {
+        self.stored_extensions = Some(stored_extensions.iter().map(|&e| e.to_owned()).collect());
+        self
+    }
+
+    /// Set the NTFS modification, access and creation times of the new file
+    ///
+    /// The times are given as Windows FILETIME values, in 100 nanosecond intervals since
+    /// 1601-01-01, and are written in an NTFS extra field. By default this field is left out.
+    pub fn ntfs_times(mut self, modified: u64, accessed: u64, created: u64) -> FileOptions {
+        self.ntfs_times = Some((modified, accessed, created));
+        self
+    }
+
+    /// Set the comment of the new file, which is stored in the central directory
+    ///
+    /// The comment is encoded like the name of the file, and can be at most 65535 bytes long.
+    pub fn file_comment<S: Into<String>>(mut self, comment: S) -> FileOptions {
+        self.file_comment = comment.into();
+        self
+    }
+
+    /// Encrypt the new file with WinZip AES, using the given password and key strength
+    ///
+    /// The data is compressed with the compression method of these options before it is
+    /// encrypted. The checksum of the file is not stored, as the authentication code that
+    /// follows the encrypted data protects its integrity instead (AE-2).
+    #[cfg(feature = "aes")]
+    pub fn encrypt_aes(mut self, password: &str, strength: AesMode) -> FileOptions {
+        self.aes_encryption = Some((strength, password.to_owned()));
+        self
+    }
+
+    /// Add an extra field with the given tag and data to the new file
+    ///
+    /// The field is written in both the local and the central directory header, after the
+    /// fields that the writer builds itself. Calling this again adds another field, and the fields
+    /// are written in the order they were added. Starting the file fails if a field has a tag
+    /// that the writer builds itself: ZIP64 (0x0001), NTFS (0x000a), extended timestamp (0x5455)
+    /// or AES (0x9901).
+    pub fn add_extra_field(mut self, tag: u16, data: &[u8]) -> FileOptions {
+        self.extra_fields.push((tag, data.to_vec()));
+        self
+    }
 }
 
 impl Default for FileOptions {
@@ -132,6 +401,20 @@ impl<W: Write + io::Seek> Write for ZipWriter<W> {
                 "No file has been started",
             ));
         }
+        let large_file = matches!(self.files.last(), Some(f) if f.large_file);
+        if !large_file && self.stats.bytes_written + buf.len() as u64 >= spec::ZIP64_BYTES_THR {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Large file option has not been set",
+            ));
+        }
+        if let Some(pending) = self.pending.as_mut() {
+            pending.buffer.extend_from_slice(buf);
+            if pending.buffer.len() as u64 >= pending.write_at {
+                self.write_pending(false)?;
+            }
+            return Ok(buf.len());
+        }
         match self.inner.ref_mut() {
             Some(ref mut w) => {
                 let write_result = w.write(buf);
@@ -171,11 +454,89 @@ impl<W: Write + io::Seek> ZipWriter<W> {
     /// Before writing to this object, the start_file command should be called.
     pub fn new(inner: W) -> ZipWriter<W> {
         ZipWriter {
-            inner: GenericZipWriter::Storer(inner),
+            inner: GenericZipWriter::Storer(MaybeEncrypted::Unencrypted(inner)),
             files: Vec::new(),
             stats: Default::default(),
             writing_to_file: false,
+            last_file_finished: true,
+            streaming: false,
+            deterministic_time: None,
+            alignment: 1,
+            merge_collision: MergeCollision::Error,
+            comment: b"zip-rs".to_vec(),
+            sync_on_finish: None,
+            min_compress_size: 0,
+            pending: None,
+        }
+    }
+
+    /// Produce identical output for identical input, for reproducible builds.
+    ///
+    /// When a time is given, every file and directory started afterwards gets that modification
+    /// time instead of the one in its `FileOptions`, without extended or NTFS timestamps, and is
+    /// marked as made by MS-DOS instead of Unix, with its name in codepage 437 when possible.
+    /// Entries are always written in the order they are added. Pass `None` to turn this off
+    /// again.
+    ///
+    /// As MS-DOS has no Unix permissions, readers ignore the permissions of these entries, and
+    /// the file type that they encode: a symlink is extracted as a regular file holding its
+    /// target. Directories are still marked as such.
+    pub fn set_deterministic(&mut self, time: Option<DateTime>) {
+        self.deterministic_time = time;
+    }
+
+    /// Align the data of stored files to a multiple of `alignment` bytes, like `zipalign` does.
+    ///
+    /// The local header of each stored file started afterwards is padded with an extra field to
+    /// reach the boundary, so the data can be memory mapped directly. Android packages need an
+    /// alignment of 4. An alignment of 0 or 1 turns this off.
+    pub fn set_alignment(&mut self, alignment: u16) {
+        self.alignment = alignment;
+    }
+
+    /// Set the comment of the archive, which is written after the central directory
+    ///
+    /// The comment can be at most 65535 bytes long. The default comment is `zip-rs`.
+    pub fn set_comment<S: Into<Vec<u8>>>(&mut self, comment: S) -> ZipResult<()> {
+        let comment = comment.into();
+        if comment.len() > u16::MAX as usize {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Archive comment is longer than 65535 bytes",
+            )));
         }
+        self.comment = comment;
+        Ok(())
+    }
+
+    /// Make `finish` wait until the archive is durably stored
+    ///
+    /// `finish` always flushes the writer. With this set, it then calls `SyncAll::sync_all` on
+    /// it, which for a `std::fs::File` makes sure the central directory and end of central
+    /// directory record are on disk before it returns. For writers without such a notion, such as
+    /// an `io::Cursor`, this is a no-op. The default is `false`.
+    pub fn set_flush_on_finish(&mut self, flush: bool)
+    where
+        W: SyncAll,
+    {
+        self.sync_on_finish = if flush { Some(W::sync_all) } else { None };
+    }
+
+    /// Choose what `merge_archive` does when a merged file has the same name as a file that is
+    /// already in the archive
+    pub fn set_merge_collision(&mut self, policy: MergeCollision) {
+        self.merge_collision = policy;
+    }
+
+    /// Store files that are smaller than `bytes` without compression, whatever compression
+    /// method their `FileOptions` request.
+    ///
+    /// Compressing a few bytes usually makes them larger. To find out whether a file reaches the
+    /// threshold, up to `bytes` of its data are held in memory before its local header is
+    /// written. AES encrypted files are always compressed as requested. A size of 0 turns this
+    /// off, which is the default.
+    pub fn set_min_compress_size(&mut self, bytes: u64) {
+        self.min_compress_size = bytes;
     }
 
     /// Start a new file for with the requested options.
@@ -184,65 +545,246 @@ impl<W: Write + io::Seek> ZipWriter<W> {
         S: Into<String>,
     {
         self.finish_file()?;
+        validate_compression(options.compression_method, options.compression_level)?;
+        let stored = options.compression_method == CompressionMethod::Stored;
+        if self.streaming && stored && options.aes_encryption.is_some() {
+            return Err(ZipError::UnsupportedArchive(
+                "Stored AES encrypted files can not be written when streaming",
+            ));
+        }
+        if options.file_comment.len() > u16::MAX as usize {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "File comment is longer than 65535 bytes",
+            )));
+        }
+        let mut extra_field = Vec::new();
+        for &(tag, ref data) in &options.extra_fields {
+            if BUILT_EXTRA_FIELDS.contains(&tag) {
+                return Err(ZipError::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Extra field has a tag that the writer builds itself",
+                )));
+            }
+            if data.len() > u16::MAX as usize - 4 {
+                return Err(ZipError::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Extra field is longer than 65535 bytes",
+                )));
+            }
+            extra_field.write_u16::<LittleEndian>(tag)?;
+            extra_field.write_u16::<LittleEndian>(data.len() as u16)?;
+            extra_field.extend_from_slice(data);
+        }
 
         {
             let writer = self.inner.get_plain();
             let header_start = writer.seek(io::SeekFrom::Current(0))?;
 
             let permissions = options.permissions.unwrap_or(0o100644);
+            let mut external_attributes = permissions << 16;
+            if permissions & 0o170000 == 0o040000 {
+                // Also set the MSDOS directory attribute
+                external_attributes |= 0x10;
+            }
             let file_name = name.into();
-            let file_name_raw = file_name.clone().into_bytes();
-            let mut file = ZipFileData {
-                system: System::Unix,
+            let deterministic_time = self.deterministic_time;
+            let (system, last_modified_time, unix_times, ntfs_times) = match deterministic_time {
+                Some(time) => (System::Dos, time, (None, None, None), None),
+                None => (
+                    System::Unix,
+                    options.last_modified_time,
+                    (
+                        options.unix_mtime.or(options.last_modified_timestamp),
+                        options.unix_atime,
+                        options.unix_ctime,
+                    ),
+                    options.ntfs_times,
+                ),
+            };
+            // Tools that do not support the UTF-8 flag expect the names of files made by MS-DOS
+            // in codepage 437, so it is used when it can represent the name and comment. Other
+            // names are stored in UTF-8, which tools take as is for files made by Unix.
+            let file_name_raw = match (
+                system,
+                file_name.as_str().to_cp437(),
+                options.file_comment.to_cp437(),
+            ) {
+                (System::Dos, Ok(raw), Ok(_)) => raw.into_owned(),
+                _ => file_name.clone().into_bytes(),
+            };
+            let file = ZipFileData {
+                system,
                 version_made_by: DEFAULT_VERSION,
-                encrypted: false,
+                version_needed_to_extract: 0,
+                encrypted: options.aes_encryption.is_some(),
+                using_data_descriptor: self.streaming,
+                is_utf8: false,
+                large_file: options.large_file,
+                aes_mode: options.aes_encryption.as_ref().map(|&(mode, _)| mode),
                 compression_method: options.compression_method,
-                last_modified_time: options.last_modified_time,
+                last_modified_time,
                 crc32: 0,
                 compressed_size: 0,
                 uncompressed_size: 0,
                 file_name: file_name,
                 file_name_raw: file_name_raw,
-                file_comment: String::new(),
+                file_comment: options.file_comment.clone(),
                 header_start: header_start,
                 data_start: 0,
-                external_attributes: permissions << 16,
+                external_attributes,
+                unix_mtime: unix_times.0,
+                unix_atime: unix_times.1,
+                unix_ctime: unix_times.2,
+                unix_uid: None,
+                unix_gid: None,
+                ntfs_mtime: ntfs_times.map(|(mtime, _, _)| mtime),
+                ntfs_atime: ntfs_times.map(|(_, atime, _)| atime),
+                ntfs_ctime: ntfs_times.map(|(_, _, ctime)| ctime),
+                extra_field,
             };
-            write_local_file_header(writer, &file)?;
-
-            let header_end = writer.seek(io::SeekFrom::Current(0))?;
-            self.stats.start = header_end;
-            file.data_start = header_end;
+            // Check that the extra fields fit in both headers before anything is written. The
+            // ZIP64 field of the central header depends on the final sizes, so it is checked with
+            // the largest sizes the file can get.
+            local_extra_field(&file, self.alignment)?;
+            let mut largest = file.clone();
+            if largest.large_file {
+                largest.compressed_size = spec::ZIP64_BYTES_THR;
+                largest.uncompressed_size = spec::ZIP64_BYTES_THR;
+            }
+            central_extra_field(&largest)?;
+            self.files.push(file);
+            self.last_file_finished = false;
+        }
 
+        // A streaming reader can only find the end of stored data from the sizes in the local
+        // header, so stored files are held back until their sizes are known when streaming, as
+        // long as they are small enough to be kept in memory
+        let hold_back = options.aes_encryption.is_none()
+            && ((self.streaming && stored) || (self.min_compress_size > 0 && !stored));
+        if hold_back {
             self.stats.bytes_written = 0;
-            self.stats.hasher = Hasher::new();
+            self.pending = Some(PendingData {
+                compression_level: options.compression_level,
+                buffer: Vec::new(),
+                write_at: if stored {
+                    MAX_HELD_BACK_STORED
+                } else {
+                    self.min_compress_size
+                },
+            });
+            return Ok(());
+        }
+        self.write_local_header()?;
 
-            self.files.push(file);
+        #[cfg(feature = "aes")]
+        {
+            if let Some((mode, ref password)) = options.aes_encryption {
+                if let Err(e) = self.inner.encrypt_aes(mode, password.as_bytes()) {
+                    // Discard the local header, so the writer can still be used
+                    self.writing_to_file = true;
+                    self.abort_file()?;
+                    return Err(e);
+                }
+            }
         }
+        self.inner
+            .switch_to(options.compression_method, options.compression_level)?;
+
+        Ok(())
+    }
+
+    /// Write the local header of the file that was started last, which begins its data
+    fn write_local_header(&mut self) -> ZipResult<()> {
+        let writer = self.inner.get_plain();
+        let file = match self.files.last_mut() {
+            None => return Err(ZipError::FileNotFound),
+            Some(f) => f,
+        };
+        write_local_file_header(writer, file, self.alignment)?;
 
-        self.inner.switch_to(options.compression_method)?;
+        let header_end = writer.seek(io::SeekFrom::Current(0))?;
+        self.stats.start = header_end;
+        file.data_start = header_end;
+
+        self.stats.bytes_written = 0;
+        self.stats.hasher = Hasher::new();
+        Ok(())
+    }
 
+    /// Write the held back data of the file that was started last
+    ///
+    /// Once the file is `finished`, its data is stored with the checksum and sizes in the local
+    /// header. Otherwise it is written with the compression method of the file, and the rest of
+    /// the data follows.
+    fn write_pending(&mut self, finished: bool) -> ZipResult<()> {
+        let pending = match self.pending.take() {
+            None => return Ok(()),
+            Some(pending) => pending,
+        };
+        let method = match self.files.last_mut() {
+            None => return Err(ZipError::FileNotFound),
+            Some(file) if !finished => file.compression_method,
+            Some(file) => {
+                // All data is known, so the local header can hold the checksum and sizes
+                file.compression_method = CompressionMethod::Stored;
+                file.using_data_descriptor = false;
+                file.crc32 = crc32fast::hash(&pending.buffer);
+                file.compressed_size = pending.buffer.len() as u64;
+                file.uncompressed_size = pending.buffer.len() as u64;
+                CompressionMethod::Stored
+            }
+        };
+        self.write_local_header()?;
+        self.inner.switch_to(method, pending.compression_level)?;
+        self.write_all(&pending.buffer)?;
         Ok(())
     }
 
     fn finish_file(&mut self) -> ZipResult<()> {
-        self.inner.switch_to(CompressionMethod::Stored)?;
+        self.write_pending(true)?;
+        self.inner.switch_to(CompressionMethod::Stored, None)?;
+        self.inner.finish_encryption()?;
         let writer = self.inner.get_plain();
 
+        // Files that were copied raw, or that were already in the archive when appending, carry
+        // their final checksum and sizes as well
+        if self.last_file_finished {
+            return Ok(());
+        }
+
         let file = match self.files.last_mut() {
             None => return Ok(()),
             Some(f) => f,
         };
-        file.crc32 = self.stats.hasher.clone().finalize();
+        // The checksum is not stored for AES encrypted files
+        file.crc32 = match file.aes_mode {
+            Some(_) => 0,
+            None => self.stats.hasher.clone().finalize(),
+        };
         file.uncompressed_size = self.stats.bytes_written;
 
         let file_end = writer.seek(io::SeekFrom::Current(0))?;
         file.compressed_size = file_end - self.stats.start;
+        if !file.large_file
+            && (file.compressed_size >= spec::ZIP64_BYTES_THR
+                || file.uncompressed_size >= spec::ZIP64_BYTES_THR)
+        {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Large file option has not been set",
+            )));
+        }
 
-        update_local_file_header(writer, file)?;
-        writer.seek(io::SeekFrom::Start(file_end))?;
+        if file.using_data_descriptor {
+            write_data_descriptor(writer, file)?;
+        } else if !self.streaming {
+            update_local_file_header(writer, file)?;
+            writer.seek(io::SeekFrom::Start(file_end))?;
+        }
 
         self.writing_to_file = false;
+        self.last_file_finished = true;
         Ok(())
     }
 
@@ -255,6 +797,12 @@ impl<W: Write + io::Seek> ZipWriter<W> {
             options.permissions = Some(0o644);
         }
         *options.permissions.as_mut().unwrap() |= 0o100000;
+        let name = name.into();
+        if let Some(ref extensions) = options.stored_extensions {
+            if has_extension(&name, extensions) {
+                options.compression_method = CompressionMethod::Stored;
+            }
+        }
         self.start_entry(name, options)?;
         self.writing_to_file = true;
         Ok(())
@@ -272,9 +820,40 @@ impl<W: Write + io::Seek> ZipWriter<W> {
         self.start_file(path_to_string(path), options)
     }
 
+    /// Discard the file that is being written, for example when reading its contents failed.
+    ///
+    /// The writer seeks back to the start of the local header of the file, so the next file
+    /// overwrites it. If less data follows than was discarded, the leftover bytes remain at the
+    /// end of the output and have to be removed by the caller. This is not possible when
+    /// streaming, as the data has already been written.
+    pub fn abort_file(&mut self) -> ZipResult<()> {
+        if !self.writing_to_file {
+            return Err(ZipError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No file has been started",
+            )));
+        }
+        let header_start = match self.files.last() {
+            Some(file) => file.header_start,
+            None => return Err(ZipError::FileNotFound),
+        };
+        self.pending = None;
+        self.inner.switch_to(CompressionMethod::Stored, None)?;
+        self.inner.finish_encryption()?;
+        self.inner
+            .get_plain()
+            .seek(io::SeekFrom::Start(header_start))?;
+        self.files.pop();
+        self.writing_to_file = false;
+        self.last_file_finished = true;
+        Ok(())
+    }
+
     /// Add a directory entry.
     ///
-    /// You can't write data to the file afterwards.
+    /// A `/` is appended to the name if needed. The entry gets the modification time and unix
+    /// permissions from `options`, and the MSDOS directory attribute. You can't write data to the
+    /// file afterwards.
     pub fn add_directory<S>(&mut self, name: S, mut options: FileOptions) -> ZipResult<()>
     where
         S: Into<String>,
@@ -284,6 +863,7 @@ impl<W: Write + io::Seek> ZipWriter<W> {
         }
         *options.permissions.as_mut().unwrap() |= 0o40000;
         options.compression_method = CompressionMethod::Stored;
+        options.aes_encryption = None;
 
         let name_as_string = name.into();
         // Append a slash to the filename if it does not end with it.
@@ -309,14 +889,198 @@ impl<W: Write + io::Seek> ZipWriter<W> {
         self.add_directory(path_to_string(path.into()), options)
     }
 
+    /// Add a symbolic link entry that points to `target`
+    ///
+    /// The target is stored uncompressed as the contents of the entry, which is how tools on Unix
+    /// recognize and restore symbolic links.
+    pub fn add_symlink<N, T>(
+        &mut self,
+        name: N,
+        target: T,
+        mut options: FileOptions,
+    ) -> ZipResult<()>
+    where
+        N: Into<String>,
+        T: Into<String>,
+    {
+        if options.permissions.is_none() {
+            options.permissions = Some(0o777);
+        }
+        *options.permissions.as_mut().unwrap() |= 0o120000;
+        options.compression_method = CompressionMethod::Stored;
+        options.aes_encryption = None;
+
+        self.start_entry(name, options)?;
+        self.writing_to_file = true;
+        self.write_all(target.into().as_bytes())?;
+        self.writing_to_file = false;
+        Ok(())
+    }
+
+    /// Recursively add the contents of the directory `root`
+    ///
+    /// Files and directories are added with names relative to `root`, in sorted order. Unix
+    /// permissions and unix modification times are taken from the file system, as are the msdos
+    /// modification times when the `time` feature is enabled. On Unix, symbolic links are added as
+    /// symbolic link entries; elsewhere they are followed. Files of 4 GiB or more are added with
+    /// [`FileOptions::large_file`] set.
+    pub fn add_path<P: AsRef<std::path::Path>>(
+        &mut self,
+        root: P,
+        options: FileOptions,
+    ) -> ZipResult<()> {
+        let root = root.as_ref();
+        self.add_path_entries(root, root, options)
+    }
+
+    fn add_path_entries(
+        &mut self,
+        root: &std::path::Path,
+        directory: &std::path::Path,
+        options: FileOptions,
+    ) -> ZipResult<()> {
+        let mut entries = std::fs::read_dir(directory)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            let name = path_to_string(path.strip_prefix(root).unwrap_or(&path));
+            let metadata = std::fs::symlink_metadata(&path)?;
+
+            let mut entry_options = options.clone();
+            if let Some(time) = file_modified_time(&metadata) {
+                entry_options.last_modified_time = time;
+            }
+            entry_options.unix_mtime = file_unix_mtime(&metadata);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                entry_options.permissions = Some(metadata.permissions().mode() & 0o777);
+
+                if metadata.file_type().is_symlink() {
+                    let target = std::fs::read_link(&path)?;
+                    self.add_symlink(name, target.to_string_lossy(), entry_options)?;
+                    continue;
+                }
+            }
+
+            if path.is_dir() {
+                self.add_directory(name, entry_options)?;
+                self.add_path_entries(root, &path, options.clone())?;
+            } else {
+                if metadata.len() >= spec::ZIP64_BYTES_THR {
+                    entry_options.large_file = true;
+                }
+                self.start_file(name, entry_options)?;
+                io::copy(&mut std::fs::File::open(&path)?, self)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a file from another archive without decompressing and recompressing it
+    ///
+    /// The compressed data of the file at `index` in `source` is copied verbatim, and the new
+    /// entry keeps its compression method, checksum, sizes, timestamp and attributes. Encrypted
+    /// files can not be copied. Nothing is written if the data of the file extends past the end
+    /// of `source`.
+    pub fn raw_copy_file<R: Read + io::Seek>(
+        &mut self,
+        source: &mut ZipArchive<R>,
+        index: usize,
+    ) -> ZipResult<()> {
+        // Check the whole entry is present before its header is written
+        source.entry_extent(index)?;
+        self.raw_copy(&mut source.by_index_raw(index)?)
+    }
+
+    /// Add all files of another archive without decompressing and recompressing them
+    ///
+    /// Every file in `source` is copied like `raw_copy_file` does, in order, and gets a new offset
+    /// and central directory record in this archive. Files whose name is already in this archive
+    /// are handled according to the policy set with `set_merge_collision`.
+    pub fn merge_archive<R: Read + io::Seek>(
+        &mut self,
+        source: &mut ZipArchive<R>,
+    ) -> ZipResult<()> {
+        self.finish_file()?;
+        let existing: HashSet<String> = self.files.iter().map(|f| f.file_name.clone()).collect();
+
+        let names: Vec<String> = source
+            .decoded_directory()
+            .0
+            .iter()
+            .map(|file| file.file_name.clone())
+            .collect();
+        if self.merge_collision == MergeCollision::Error
+            && names.iter().any(|name| existing.contains(name))
+        {
+            return Err(ZipError::InvalidArchive("Duplicate file name"));
+        }
+
+        for (index, name) in names.into_iter().enumerate() {
+            if existing.contains(&name) {
+                match self.merge_collision {
+                    MergeCollision::Skip => continue,
+                    _ => self.files.retain(|f| f.file_name != name),
+                }
+            }
+            self.raw_copy_file(source, index)?;
+        }
+        Ok(())
+    }
+
+    /// Add a file from another archive, copying its compressed data verbatim
+    ///
+    /// The file must have been opened with `ZipArchive::by_index_raw`, and keeps its compression
+    /// method, checksum, sizes, timestamp and attributes.
+    fn raw_copy(&mut self, file: &mut ZipFile) -> ZipResult<()> {
+        let mut data = file.get_metadata().clone();
+        // The checksum and sizes are known up front, so they go in the local header
+        data.using_data_descriptor = false;
+        data.large_file = data.large_file
+            || data.compressed_size >= spec::ZIP64_BYTES_THR
+            || data.uncompressed_size >= spec::ZIP64_BYTES_THR;
+        if data.encrypted {
+            return Err(ZipError::UnsupportedArchive(
+                "Encrypted files are not supported",
+            ));
+        }
+
+        self.finish_file()?;
+
+        let writer = self.inner.get_plain();
+        data.header_start = writer.stream_position()?;
+        write_local_file_header(writer, &data, self.alignment)?;
+        data.data_start = writer.stream_position()?;
+
+        let copied = io::copy(file, writer)?;
+        if copied != data.compressed_size {
+            // Let the next entry or the central directory overwrite the partial entry
+            writer.seek(io::SeekFrom::Start(data.header_start))?;
+            return Err(ZipError::InvalidArchive("Raw file data is truncated"));
+        }
+
+        self.files.push(data);
+        self.writing_to_file = false;
+        self.last_file_finished = true;
+        Ok(())
+    }
+
     /// Finish the last file and write all other zip-structures
     ///
-    /// This will return the writer, but one should normally not append any data to the end of the file.
-    /// Note that the zipfile will also be finished on drop.
-    pub fn finish(&mut self) -> ZipResult<W> {
+    /// The central directory and end of central directory record are written and flushed before
+    /// the writer is returned, but one should normally not append any data to the end of the
+    /// file. Note that the zipfile will also be finished on drop, but errors can then only be
+    /// reported on stderr, so calling this is recommended.
+    pub fn finish(mut self) -> ZipResult<W> {
         self.finalize()?;
-        let inner = mem::replace(&mut self.inner, GenericZipWriter::Closed);
-        Ok(inner.unwrap())
+        let mut inner = mem::replace(&mut self.inner, GenericZipWriter::Closed).unwrap();
+        inner.flush()?;
+        if let Some(sync) = self.sync_on_finish {
+            sync(&mut inner)?;
+        }
+        Ok(inner)
     }
 
     fn finalize(&mut self) -> ZipResult<()> {
@@ -331,50 +1095,181 @@ impl<W: Write + io::Seek> ZipWriter<W> {
             }
             let central_size = writer.seek(io::SeekFrom::Current(0))? - central_start;
 
+            if self.files.len() >= spec::ZIP64_ENTRY_THR
+                || central_size >= spec::ZIP64_BYTES_THR
+                || central_start >= spec::ZIP64_BYTES_THR
+            {
+                let zip64_footer = spec::Zip64CentralDirectoryEnd {
+                    version_made_by: DEFAULT_VERSION as u16,
+                    version_needed_to_extract: 45,
+                    disk_number: 0,
+                    disk_with_central_directory: 0,
+                    number_of_files_on_this_disk: self.files.len() as u64,
+                    number_of_files: self.files.len() as u64,
+                    central_directory_size: central_size,
+                    central_directory_offset: central_start,
+                };
+                let zip64_footer_start = writer.stream_position()?;
+                zip64_footer.write(writer)?;
+
+                let locator = spec::Zip64CentralDirectoryEndLocator {
+                    disk_with_central_directory: 0,
+                    end_of_central_directory_offset: zip64_footer_start,
+                    number_of_disks: 1,
+                };
+                locator.write(writer)?;
+            }
+
+            // Values that do not fit are set to the marker that defers to the ZIP64 record
             let footer = spec::CentralDirectoryEnd {
                 disk_number: 0,
                 disk_with_central_directory: 0,
-                number_of_files_on_this_disk: self.files.len() as u16,
-                number_of_files: self.files.len() as u16,
-                central_directory_size: central_size as u32,
-                central_directory_offset: central_start as u32,
-                zip_file_comment: b"zip-rs".to_vec(),
+                number_of_files_on_this_disk: self.files.len().min(spec::ZIP64_ENTRY_THR) as u16,
+                number_of_files: self.files.len().min(spec::ZIP64_ENTRY_THR) as u16,
+                central_directory_size: central_size.min(spec::ZIP64_BYTES_THR) as u32,
+                central_directory_offset: central_start.min(spec::ZIP64_BYTES_THR) as u32,
+                zip_file_comment: self.comment.clone(),
             };
 
             footer.write(writer)?;
+            writer.flush()?;
         }
 
         Ok(())
     }
 }
 
-impl<W: Write + io::Seek> Drop for ZipWriter<W> {
-    fn drop(&mut self) {
-        if !self.inner.is_closed() {
-            if let Err(e) = self.finalize() {
-                let _ = write!(&mut io::stderr(), "ZipWriter drop failed: {:?}", e);
-            }
+impl<A: Read + Write + io::Seek> ZipWriter<A> {
+    /// Initializes the ZipWriter to add files to an existing archive.
+    ///
+    /// The central directory of the archive is parsed, and new files are written over it. When
+    /// finished, a central directory that lists both the existing and the new files is written.
+    /// The data of the existing files is left untouched.
+    ///
+    /// The archive is not truncated, so if the new end of the archive comes before the old one,
+    /// for example because no files were added, the leftover bytes have to be removed by the
+    /// caller, e.g. with `File::set_len`.
+    pub fn new_append(mut readwriter: A) -> ZipResult<ZipWriter<A>> {
+        let (footer, cde_start_pos) = spec::CentralDirectoryEnd::find_and_parse(&mut readwriter)?;
+
+        if footer.disk_number != footer.disk_with_central_directory {
+            return Err(ZipError::UnsupportedArchive(
+                "Support for multi-disk files is not implemented",
+            ));
         }
+
+        let (archive_offset, directory_start, number_of_files) =
+            ZipArchive::get_directory_counts(&mut readwriter, &footer, cde_start_pos)?;
+
+        readwriter.seek(io::SeekFrom::Start(directory_start))?;
+        let files = (0..number_of_files)
+            .map(|_| {
+                central_header_to_zip_file(&mut readwriter, archive_offset, &Default::default())
+            })
+            .collect::<ZipResult<Vec<_>>>()?;
+
+        readwriter.seek(io::SeekFrom::Start(directory_start))?;
+        let mut writer = ZipWriter::new(readwriter);
+        writer.files = files;
+        writer.comment = footer.zip_file_comment;
+        writer.last_file_finished = true;
+        Ok(writer)
     }
 }
 
-impl<W: Write + io::Seek> GenericZipWriter<W> {
-    fn switch_to(&mut self, compression: CompressionMethod) -> ZipResult<()> {
-        match self.current_compression() {
-            Some(method) if method == compression => return Ok(()),
-            None => Err(io::Error::new(
-                io::ErrorKind::BrokenPipe,
-                "ZipWriter was already closed",
-            ))?,
-            _ => {}
-        }
-
-        let bare = match mem::replace(self, GenericZipWriter::Closed) {
+impl<W: Write> ZipWriter<StreamWriter<W>> {
+    /// Initializes a ZipWriter for a sink that can not seek.
+    ///
+    /// As the local headers can not be updated once the data of a file is written, the checksum
+    /// and sizes of each file are written in a data descriptor after its data instead. Stored
+    /// files of up to 1 MiB are held in memory until they are finished, and written with their
+    /// checksum and sizes in the local header, as streaming readers can not find the end of their
+    /// data otherwise. Larger stored files get a data descriptor as well, so they can only be
+    /// read with `ZipArchive`. For the same reason, stored files can not be AES encrypted.
+    pub fn new_streaming(inner: W) -> ZipWriter<StreamWriter<W>> {
+        let mut writer = ZipWriter::new(StreamWriter {
+            inner,
+            bytes_written: 0,
+        });
+        writer.streaming = true;
+        writer
+    }
+}
+
+impl<W: Write> StreamWriter<W> {
+    /// Unwrap and return the inner writer object
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for StreamWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.bytes_written += count as u64;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> io::Seek for StreamWriter<W> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        match pos {
+            io::SeekFrom::Current(0) => Ok(self.bytes_written),
+            io::SeekFrom::Start(offset) if offset == self.bytes_written => Ok(offset),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "Seeking is not supported when streaming",
+            )),
+        }
+    }
+}
+
+impl<W: Write + io::Seek> Drop for ZipWriter<W> {
+    fn drop(&mut self) {
+        // This is only a fallback for when `finish` was not called, so errors can not be returned
+        if !self.inner.is_closed() {
+            if let Err(e) = self.finalize() {
+                let _ = writeln!(
+                    &mut io::stderr(),
+                    "ZipWriter drop failed, the archive is incomplete: {:?}",
+                    e
+                );
+            }
+        }
+    }
+}
+
+impl<W: Write + io::Seek> GenericZipWriter<W> {
+    #[cfg_attr(
+        not(any(feature = "deflate", feature = "zstd")),
+        allow(unused_variables)
+    )]
+    fn switch_to(
+        &mut self,
+        compression: CompressionMethod,
+        compression_level: Option<i32>,
+    ) -> ZipResult<()> {
+        match self.current_compression() {
+            Some(method) if method == compression => return Ok(()),
+            None => Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "ZipWriter was already closed",
+            ))?,
+            _ => {}
+        }
+
+        let bare = match mem::replace(self, GenericZipWriter::Closed) {
             GenericZipWriter::Storer(w) => w,
             #[cfg(feature = "deflate")]
             GenericZipWriter::Deflater(w) => w.finish()?,
             #[cfg(feature = "bzip2")]
             GenericZipWriter::Bzip2(w) => w.finish()?,
+            #[cfg(feature = "zstd")]
+            GenericZipWriter::Zstd(w) => w.finish()?,
             GenericZipWriter::Closed => Err(io::Error::new(
                 io::ErrorKind::BrokenPipe,
                 "ZipWriter was already closed",
@@ -384,15 +1279,29 @@ impl<W: Write + io::Seek> GenericZipWriter<W> {
         *self = match compression {
             CompressionMethod::Stored => GenericZipWriter::Storer(bare),
             #[cfg(feature = "deflate")]
-            CompressionMethod::Deflated => GenericZipWriter::Deflater(DeflateEncoder::new(
-                bare,
-                flate2::Compression::default(),
-            )),
+            CompressionMethod::Deflated => {
+                let level = compression_level.map_or(flate2::Compression::default(), |level| {
+                    flate2::Compression::new(level as u32)
+                });
+                GenericZipWriter::Deflater(DeflateEncoder::new(bare, level))
+            }
             #[cfg(feature = "bzip2")]
             CompressionMethod::Bzip2 => {
                 GenericZipWriter::Bzip2(BzEncoder::new(bare, bzip2::Compression::Default))
             }
-            CompressionMethod::Unsupported(..) => {
+            #[cfg(feature = "zstd")]
+            CompressionMethod::Zstd => {
+                let level = compression_level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL);
+                GenericZipWriter::Zstd(ZstdEncoder::new(bare, level)?)
+            }
+            #[cfg(feature = "reduce")]
+            CompressionMethod::Reduced(_) => {
+                return Err(ZipError::UnsupportedArchive("Unsupported compression"))
+            }
+            CompressionMethod::Mp3
+            | CompressionMethod::Jpeg
+            | CompressionMethod::WavPack
+            | CompressionMethod::Unsupported(..) => {
                 return Err(ZipError::UnsupportedArchive("Unsupported compression"))
             }
         };
@@ -407,6 +1316,8 @@ impl<W: Write + io::Seek> GenericZipWriter<W> {
             GenericZipWriter::Deflater(ref mut w) => Some(w as &mut dyn Write),
             #[cfg(feature = "bzip2")]
             GenericZipWriter::Bzip2(ref mut w) => Some(w as &mut dyn Write),
+            #[cfg(feature = "zstd")]
+            GenericZipWriter::Zstd(ref mut w) => Some(w as &mut dyn Write),
             GenericZipWriter::Closed => None,
         }
     }
@@ -420,11 +1331,52 @@ impl<W: Write + io::Seek> GenericZipWriter<W> {
 
     fn get_plain(&mut self) -> &mut W {
         match *self {
-            GenericZipWriter::Storer(ref mut w) => w,
+            GenericZipWriter::Storer(MaybeEncrypted::Unencrypted(ref mut w)) => w,
             _ => panic!("Should have switched to stored beforehand"),
         }
     }
 
+    /// Encrypt everything that is written from now on, until `finish_encryption` is called
+    ///
+    /// The writer is left as it was if this fails.
+    #[cfg(feature = "aes")]
+    fn encrypt_aes(&mut self, mode: AesMode, password: &[u8]) -> ZipResult<()> {
+        let cipher = match *self {
+            GenericZipWriter::Storer(MaybeEncrypted::Unencrypted(ref mut w)) => {
+                AesWriter::start(w, mode, password)?
+            }
+            _ => {
+                return Err(ZipError::Io(io::Error::new(
+                    io::ErrorKind::Other,
+                    "Encryption must start before the data is compressed",
+                )))
+            }
+        };
+        *self = match mem::replace(self, GenericZipWriter::Closed) {
+            GenericZipWriter::Storer(MaybeEncrypted::Unencrypted(w)) => {
+                GenericZipWriter::Storer(MaybeEncrypted::Aes(AesWriter::new(w, cipher)))
+            }
+            _ => unreachable!(),
+        };
+        Ok(())
+    }
+
+    /// Stop encrypting, writing the trailer of the encrypted data if needed
+    fn finish_encryption(&mut self) -> ZipResult<()> {
+        #[cfg(feature = "aes")]
+        {
+            if let GenericZipWriter::Storer(MaybeEncrypted::Aes(..)) = *self {
+                *self = match mem::replace(self, GenericZipWriter::Closed) {
+                    GenericZipWriter::Storer(MaybeEncrypted::Aes(w)) => {
+                        GenericZipWriter::Storer(MaybeEncrypted::Unencrypted(w.finish()?))
+                    }
+                    _ => unreachable!(),
+                };
+            }
+        }
+        Ok(())
+    }
+
     fn current_compression(&self) -> Option<CompressionMethod> {
         match *self {
             GenericZipWriter::Storer(..) => Some(CompressionMethod::Stored),
@@ -432,54 +1384,156 @@ impl<W: Write + io::Seek> GenericZipWriter<W> {
             GenericZipWriter::Deflater(..) => Some(CompressionMethod::Deflated),
             #[cfg(feature = "bzip2")]
             GenericZipWriter::Bzip2(..) => Some(CompressionMethod::Bzip2),
+            #[cfg(feature = "zstd")]
+            GenericZipWriter::Zstd(..) => Some(CompressionMethod::Zstd),
             GenericZipWriter::Closed => None,
         }
     }
 
     fn unwrap(self) -> W {
         match self {
-            GenericZipWriter::Storer(w) => w,
+            GenericZipWriter::Storer(MaybeEncrypted::Unencrypted(w)) => w,
             _ => panic!("Should have switched to stored beforehand"),
         }
     }
 }
 
-fn write_local_file_header<T: Write>(writer: &mut T, file: &ZipFileData) -> ZipResult<()> {
+/// Check that a file can be compressed with the given options, before anything is written
+fn validate_compression(method: CompressionMethod, level: Option<i32>) -> ZipResult<()> {
+    if !CompressionMethod::supported().contains(&method) {
+        return Err(ZipError::UnsupportedArchive(unsupported_compression(
+            method,
+        )));
+    }
+    let valid_levels: Option<std::ops::RangeInclusive<i32>> = match method {
+        #[cfg(feature = "deflate")]
+        CompressionMethod::Deflated => Some(0..=9),
+        #[cfg(feature = "zstd")]
+        CompressionMethod::Zstd => Some(1..=22),
+        _ => None,
+    };
+    match (level, valid_levels) {
+        (Some(level), Some(valid_levels)) if !valid_levels.contains(&level) => Err(
+            ZipError::UnsupportedArchive("Unsupported compression level"),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Describe why a compression method can not be written, naming the feature it needs
+fn unsupported_compression(method: CompressionMethod) -> &'static str {
+    match method.to_u16() {
+        8 => "Deflated compression requires the deflate feature",
+        12 => "Bzip2 compression requires the bzip2 feature",
+        93 => "Zstd compression requires the zstd feature",
+        94 => "Mp3 compression can not be written",
+        96 => "Jpeg compression can not be written",
+        97 => "WavPack compression can not be written",
+        _ => "Unsupported compression method",
+    }
+}
+
+fn write_local_file_header<T: Write>(
+    writer: &mut T,
+    file: &ZipFileData,
+    alignment: u16,
+) -> ZipResult<()> {
+    let extra_field = local_extra_field(file, alignment)?;
     // local file header signature
     writer.write_u32::<LittleEndian>(spec::LOCAL_FILE_HEADER_SIGNATURE)?;
     // version needed to extract
     writer.write_u16::<LittleEndian>(file.version_needed())?;
     // general purpose bit flag
-    let flag = if !file.file_name.is_ascii() {
-        1u16 << 11
-    } else {
-        0
-    };
-    writer.write_u16::<LittleEndian>(flag)?;
+    writer.write_u16::<LittleEndian>(general_purpose_flag(file))?;
     // Compression method
-    writer.write_u16::<LittleEndian>(file.compression_method.to_u16())?;
+    writer.write_u16::<LittleEndian>(header_compression_method(file))?;
     // last mod file time and last mod file date
     writer.write_u16::<LittleEndian>(file.last_modified_time.timepart())?;
     writer.write_u16::<LittleEndian>(file.last_modified_time.datepart())?;
-    // crc-32
-    writer.write_u32::<LittleEndian>(file.crc32)?;
-    // compressed size
-    writer.write_u32::<LittleEndian>(file.compressed_size as u32)?;
-    // uncompressed size
-    writer.write_u32::<LittleEndian>(file.uncompressed_size as u32)?;
+    // crc-32, compressed size and uncompressed size. These are zero if they follow in a data
+    // descriptor. The sizes of large files are stored in the ZIP64 extra field instead.
+    if file.using_data_descriptor {
+        writer.write_u32::<LittleEndian>(0)?;
+    } else {
+        writer.write_u32::<LittleEndian>(file.crc32)?;
+    }
+    if file.large_file {
+        writer.write_u32::<LittleEndian>(spec::ZIP64_BYTES_THR as u32)?;
+        writer.write_u32::<LittleEndian>(spec::ZIP64_BYTES_THR as u32)?;
+    } else if file.using_data_descriptor {
+        writer.write_u32::<LittleEndian>(0)?;
+        writer.write_u32::<LittleEndian>(0)?;
+    } else {
+        writer.write_u32::<LittleEndian>(file.compressed_size as u32)?;
+        writer.write_u32::<LittleEndian>(file.uncompressed_size as u32)?;
+    }
     // file name length
-    writer.write_u16::<LittleEndian>(file.file_name.as_bytes().len() as u16)?;
+    writer.write_u16::<LittleEndian>(file.file_name_raw.len() as u16)?;
     // extra field length
-    let extra_field = build_extra_field(file)?;
     writer.write_u16::<LittleEndian>(extra_field.len() as u16)?;
     // file name
-    writer.write_all(file.file_name.as_bytes())?;
+    writer.write_all(&file.file_name_raw)?;
     // extra field
     writer.write_all(&extra_field)?;
 
     Ok(())
 }
 
+/// The extra field of the local header of a file, which is checked to fit in the header
+fn local_extra_field(file: &ZipFileData, alignment: u16) -> ZipResult<Vec<u8>> {
+    let mut extra_field = Vec::new();
+    if file.large_file {
+        write_local_zip64_extra_field(&mut extra_field, file)?;
+    }
+    extra_field.extend_from_slice(&build_extra_field(file, false)?);
+    if file.compression_method == CompressionMethod::Stored
+        && file.aes_mode.is_none()
+        && alignment > 1
+    {
+        pad_extra_field(&mut extra_field, file, alignment)?;
+    }
+    extra_field_length(extra_field.len())?;
+    Ok(extra_field)
+}
+
+/// The extra field of the central header of a file, which is checked to fit in the header
+fn central_extra_field(file: &ZipFileData) -> ZipResult<Vec<u8>> {
+    let mut extra_field = Vec::new();
+    write_central_zip64_extra_field(&mut extra_field, file)?;
+    extra_field.extend_from_slice(&build_extra_field(file, true)?);
+    extra_field_length(extra_field.len())?;
+    Ok(extra_field)
+}
+
+/// Check that the combined extra fields of a header fit in its 16 bit length
+fn extra_field_length(length: usize) -> ZipResult<u16> {
+    if length > u16::MAX as usize {
+        return Err(ZipError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Extra fields are longer than 65535 bytes",
+        )));
+    }
+    Ok(length as u16)
+}
+
+/// Append an alignment extra field (0xa11e), as used by Android's `zipalign`, so that the file
+/// data starts on a multiple of `alignment`
+fn pad_extra_field(extra_field: &mut Vec<u8>, file: &ZipFileData, alignment: u16) -> ZipResult<()> {
+    const HEADER_SIZE: u64 = 30;
+    const PADDING_FIELD_SIZE: u64 = 6;
+    let unpadded_data_start = file.header_start
+        + HEADER_SIZE
+        + file.file_name_raw.len() as u64
+        + extra_field.len() as u64
+        + PADDING_FIELD_SIZE;
+    let padding = (alignment as u64 - unpadded_data_start % alignment as u64) % alignment as u64;
+    extra_field.write_u16::<LittleEndian>(0xa11e)?;
+    extra_field.write_u16::<LittleEndian>(2 + padding as u16)?;
+    extra_field.write_u16::<LittleEndian>(alignment)?;
+    extra_field.resize(extra_field.len() + padding as usize, 0);
+    Ok(())
+}
+
 fn update_local_file_header<T: Write + io::Seek>(
     writer: &mut T,
     file: &ZipFileData,
@@ -487,12 +1541,73 @@ fn update_local_file_header<T: Write + io::Seek>(
     const CRC32_OFFSET: u64 = 14;
     writer.seek(io::SeekFrom::Start(file.header_start + CRC32_OFFSET))?;
     writer.write_u32::<LittleEndian>(file.crc32)?;
-    writer.write_u32::<LittleEndian>(file.compressed_size as u32)?;
-    writer.write_u32::<LittleEndian>(file.uncompressed_size as u32)?;
+    if file.large_file {
+        // The sizes go in the ZIP64 extra field, which directly follows the file name
+        const FILE_NAME_OFFSET: u64 = 30;
+        const ZIP64_SIZES_OFFSET: u64 = 4;
+        writer.seek(io::SeekFrom::Start(
+            file.header_start
+                + FILE_NAME_OFFSET
+                + file.file_name_raw.len() as u64
+                + ZIP64_SIZES_OFFSET,
+        ))?;
+        writer.write_u64::<LittleEndian>(file.uncompressed_size)?;
+        writer.write_u64::<LittleEndian>(file.compressed_size)?;
+    } else {
+        writer.write_u32::<LittleEndian>(file.compressed_size as u32)?;
+        writer.write_u32::<LittleEndian>(file.uncompressed_size as u32)?;
+    }
     Ok(())
 }
 
+fn write_data_descriptor<T: Write>(writer: &mut T, file: &ZipFileData) -> ZipResult<()> {
+    // data descriptor signature
+    writer.write_u32::<LittleEndian>(spec::DATA_DESCRIPTOR_SIGNATURE)?;
+    // crc-32
+    writer.write_u32::<LittleEndian>(file.crc32)?;
+    if file.large_file {
+        // compressed size and uncompressed size, in ZIP64 format
+        writer.write_u64::<LittleEndian>(file.compressed_size)?;
+        writer.write_u64::<LittleEndian>(file.uncompressed_size)?;
+    } else {
+        // compressed size
+        writer.write_u32::<LittleEndian>(file.compressed_size as u32)?;
+        // uncompressed size
+        writer.write_u32::<LittleEndian>(file.uncompressed_size as u32)?;
+    }
+    Ok(())
+}
+
+fn general_purpose_flag(file: &ZipFileData) -> u16 {
+    let mut flag = 0;
+    if file.encrypted {
+        flag |= 1u16;
+    }
+    if file.using_data_descriptor {
+        flag |= 1u16 << 3;
+    }
+    // The name and comment are in UTF-8 unless the name was encoded in codepage 437
+    if file.file_name_raw == file.file_name.as_bytes()
+        && !(file.file_name_raw.is_ascii() && file.file_comment.is_ascii())
+    {
+        flag |= 1u16 << 11;
+    }
+    flag
+}
+
+/// Encode the comment of a file like its name, in UTF-8 or codepage 437
+fn file_comment_raw(file: &ZipFileData) -> Cow<'_, [u8]> {
+    if general_purpose_flag(file) & (1u16 << 11) != 0 {
+        return Cow::Borrowed(file.file_comment.as_bytes());
+    }
+    file.file_comment
+        .as_str()
+        .to_cp437()
+        .unwrap_or(Cow::Borrowed(file.file_comment.as_bytes()))
+}
+
 fn write_central_directory_header<T: Write>(writer: &mut T, file: &ZipFileData) -> ZipResult<()> {
+    let extra_field = central_extra_field(file)?;
     // central file header signature
     writer.write_u32::<LittleEndian>(spec::CENTRAL_DIRECTORY_HEADER_SIGNATURE)?;
     // version made by
@@ -501,30 +1616,25 @@ fn write_central_directory_header<T: Write>(writer: &mut T, file: &ZipFileData)
     // version needed to extract
     writer.write_u16::<LittleEndian>(file.version_needed())?;
     // general puprose bit flag
-    let flag = if !file.file_name.is_ascii() {
-        1u16 << 11
-    } else {
-        0
-    };
-    writer.write_u16::<LittleEndian>(flag)?;
+    writer.write_u16::<LittleEndian>(general_purpose_flag(file))?;
     // compression method
-    writer.write_u16::<LittleEndian>(file.compression_method.to_u16())?;
+    writer.write_u16::<LittleEndian>(header_compression_method(file))?;
     // last mod file time + date
     writer.write_u16::<LittleEndian>(file.last_modified_time.timepart())?;
     writer.write_u16::<LittleEndian>(file.last_modified_time.datepart())?;
     // crc-32
     writer.write_u32::<LittleEndian>(file.crc32)?;
     // compressed size
-    writer.write_u32::<LittleEndian>(file.compressed_size as u32)?;
+    writer.write_u32::<LittleEndian>(file.compressed_size.min(spec::ZIP64_BYTES_THR) as u32)?;
     // uncompressed size
-    writer.write_u32::<LittleEndian>(file.uncompressed_size as u32)?;
+    writer.write_u32::<LittleEndian>(file.uncompressed_size.min(spec::ZIP64_BYTES_THR) as u32)?;
     // file name length
-    writer.write_u16::<LittleEndian>(file.file_name.as_bytes().len() as u16)?;
+    writer.write_u16::<LittleEndian>(file.file_name_raw.len() as u16)?;
     // extra field length
-    let extra_field = build_extra_field(file)?;
     writer.write_u16::<LittleEndian>(extra_field.len() as u16)?;
     // file comment length
-    writer.write_u16::<LittleEndian>(0)?;
+    let file_comment_raw = file_comment_raw(file);
+    writer.write_u16::<LittleEndian>(file_comment_raw.len() as u16)?;
     // disk number start
     writer.write_u16::<LittleEndian>(0)?;
     // internal file attribytes
@@ -532,23 +1642,176 @@ fn write_central_directory_header<T: Write>(writer: &mut T, file: &ZipFileData)
     // external file attributes
     writer.write_u32::<LittleEndian>(file.external_attributes)?;
     // relative offset of local header
-    writer.write_u32::<LittleEndian>(file.header_start as u32)?;
+    writer.write_u32::<LittleEndian>(file.header_start.min(spec::ZIP64_BYTES_THR) as u32)?;
     // file name
-    writer.write_all(file.file_name.as_bytes())?;
+    writer.write_all(&file.file_name_raw)?;
     // extra field
     writer.write_all(&extra_field)?;
     // file comment
-    // <none>
+    writer.write_all(&file_comment_raw)?;
+
+    Ok(())
+}
 
+/// Write the ZIP64 extra field of a local header, with room for both sizes
+fn write_local_zip64_extra_field(writer: &mut Vec<u8>, file: &ZipFileData) -> ZipResult<()> {
+    writer.write_u16::<LittleEndian>(0x0001)?;
+    writer.write_u16::<LittleEndian>(16)?;
+    writer.write_u64::<LittleEndian>(file.uncompressed_size)?;
+    writer.write_u64::<LittleEndian>(file.compressed_size)?;
     Ok(())
 }
 
-fn build_extra_field(_file: &ZipFileData) -> ZipResult<Vec<u8>> {
-    let writer = Vec::new();
-    // Future work
+/// Write the ZIP64 extra field of a central directory header, holding the values that do not
+/// fit the header itself. Nothing is written if every value fits.
+fn write_central_zip64_extra_field(writer: &mut Vec<u8>, file: &ZipFileData) -> ZipResult<()> {
+    let values: Vec<u64> = [
+        file.uncompressed_size,
+        file.compressed_size,
+        file.header_start,
+    ]
+    .iter()
+    .cloned()
+    .filter(|&value| value >= spec::ZIP64_BYTES_THR)
+    .collect();
+    if values.is_empty() {
+        return Ok(());
+    }
+    writer.write_u16::<LittleEndian>(0x0001)?;
+    writer.write_u16::<LittleEndian>(values.len() as u16 * 8)?;
+    for value in values {
+        writer.write_u64::<LittleEndian>(value)?;
+    }
+    Ok(())
+}
+
+/// The compression method in the headers of a file. For AES encrypted files, the actual method is
+/// stored in the AES extra field instead.
+fn header_compression_method(file: &ZipFileData) -> u16 {
+    const AES_COMPRESSION_METHOD: u16 = 99;
+    match file.aes_mode {
+        Some(_) => AES_COMPRESSION_METHOD,
+        None => file.compression_method.to_u16(),
+    }
+}
+
+/// The tags of the extra fields that the writer builds from the metadata of a file: ZIP64,
+/// NTFS, extended timestamp and AES
+const BUILT_EXTRA_FIELDS: &[u16] = &[0x0001, 0x000a, 0x5455, 0x9901];
+
+fn build_extra_field(file: &ZipFileData, central: bool) -> ZipResult<Vec<u8>> {
+    let mut writer = Vec::new();
+    let timestamps = [file.unix_mtime, file.unix_atime, file.unix_ctime];
+    if timestamps.iter().any(Option::is_some) {
+        // Extended timestamp. The flags announce every time in the local header, but the central
+        // header only holds the modification time.
+        let stored = if central {
+            &timestamps[..1]
+        } else {
+            &timestamps[..]
+        };
+        let stored: Vec<u32> = stored.iter().flatten().copied().collect();
+        let flags = timestamps
+            .iter()
+            .enumerate()
+            .filter(|(_, time)| time.is_some())
+            .fold(0u8, |flags, (bit, _)| flags | 1 << bit);
+        writer.write_u16::<LittleEndian>(0x5455)?;
+        writer.write_u16::<LittleEndian>(1 + 4 * stored.len() as u16)?;
+        writer.write_u8(flags)?;
+        for time in stored {
+            writer.write_u32::<LittleEndian>(time)?;
+        }
+    }
+    if let Some(mode) = file.aes_mode {
+        // AES extra field. New files use vendor version AE-2, which leaves out the checksum, and
+        // files that were read from an archive keep the version they have.
+        let vendor_version = extra_fields(&file.extra_field)
+            .find(|&(kind, field)| kind == 0x9901 && field.len() >= 2)
+            .map_or(2, |(_, field)| u16::from_le_bytes([field[0], field[1]]));
+        writer.write_u16::<LittleEndian>(0x9901)?;
+        writer.write_u16::<LittleEndian>(7)?;
+        writer.write_u16::<LittleEndian>(vendor_version)?;
+        writer.write_all(b"AE")?;
+        writer.write_u8(mode.strength())?;
+        writer.write_u16::<LittleEndian>(file.compression_method.to_u16())?;
+    }
+    if let (Some(mtime), Some(atime), Some(ctime)) =
+        (file.ntfs_mtime, file.ntfs_atime, file.ntfs_ctime)
+    {
+        // NTFS extra field, with the timestamps in attribute 1
+        writer.write_u16::<LittleEndian>(0x000a)?;
+        writer.write_u16::<LittleEndian>(32)?;
+        writer.write_u32::<LittleEndian>(0)?;
+        writer.write_u16::<LittleEndian>(0x0001)?;
+        writer.write_u16::<LittleEndian>(24)?;
+        writer.write_u64::<LittleEndian>(mtime)?;
+        writer.write_u64::<LittleEndian>(atime)?;
+        writer.write_u64::<LittleEndian>(ctime)?;
+    }
+    // Any other fields, such as those of files that were read from an archive, are kept as is
+    for (kind, field) in extra_fields(&file.extra_field) {
+        if !BUILT_EXTRA_FIELDS.contains(&kind) {
+            writer.write_u16::<LittleEndian>(kind)?;
+            writer.write_u16::<LittleEndian>(field.len() as u16)?;
+            writer.write_all(field)?;
+        }
+    }
     Ok(writer)
 }
 
+/// Split an extra field into the tags and contents of its fields, up to the first truncated one
+fn extra_fields(mut data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 4 {
+            return None;
+        }
+        let kind = u16::from_le_bytes([data[0], data[1]]);
+        let len = u16::from_le_bytes([data[2], data[3]]) as usize;
+        let field = data.get(4..4 + len)?;
+        data = &data[4 + len..];
+        Some((kind, field))
+    })
+}
+
+fn has_extension(name: &str, extensions: &[String]) -> bool {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    match file_name.rfind('.') {
+        Some(dot) if dot > 0 => {
+            let extension = &file_name[dot + 1..];
+            extensions
+                .iter()
+                .any(|candidate| candidate.eq_ignore_ascii_case(extension))
+        }
+        _ => false,
+    }
+}
+
+/// The modification time of a file in seconds since the epoch, if it fits an extended timestamp
+fn file_unix_mtime(metadata: &std::fs::Metadata) -> Option<u32> {
+    let modified = metadata.modified().ok()?;
+    let seconds = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some(seconds)
+        .filter(|&seconds| seconds <= u32::MAX as u64)
+        .map(|seconds| seconds as u32)
+}
+
+#[cfg(feature = "time")]
+fn file_modified_time(metadata: &std::fs::Metadata) -> Option<DateTime> {
+    let modified = metadata.modified().ok()?;
+    let since_epoch = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    let timespec = time::Timespec::new(since_epoch.as_secs() as i64, 0);
+    DateTime::from_time(time::at(timespec)).ok()
+}
+
+#[cfg(not(feature = "time"))]
+fn file_modified_time(_metadata: &std::fs::Metadata) -> Option<DateTime> {
+    None
+}
+
 fn path_to_string(path: &std::path::Path) -> String {
     let mut path_str = String::new();
     for component in path.components() {
@@ -573,9 +1836,14 @@ mod test {
     use std::io;
     use std::io::Write;
 
+    /// Finish writing the archive, and open the result for reading
+    fn reopen(writer: ZipWriter<io::Cursor<Vec<u8>>>) -> crate::ZipArchive<io::Cursor<Vec<u8>>> {
+        crate::ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
     #[test]
     fn write_empty_zip() {
-        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let writer = ZipWriter::new(io::Cursor::new(Vec::new()));
         let result = writer.finish().unwrap();
         assert_eq!(result.get_ref().len(), 28);
         assert_eq!(
@@ -587,6 +1855,45 @@ mod test {
         );
     }
 
+    #[test]
+    fn flush_on_finish() {
+        use std::fs;
+
+        let path = std::env::temp_dir().join(format!("zip-rs-flush-{}.zip", std::process::id()));
+        let mut writer = ZipWriter::new(fs::File::create(&path).unwrap());
+        writer.set_flush_on_finish(true);
+        writer.start_file("file", FileOptions::default()).unwrap();
+        writer.write_all(b"durable").unwrap();
+        writer.finish().unwrap();
+        let archive = crate::ZipArchive::new(fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(archive.len(), 1);
+        fs::remove_file(&path).unwrap();
+
+        // Writers that are not files are only flushed
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.set_flush_on_finish(true);
+        writer.start_file("file", FileOptions::default()).unwrap();
+        let result = writer.finish().unwrap();
+        assert_eq!(crate::ZipArchive::new(result).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn write_comment() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.set_comment("archive comment").unwrap();
+        writer.start_file("file", FileOptions::default()).unwrap();
+        assert!(writer.set_comment(vec![b'x'; 65536]).is_err());
+        let result = writer.finish().unwrap();
+        let archive = crate::ZipArchive::new(result).unwrap();
+        assert_eq!(archive.comment(), b"archive comment");
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.set_comment(vec![b'x'; 65535]).unwrap();
+        let result = writer.finish().unwrap();
+        let archive = crate::ZipArchive::new(result).unwrap();
+        assert_eq!(archive.comment(), &[b'x'; 65535][..]);
+    }
+
     #[test]
     fn write_zip_dir() {
         let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
@@ -602,15 +1909,16 @@ mod test {
             .write(b"writing to a directory is not allowed, and will not write any data")
             .is_err());
         let result = writer.finish().unwrap();
-        assert_eq!(result.get_ref().len(), 114);
+        assert_eq!(result.get_ref().len(), 132);
         assert_eq!(
             *result.get_ref(),
             &[
                 80u8, 75, 3, 4, 20, 0, 0, 0, 0, 0, 163, 165, 15, 77, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 5, 0, 0, 0, 116, 101, 115, 116, 47, 80, 75, 1, 2, 46, 3, 20, 0, 0, 0, 0, 0,
-                163, 165, 15, 77, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 237, 65, 0, 0, 0, 0, 116, 101, 115, 116, 47, 80, 75, 5, 6, 0, 0, 0, 0, 1, 0,
-                1, 0, 51, 0, 0, 0, 35, 0, 0, 0, 6, 0, 122, 105, 112, 45, 114, 115
+                0, 0, 5, 0, 9, 0, 116, 101, 115, 116, 47, 85, 84, 5, 0, 1, 210, 144, 116, 91, 80,
+                75, 1, 2, 46, 3, 20, 0, 0, 0, 0, 0, 163, 165, 15, 77, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 5, 0, 9, 0, 0, 0, 0, 0, 0, 0, 16, 0, 237, 65, 0, 0, 0, 0, 116, 101, 115, 116,
+                47, 85, 84, 5, 0, 1, 210, 144, 116, 91, 80, 75, 5, 6, 0, 0, 0, 0, 1, 0, 1, 0, 60,
+                0, 0, 0, 44, 0, 0, 0, 6, 0, 122, 105, 112, 45, 114, 115
             ] as &[u8]
         );
     }
@@ -620,12 +1928,23 @@ mod test {
         let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
         let options = FileOptions {
             compression_method: CompressionMethod::Stored,
+            compression_level: None,
             last_modified_time: DateTime::default(),
             permissions: Some(33188),
+            large_file: false,
+            aes_encryption: None,
+            stored_extensions: None,
+            file_comment: String::new(),
+            ntfs_times: None,
+            last_modified_timestamp: None,
+            unix_mtime: None,
+            unix_atime: None,
+            unix_ctime: None,
+            extra_fields: Vec::new(),
         };
         writer.start_file("mimetype", options).unwrap();
         writer
-            .write(b"application/vnd.oasis.opendocument.text")
+            .write_all(b"application/vnd.oasis.opendocument.text")
             .unwrap();
         let result = writer.finish().unwrap();
         assert_eq!(result.get_ref().len(), 159);
@@ -634,6 +1953,1127 @@ mod test {
         assert_eq!(result.get_ref(), &v);
     }
 
+    #[test]
+    fn write_streaming() {
+        use crate::read::ZipArchive;
+        use std::io::Read;
+
+        let mut writer = ZipWriter::new_streaming(Vec::new());
+        writer
+            .start_file("deflated.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"streamed contents").unwrap();
+        writer.add_directory("dir", FileOptions::default()).unwrap();
+        writer
+            .start_file(
+                "stored.txt",
+                FileOptions::default().compression_method(CompressionMethod::Stored),
+            )
+            .unwrap();
+        writer.write_all(b"more streamed contents").unwrap();
+        let result = writer.finish().unwrap().into_inner();
+
+        // The local header of a compressed file announces a data descriptor, and has no checksum
+        // or sizes
+        #[cfg(feature = "deflate")]
+        {
+            assert_eq!(result[6] & (1 << 3), 1 << 3);
+            assert_eq!(result[14..26], [0; 12]);
+        }
+
+        let mut archive = ZipArchive::new(io::Cursor::new(result)).unwrap();
+        assert_eq!(archive.len(), 3);
+        let mut contents = String::new();
+        archive
+            .by_name("deflated.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "streamed contents");
+        contents.clear();
+        archive
+            .by_name("stored.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "more streamed contents");
+    }
+
+    #[test]
+    fn write_streaming_stored() {
+        use crate::read::read_zipfile_from_stream;
+        use std::io::Read;
+
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let mut writer = ZipWriter::new_streaming(Vec::new());
+        writer.start_file("stored.txt", options.clone()).unwrap();
+        writer.write_all(b"stored contents").unwrap();
+        writer.add_directory("dir", options.clone()).unwrap();
+        writer.start_file("empty.txt", options).unwrap();
+        let result = writer.finish().unwrap().into_inner();
+
+        // Stored files have their checksum and sizes in the local header instead
+        assert_eq!(result[6] & (1 << 3), 0);
+        assert_eq!(result[18..26], [15, 0, 0, 0, 15, 0, 0, 0]);
+
+        let mut reader = io::Cursor::new(result);
+        let mut files = Vec::new();
+        while let Some(mut file) = read_zipfile_from_stream(&mut reader).unwrap() {
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).unwrap();
+            files.push((file.name().to_owned(), contents));
+        }
+        let expected = [
+            ("stored.txt", "stored contents"),
+            ("dir/", ""),
+            ("empty.txt", ""),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&(name, contents)| (name.to_owned(), contents.to_owned()))
+            .collect();
+        assert_eq!(files, expected);
+    }
+
+    #[test]
+    fn write_streaming_large_stored() {
+        use crate::read::ZipArchive;
+
+        // Stored files too large to hold back are written with a data descriptor
+        let large = vec![7; super::MAX_HELD_BACK_STORED as usize + 1];
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let mut writer = ZipWriter::new_streaming(Vec::new());
+        writer.start_file("large.bin", options.clone()).unwrap();
+        writer.write_all(&large[..1000]).unwrap();
+        writer.write_all(&large[1000..]).unwrap();
+        writer.start_file("small.txt", options).unwrap();
+        writer.write_all(b"small").unwrap();
+        let result = writer.finish().unwrap().into_inner();
+        assert_eq!(result[6] & (1 << 3), 1 << 3);
+        assert_eq!(result[8], 0);
+
+        let mut archive = ZipArchive::new(io::Cursor::new(result)).unwrap();
+        assert!(archive.read_file("large.bin").unwrap() == large);
+        assert_eq!(archive.read_file("small.txt").unwrap(), b"small");
+    }
+
+    #[cfg(feature = "aes")]
+    #[test]
+    fn write_streaming_stored_aes() {
+        use crate::AesMode;
+
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .encrypt_aes("password", AesMode::Aes256);
+        let mut writer = ZipWriter::new_streaming(Vec::new());
+        assert!(writer.start_file("secret.txt", options).is_err());
+        writer
+            .start_file("plain.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"contents").unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn finish_on_drop() {
+        let mut buf = Vec::new();
+        {
+            let mut writer = ZipWriter::new(io::Cursor::new(&mut buf));
+            writer.start_file("test", FileOptions::default()).unwrap();
+            writer.write_all(b"written before drop").unwrap();
+        }
+        let mut finished = ZipWriter::new(io::Cursor::new(Vec::new()));
+        finished.start_file("test", FileOptions::default()).unwrap();
+        finished.write_all(b"written before drop").unwrap();
+        assert_eq!(buf.len(), finished.finish().unwrap().into_inner().len());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn write_zstd() {
+        use crate::read::ZipArchive;
+
+        let contents = b"zstandard compressed contents ".repeat(100);
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for &level in &[None, Some(1), Some(19)] {
+            let options = FileOptions::default()
+                .compression_method(CompressionMethod::Zstd)
+                .compression_level(level);
+            writer.start_file(format!("{:?}", level), options).unwrap();
+            writer.write_all(&contents).unwrap();
+        }
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Zstd)
+            .compression_level(Some(23));
+        assert!(writer.start_file("invalid", options).is_err());
+        let result = writer.finish().unwrap();
+
+        let mut archive = ZipArchive::new(result).unwrap();
+        for i in 0..3 {
+            let file = archive.by_index(i).unwrap();
+            assert_eq!(file.compression(), CompressionMethod::Zstd);
+            assert!(file.compressed_size() < file.size());
+        }
+        for name in &["None", "Some(1)", "Some(19)"] {
+            assert_eq!(archive.read_file(name).unwrap(), contents);
+        }
+    }
+
+    #[cfg(not(feature = "bzip2"))]
+    #[test]
+    fn unsupported_compression() {
+        use crate::result::ZipError;
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::from_u16(12));
+        match writer.start_file("bzip2", options) {
+            Err(ZipError::UnsupportedArchive(msg)) => {
+                assert_eq!(msg, "Bzip2 compression requires the bzip2 feature")
+            }
+            _ => panic!("Bzip2 compression should not be supported"),
+        }
+        let result = writer.finish().unwrap();
+        assert_eq!(crate::ZipArchive::new(result).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn write_deterministic() {
+        let write = |time, ntfs_time| {
+            let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+            writer.set_deterministic(DateTime::from_date_and_time(2000, 1, 1, 0, 0, 0).ok());
+            let options = FileOptions::default()
+                .last_modified_time(time)
+                .ntfs_times(ntfs_time, ntfs_time, ntfs_time);
+            writer.add_directory("dir", options.clone()).unwrap();
+            writer.start_file("dir/b.txt", options.clone()).unwrap();
+            writer.write_all(b"second").unwrap();
+            writer.start_file("a.txt", options).unwrap();
+            writer.write_all(b"first").unwrap();
+            writer.finish().unwrap().into_inner()
+        };
+        let first = write(
+            DateTime::from_date_and_time(2018, 8, 15, 20, 45, 6).unwrap(),
+            131_797_719_060_000_000,
+        );
+        let second = write(
+            DateTime::from_date_and_time(2020, 2, 29, 12, 0, 0).unwrap(),
+            132_273_792_000_000_000,
+        );
+        assert_eq!(first, second);
+
+        let mut archive = crate::ZipArchive::new(io::Cursor::new(first)).unwrap();
+        assert_eq!(archive.by_index(1).unwrap().name(), "dir/b.txt");
+        let file = archive.by_index(2).unwrap();
+        assert_eq!(file.last_modified().year(), 2000);
+        assert_eq!(file.ntfs_modified(), None);
+    }
+
+    #[test]
+    fn write_aligned() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.set_alignment(4096);
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        for name in &["a", "resources.arsc", "lib/armeabi/libfoo.so"] {
+            writer.start_file(*name, options.clone()).unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+        let result = writer.finish().unwrap().into_inner();
+
+        let mut archive = crate::ZipArchive::new(io::Cursor::new(result)).unwrap();
+        for location in archive.index().unwrap() {
+            assert_eq!(location.data_start % 4096, 0);
+        }
+    }
+
+    #[test]
+    fn raw_copy_file() {
+        use std::io::Read;
+
+        let mut source = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let time = DateTime::from_date_and_time(2018, 8, 15, 20, 45, 6).unwrap();
+        let options = FileOptions::default().last_modified_time(time);
+        source.start_file("first.txt", options.clone()).unwrap();
+        source.write_all(b"first file").unwrap();
+        source.start_file("second.txt", options.clone()).unwrap();
+        source.write_all(b"second file").unwrap();
+        let source = source.finish().unwrap();
+        let mut source = crate::ZipArchive::new(source).unwrap();
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.raw_copy_file(&mut source, 1).unwrap();
+        writer.start_file("third.txt", options).unwrap();
+        writer.write_all(b"third file").unwrap();
+        let mut archive = reopen(writer);
+        assert_eq!(archive.len(), 2);
+        let mut copy = archive.by_index(0).unwrap();
+        let original = source.by_index(1).unwrap();
+        assert_eq!(copy.name(), "second.txt");
+        assert_eq!(copy.compression(), original.compression());
+        assert_eq!(copy.crc32(), original.crc32());
+        assert_eq!(copy.compressed_size(), original.compressed_size());
+        assert_eq!(copy.last_modified().datepart(), time.datepart());
+        let mut contents = String::new();
+        copy.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "second file");
+    }
+
+    #[test]
+    fn raw_copy_out_of_bounds() {
+        let source = include_bytes!("../tests/data/data_out_of_bounds.zip");
+        let mut source = crate::ZipArchive::new(io::Cursor::new(&source[..])).unwrap();
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        assert!(writer.raw_copy_file(&mut source, 0).is_err());
+        let result = writer.finish().unwrap().into_inner();
+        assert_eq!(result.len(), 28);
+        assert_eq!(
+            crate::ZipArchive::new(io::Cursor::new(result))
+                .unwrap()
+                .len(),
+            0
+        );
+    }
+
+    #[test]
+    fn unix_permissions() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        writer
+            .start_file("script.sh", options.clone().unix_permissions(0o755))
+            .unwrap();
+        writer.write_all(b"#!/bin/sh\n").unwrap();
+        writer.start_file("data.txt", options).unwrap();
+        let mut archive = reopen(writer);
+        assert_eq!(archive.by_index(0).unwrap().unix_mode(), Some(0o100755));
+        assert_eq!(archive.by_index(1).unwrap().unix_mode(), Some(0o100644));
+    }
+
+    #[test]
+    fn add_directory() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let time = DateTime::from_date_and_time(2018, 8, 15, 20, 45, 6).unwrap();
+        let options = FileOptions::default().last_modified_time(time);
+        writer.add_directory("empty", options.clone()).unwrap();
+        writer
+            .add_directory("private/", options.unix_permissions(0o700))
+            .unwrap();
+        let mut archive = reopen(writer);
+        let dir = archive.by_index(0).unwrap();
+        assert_eq!(dir.name(), "empty/");
+        assert!(dir.is_dir());
+        assert_eq!(dir.size(), 0);
+        assert_eq!(dir.unix_mode(), Some(0o40755));
+        assert_eq!(dir.last_modified().datepart(), time.datepart());
+        assert_eq!(dir.last_modified().timepart(), time.timepart());
+        drop(dir);
+        let dir = archive.by_index(1).unwrap();
+        assert_eq!(dir.name(), "private/");
+        assert!(dir.is_dir());
+        assert_eq!(dir.unix_mode(), Some(0o40700));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn add_path() {
+        use std::fs;
+        use std::io::Read;
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join(format!("zip-rs-add-path-{}", std::process::id()));
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::create_dir_all(root.join("empty")).unwrap();
+        fs::write(root.join("bin").join("run.sh"), b"#!/bin/sh\n").unwrap();
+        fs::set_permissions(
+            root.join("bin").join("run.sh"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        fs::write(root.join("readme.txt"), b"hello").unwrap();
+        fs::set_permissions(root.join("readme.txt"), fs::Permissions::from_mode(0o644)).unwrap();
+        std::os::unix::fs::symlink("readme.txt", root.join("link")).unwrap();
+
+        let modified = |path: std::path::PathBuf| {
+            let time = fs::symlink_metadata(path).unwrap().modified().unwrap();
+            time.duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as u32
+        };
+        let readme_modified = modified(root.join("readme.txt"));
+        let link_modified = modified(root.join("link"));
+
+        // Every entry gets its own modification time rather than the one in the options
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().unix_modification_time(1);
+        writer.add_path(&root, options).unwrap();
+        let result = writer.finish().unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let mut archive = crate::ZipArchive::new(result).unwrap();
+        assert_eq!(archive.len(), 5);
+        let name_at =
+            |archive: &mut crate::ZipArchive<_>, i| archive.by_index(i).unwrap().name().to_owned();
+        assert_eq!(name_at(&mut archive, 0), "bin/");
+        assert_eq!(name_at(&mut archive, 1), "bin/run.sh");
+        assert_eq!(name_at(&mut archive, 2), "empty/");
+        assert_eq!(name_at(&mut archive, 3), "link");
+        assert_eq!(name_at(&mut archive, 4), "readme.txt");
+
+        assert!(archive.by_name("empty/").unwrap().is_dir());
+        assert_eq!(
+            archive.by_name("bin/run.sh").unwrap().unix_mode(),
+            Some(0o100755)
+        );
+        let mut link = archive.by_name("link").unwrap();
+        assert_eq!(link.unix_mode(), Some(0o120777));
+        assert_eq!(link.unix_modified(), Some(link_modified));
+        let mut target = String::new();
+        link.read_to_string(&mut target).unwrap();
+        assert_eq!(target, "readme.txt");
+        drop(link);
+        let mut readme = archive.by_name("readme.txt").unwrap();
+        assert_eq!(readme.unix_mode(), Some(0o100644));
+        assert_eq!(readme.unix_modified(), Some(readme_modified));
+        let mut contents = String::new();
+        readme.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello");
+    }
+
+    /// A cursor that pretends to start 4 GiB into the stream, so ZIP64 offsets can be tested
+    /// without writing that much data
+    struct OffsetCursor(io::Cursor<Vec<u8>>);
+
+    const FAKE_OFFSET: u64 = 0x1_0000_0000;
+
+    impl io::Read for OffsetCursor {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl io::Write for OffsetCursor {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl io::Seek for OffsetCursor {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            let pos = match pos {
+                io::SeekFrom::Start(pos) => io::SeekFrom::Start(
+                    pos.checked_sub(FAKE_OFFSET)
+                        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?,
+                ),
+                pos => pos,
+            };
+            Ok(self.0.seek(pos)? + FAKE_OFFSET)
+        }
+    }
+
+    #[test]
+    fn write_zip64_offsets() {
+        use std::io::Read;
+
+        let mut writer = ZipWriter::new(OffsetCursor(io::Cursor::new(Vec::new())));
+        writer
+            .start_file("first.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"first file").unwrap();
+        writer.add_directory("dir", FileOptions::default()).unwrap();
+        let result = writer.finish().unwrap();
+        // The central directory starts past 4 GiB, so the ZIP64 end of central directory record
+        // and locator precede the regular one
+        let bytes = result.0.get_ref();
+        let footer = &bytes[bytes.len() - 22 - 6 - 20 - 56..];
+        assert_eq!(&footer[..4], b"PK\x06\x06");
+        assert_eq!(&footer[56..60], b"PK\x06\x07");
+
+        let mut archive = crate::ZipArchive::new(result).unwrap();
+        assert_eq!(archive.len(), 2);
+        let locations = archive.index().unwrap();
+        assert!(locations[0].data_start > FAKE_OFFSET);
+        let mut contents = String::new();
+        archive
+            .by_index(0)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "first file");
+        assert!(archive.by_name("dir/").unwrap().is_dir());
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn write_in_chunks() {
+        use crate::read::read_zipfile_from_stream;
+        use std::io::Read;
+
+        let chunk: Vec<u8> = (0..4096u32).map(|i| (i % 251) as u8).collect();
+        let chunks = 4096;
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.start_file("large", FileOptions::default()).unwrap();
+        for _ in 0..chunks {
+            writer.write_all(&chunk).unwrap();
+        }
+        let mut result = writer.finish().unwrap();
+
+        // The local header was updated with the sizes and checksum once the data was written
+        result.set_position(0);
+        let mut file = read_zipfile_from_stream(&mut result).unwrap().unwrap();
+        assert_eq!(file.size(), (chunk.len() * chunks) as u64);
+        assert!(file.compressed_size() < file.size() / 10);
+        let mut buffer = vec![0u8; chunk.len()];
+        for _ in 0..chunks {
+            file.read_exact(&mut buffer).unwrap();
+            assert_eq!(buffer, chunk);
+        }
+        assert_eq!(file.read(&mut buffer).unwrap(), 0);
+    }
+
+    /// A cursor that only keeps the data of small writes, such as headers, and reads zeros
+    /// everywhere else, so files of more than 4 GiB can be written without storing them. Large
+    /// writes must only hold zeros.
+    #[derive(Default)]
+    struct SparseCursor {
+        position: u64,
+        len: u64,
+        writes: Vec<(u64, Vec<u8>)>,
+    }
+
+    impl io::Read for SparseCursor {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = ::std::cmp::min(buf.len() as u64, self.len.saturating_sub(self.position));
+            let buf = &mut buf[..count as usize];
+            for byte in buf.iter_mut() {
+                *byte = 0;
+            }
+            // Later writes overwrite earlier ones
+            for &(start, ref data) in &self.writes {
+                let end = start + data.len() as u64;
+                let overlap_start = ::std::cmp::max(start, self.position);
+                let overlap_end = ::std::cmp::min(end, self.position + count);
+                if overlap_start < overlap_end {
+                    buf[(overlap_start - self.position) as usize
+                        ..(overlap_end - self.position) as usize]
+                        .copy_from_slice(
+                            &data[(overlap_start - start) as usize..(overlap_end - start) as usize],
+                        );
+                }
+            }
+            self.position += count;
+            Ok(count as usize)
+        }
+    }
+
+    impl io::Write for SparseCursor {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.len() <= 4096 {
+                self.writes.push((self.position, buf.to_vec()));
+            }
+            self.position += buf.len() as u64;
+            self.len = ::std::cmp::max(self.len, self.position);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl io::Seek for SparseCursor {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.position = match pos {
+                io::SeekFrom::Start(pos) => pos,
+                io::SeekFrom::Current(offset) => (self.position as i64 + offset) as u64,
+                io::SeekFrom::End(offset) => (self.len as i64 + offset) as u64,
+            };
+            Ok(self.position)
+        }
+    }
+
+    #[test]
+    fn write_zip64_file() {
+        let chunk = vec![0; 1 << 20];
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+        // The sizes of a file of more than 4 GiB are only stored when it was started with
+        // `large_file`, unlike the offsets, as its local header needs room for them
+        let mut writer = ZipWriter::new(SparseCursor::default());
+        writer.start_file("small", stored.clone()).unwrap();
+        writer.stats.bytes_written = crate::spec::ZIP64_BYTES_THR - 4;
+        assert!(writer.write_all(b"four").is_err());
+
+        let mut writer = ZipWriter::new(SparseCursor::default());
+        writer
+            .start_file("large", stored.clone().large_file(true))
+            .unwrap();
+        for _ in 0..4096 {
+            writer.write_all(&chunk).unwrap();
+        }
+        writer.write_all(b"tail").unwrap();
+        writer.start_file("after", stored).unwrap();
+        writer.write_all(b"after the large file").unwrap();
+        let mut archive = crate::ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let size = 0x1_0000_0004;
+        let file = archive.by_name("large").unwrap();
+        assert_eq!((file.size(), file.compressed_size()), (size, size));
+        drop(file);
+        assert_eq!(archive.read_range(0, size - 6, 10).unwrap(), b"\0\0tail");
+        assert_eq!(archive.read_file("after").unwrap(), b"after the large file");
+        assert!(archive.index().unwrap()[1].data_start > size);
+    }
+
+    #[test]
+    fn write_large_file_option() {
+        use std::io::Read;
+
+        let options = FileOptions::default().large_file(true);
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.start_file("large.txt", options).unwrap();
+        writer.write_all(b"not that large").unwrap();
+        writer
+            .start_file("small.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"small").unwrap();
+        let mut archive = reopen(writer);
+        let mut contents = String::new();
+        let mut file = archive.by_name("large.txt").unwrap();
+        assert_eq!(file.size(), 14);
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "not that large");
+        drop(file);
+        contents.clear();
+        let mut file = archive.by_name("small.txt").unwrap();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "small");
+    }
+
+    #[test]
+    fn last_modified_time() {
+        let time = DateTime::from_date_and_time(2107, 12, 31, 23, 59, 58).unwrap();
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().last_modified_time(time);
+        writer.start_file("test.txt", options).unwrap();
+        let mut archive = reopen(writer);
+        let modified = archive.by_index(0).unwrap().last_modified();
+        assert_eq!(modified.year(), 2107);
+        assert_eq!(modified.month(), 12);
+        assert_eq!(modified.day(), 31);
+        assert_eq!(modified.hour(), 23);
+        assert_eq!(modified.minute(), 59);
+        assert_eq!(modified.second(), 58);
+    }
+
+    #[test]
+    fn append() {
+        use std::io::Read;
+
+        let mut archive = Vec::new();
+        archive.extend_from_slice(include_bytes!("../tests/data/mimetype.zip"));
+        let mut writer = ZipWriter::new_append(io::Cursor::new(archive)).unwrap();
+        writer
+            .start_file("content.xml", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"<office:document-content/>").unwrap();
+        let mut archive = reopen(writer);
+        assert_eq!(archive.len(), 2);
+        let mut contents = String::new();
+        archive
+            .by_name("mimetype")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "application/vnd.oasis.opendocument.text");
+        contents.clear();
+        archive
+            .by_name("content.xml")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "<office:document-content/>");
+    }
+
+    #[test]
+    fn merge_archive() {
+        use super::MergeCollision;
+        use std::io::Read;
+
+        let open = |bytes: &'static [u8]| crate::ZipArchive::new(io::Cursor::new(bytes)).unwrap();
+        let mut files_and_dirs = open(include_bytes!("../tests/data/files_and_dirs.zip"));
+        let mut mimetype = open(include_bytes!("../tests/data/mimetype.zip"));
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.merge_archive(&mut mimetype).unwrap();
+        writer.merge_archive(&mut files_and_dirs).unwrap();
+        assert!(matches!(
+            writer.merge_archive(&mut mimetype),
+            Err(crate::result::ZipError::InvalidArchive(
+                "Duplicate file name"
+            ))
+        ));
+        writer.set_merge_collision(MergeCollision::Skip);
+        writer.merge_archive(&mut mimetype).unwrap();
+        writer.set_merge_collision(MergeCollision::Overwrite);
+        writer.merge_archive(&mut mimetype).unwrap();
+        let mut archive = reopen(writer);
+        assert_eq!(archive.len(), 4);
+        let names: Vec<String> = (0..archive.len())
+            .map(|i| archive.by_index(i).unwrap().name().to_owned())
+            .collect();
+        assert_eq!(names, ["file0.txt", "dir1/", "dir2/", "mimetype"]);
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).unwrap();
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents).unwrap();
+            assert_eq!(contents.len() as u64, file.size());
+        }
+        let mut contents = String::new();
+        archive
+            .by_name("mimetype")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "application/vnd.oasis.opendocument.text");
+    }
+
+    #[test]
+    fn abort_file() {
+        use std::io::Read;
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file("kept.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"kept").unwrap();
+        writer
+            .start_file("failed.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"partial").unwrap();
+        writer.abort_file().unwrap();
+        assert!(writer.write_all(b"more").is_err());
+        assert!(writer.abort_file().is_err());
+        writer
+            .start_file("next.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"the next file").unwrap();
+        let mut archive = reopen(writer);
+        assert_eq!(archive.len(), 2);
+        assert!(archive.by_name("failed.txt").is_err());
+        let mut contents = String::new();
+        archive
+            .by_name("next.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "the next file");
+    }
+
+    #[cfg(all(feature = "aes", feature = "deflate"))]
+    #[test]
+    fn write_aes() {
+        use crate::aes::{AesCipher, AUTH_CODE_LENGTH, PWD_VERIFY_LENGTH};
+        use crate::types::AesMode;
+        use std::io::Read;
+
+        let contents = b"secret contents ".repeat(100);
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .encrypt_aes("password", AesMode::Aes256);
+        writer.start_file("secret.txt", options).unwrap();
+        writer.write_all(&contents).unwrap();
+        let mut archive = reopen(writer);
+        assert!(archive.by_index(0).is_err());
+        let mut file = archive.by_index_raw(0).unwrap();
+        assert_eq!(file.crc32(), 0);
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).unwrap();
+
+        let (salt, rest) = data.split_at(AesMode::Aes256.salt_length());
+        let (verification, rest) = rest.split_at(PWD_VERIFY_LENGTH);
+        let (encrypted, auth_code) = rest.split_at(rest.len() - AUTH_CODE_LENGTH);
+        let (mut cipher, expected_verification) =
+            AesCipher::new(AesMode::Aes256, b"password", salt);
+        assert_eq!(verification, expected_verification);
+        let mut decrypted = encrypted.to_vec();
+        cipher.decrypt(&mut decrypted);
+        assert_eq!(auth_code, cipher.auth_code());
+
+        let mut decompressed = Vec::new();
+        flate2::read::DeflateDecoder::new(&decrypted[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, contents);
+    }
+
+    #[cfg(feature = "aes")]
+    #[test]
+    fn aes_known_archive() {
+        use crate::aes::{AesCipher, AUTH_CODE_LENGTH, PWD_VERIFY_LENGTH};
+        use crate::types::AesMode;
+        use std::io::Read;
+
+        // Written with OpenSSL rather than this crate, with the password "helloworld"
+        let fixture = include_bytes!("../tests/data/aes_archive.zip");
+        let mut archive = crate::ZipArchive::new(io::Cursor::new(&fixture[..])).unwrap();
+        let line = b"Lorem ipsum dolor sit amet\n";
+        let long: Vec<u8> = (0..200)
+            .flat_map(|i| format!("line {:04} of the secret file\n", i).into_bytes())
+            .collect();
+        let expected = [
+            ("secret_128.txt", AesMode::Aes128, line.to_vec()),
+            ("secret_192.txt", AesMode::Aes192, line.repeat(3)),
+            ("secret_256.txt", AesMode::Aes256, long),
+        ];
+        for (index, (name, mode, contents)) in expected.iter().enumerate() {
+            let mut file = archive.by_index_raw(index).unwrap();
+            assert_eq!(file.name(), *name);
+            let mut data = Vec::new();
+            file.read_to_end(&mut data).unwrap();
+
+            let (salt, rest) = data.split_at(mode.salt_length());
+            let (verification, rest) = rest.split_at(PWD_VERIFY_LENGTH);
+            let (encrypted, auth_code) = rest.split_at(rest.len() - AUTH_CODE_LENGTH);
+
+            // Encrypting the contents with the same salt must give exactly the same data
+            let (mut cipher, expected_verification) = AesCipher::new(*mode, b"helloworld", salt);
+            assert_eq!(verification, expected_verification);
+            let mut buffer = contents.clone();
+            cipher.encrypt(&mut buffer);
+            assert_eq!(buffer, encrypted);
+            assert_eq!(auth_code, cipher.auth_code());
+        }
+    }
+
+    #[cfg(feature = "aes")]
+    #[test]
+    fn write_aes_error() {
+        use crate::types::AesMode;
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        /// A writer that fails once the given number of bytes has been written
+        struct FailingWriter {
+            inner: io::Cursor<Vec<u8>>,
+            budget: Rc<Cell<Option<usize>>>,
+        }
+
+        impl io::Write for FailingWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if let Some(budget) = self.budget.get() {
+                    if buf.len() > budget {
+                        return Err(io::Error::new(io::ErrorKind::Other, "out of space"));
+                    }
+                    self.budget.set(Some(budget - buf.len()));
+                }
+                self.inner.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl io::Seek for FailingWriter {
+            fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+                self.inner.seek(pos)
+            }
+        }
+
+        // The local header fits, but the salt that follows it does not
+        let budget = Rc::new(Cell::new(Some(30 + 10 + 11)));
+        let mut writer = ZipWriter::new(FailingWriter {
+            inner: io::Cursor::new(Vec::new()),
+            budget: budget.clone(),
+        });
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .encrypt_aes("password", AesMode::Aes256);
+        assert!(writer.start_file("secret.txt", options).is_err());
+
+        budget.set(None);
+        writer
+            .start_file("plain.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"plain contents").unwrap();
+        let result = writer.finish().unwrap().inner;
+        let mut archive = crate::ZipArchive::new(result).unwrap();
+        assert_eq!(archive.len(), 1);
+        assert_eq!(archive.read_file("plain.txt").unwrap(), b"plain contents");
+    }
+
+    #[test]
+    fn write_name_encodings() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.set_deterministic(Some(DateTime::default()));
+        writer
+            .start_file("Curaçao.txt", FileOptions::default())
+            .unwrap();
+        writer
+            .start_file("日本.txt", FileOptions::default())
+            .unwrap();
+        let mut archive = reopen(writer);
+        let file = archive.by_index(0).unwrap();
+        assert_eq!(file.name(), "Curaçao.txt");
+        assert_eq!(file.name_raw(), b"Cura\x87ao.txt");
+        drop(file);
+        let file = archive.by_index(1).unwrap();
+        assert_eq!(file.name(), "日本.txt");
+        assert_eq!(file.name_raw(), "日本.txt".as_bytes());
+    }
+
+    #[test]
+    fn write_file_comment() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        writer
+            .start_file("ascii", options.clone().file_comment("plain comment"))
+            .unwrap();
+        writer
+            .start_file("utf8", options.clone().file_comment("日本語のコメント"))
+            .unwrap();
+        writer.set_deterministic(Some(DateTime::default()));
+        writer
+            .start_file("Curaçao", options.file_comment("Größe"))
+            .unwrap();
+        let mut archive = reopen(writer);
+        assert_eq!(archive.by_index(0).unwrap().comment(), "plain comment");
+        assert_eq!(archive.by_index(1).unwrap().comment(), "日本語のコメント");
+        let file = archive.by_index(2).unwrap();
+        assert_eq!(file.name_raw(), b"Cura\x87ao");
+        assert_eq!(file.comment(), "Größe");
+    }
+
+    #[test]
+    fn write_ntfs_times() {
+        // 2021-01-01 00:00:00 UTC, and one and two hours later
+        let modified = 132_539_328_000_000_000;
+        let accessed = modified + 36_000_000_000;
+        let created = modified + 72_000_000_000;
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        writer
+            .start_file(
+                "ntfs",
+                options.clone().ntfs_times(modified, accessed, created),
+            )
+            .unwrap();
+        writer.start_file("plain", options).unwrap();
+        let mut archive = reopen(writer);
+        let file = archive.by_index(0).unwrap();
+        assert_eq!(file.ntfs_modified(), Some(modified));
+        assert_eq!(file.ntfs_accessed(), Some(accessed));
+        assert_eq!(file.ntfs_created(), Some(created));
+        drop(file);
+        assert_eq!(archive.by_index(1).unwrap().ntfs_modified(), None);
+    }
+
+    #[test]
+    fn write_extra_field() {
+        let vendor = b"\xfe\xca\x06\x00vendor";
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().last_modified_time(DateTime::default());
+        writer
+            .start_file("vendor", options.clone().add_extra_field(0xcafe, b"vendor"))
+            .unwrap();
+        writer.start_file("plain", options.clone()).unwrap();
+        let both = options
+            .clone()
+            .add_extra_field(0xcafe, b"vendor")
+            .add_extra_field(0xbeef, b"");
+        writer.start_file("both", both).unwrap();
+
+        // Fields that the writer builds itself can not be given
+        let timestamp = options.clone().add_extra_field(0x5455, b"\x01\0\0\0\0");
+        assert!(writer.start_file("timestamp", timestamp).is_err());
+        let result = writer.finish().unwrap();
+
+        let mut archive = crate::ZipArchive::new(result.clone()).unwrap();
+        assert_eq!(archive.len(), 3);
+        let both = archive.by_name("both").unwrap().extra_data().to_vec();
+        assert!(both.ends_with(b"\xfe\xca\x06\x00vendor\xef\xbe\x00\x00"));
+        let extra = archive.by_index(0).unwrap().extra_data().to_vec();
+        assert!(extra.ends_with(vendor));
+        let local = archive.local_header(0).unwrap();
+        assert!(local.extra_field.ends_with(vendor));
+        assert!(!archive.by_index(1).unwrap().extra_data().ends_with(vendor));
+
+        // Unknown fields of the files in an archive are kept when appending to it
+        let mut writer = ZipWriter::new_append(result).unwrap();
+        writer.start_file("appended", options).unwrap();
+        let mut archive = reopen(writer);
+        assert_eq!(archive.len(), 4);
+        assert_eq!(archive.by_name("vendor").unwrap().extra_data(), &extra[..]);
+    }
+
+    #[test]
+    fn write_extra_field_too_long() {
+        let data = vec![0; u16::MAX as usize - 4];
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        writer
+            .start_file("largest", options.clone().add_extra_field(0xcafe, &data))
+            .unwrap();
+
+        // The extended timestamp no longer fits next to the field
+        let options = options.unix_modification_time(1_600_000_000);
+        assert!(writer
+            .start_file("timestamped", options.add_extra_field(0xcafe, &data))
+            .is_err());
+        let mut archive = reopen(writer);
+        assert_eq!(archive.len(), 1);
+        assert_eq!(
+            archive.by_index(0).unwrap().extra_data().len(),
+            data.len() + 4
+        );
+    }
+
+    #[test]
+    fn append_aes() {
+        use std::io::Read;
+
+        let fixture = include_bytes!("../tests/data/aes_archive.zip");
+        let mut original = crate::ZipArchive::new(io::Cursor::new(&fixture[..])).unwrap();
+        let mut writer = ZipWriter::new_append(io::Cursor::new(fixture.to_vec())).unwrap();
+        writer
+            .start_file("plain.txt", FileOptions::default())
+            .unwrap();
+        writer.write_all(b"plain").unwrap();
+        let result = writer.finish().unwrap();
+
+        let bytes = result.get_ref().clone();
+        let mut archive = crate::ZipArchive::new(result).unwrap();
+        assert_eq!(archive.len(), 4);
+        for index in 0..3 {
+            let mut before = original.by_index_raw(index).unwrap();
+            let mut after = archive.by_index_raw(index).unwrap();
+            assert_eq!(after.compression(), CompressionMethod::Stored);
+            assert_eq!(after.extra_data(), before.extra_data());
+            let (mut before_data, mut after_data) = (Vec::new(), Vec::new());
+            before.read_to_end(&mut before_data).unwrap();
+            after.read_to_end(&mut after_data).unwrap();
+            assert_eq!(after_data, before_data);
+
+            // The central header still announces AES, with the AE-2 extra field of the original
+            let name = bytes
+                .windows(14)
+                .rposition(|window| window == after.name().as_bytes())
+                .unwrap();
+            let header = &bytes[name - 46..name];
+            assert_eq!(&header[..4], b"PK\x01\x02");
+            assert_eq!(&header[10..12], &99u16.to_le_bytes());
+            assert_eq!(&bytes[name + 14..][..8], b"\x01\x99\x07\x00\x02\x00AE");
+        }
+    }
+
+    #[test]
+    fn write_extended_timestamp() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let time = DateTime::from_date_and_time(2020, 9, 13, 12, 26, 41).unwrap();
+        writer
+            .start_file("msdos", FileOptions::default().last_modified_time(time))
+            .unwrap();
+        let options = FileOptions::default()
+            .last_modified_time(time)
+            .unix_modification_time(1_600_000_010);
+        writer.start_file("modified", options.clone()).unwrap();
+        writer
+            .start_file(
+                "all",
+                options
+                    .unix_access_time(1_600_000_100)
+                    .unix_creation_time(1_500_000_000),
+            )
+            .unwrap();
+        let result = writer.finish().unwrap();
+
+        let bytes = result.get_ref().clone();
+        let mut archive = crate::ZipArchive::new(result).unwrap();
+        let file = archive.by_name("msdos").unwrap();
+        assert_eq!(file.unix_modified(), Some(1_600_000_001));
+        assert_eq!(file.last_modified().second(), 40);
+        drop(file);
+        let file = archive.by_name("modified").unwrap();
+        assert_eq!(file.unix_modified(), Some(1_600_000_010));
+        assert_eq!(file.unix_accessed(), None);
+        assert_eq!(file.last_modified().second(), 40);
+        drop(file);
+        let file = archive.by_name("all").unwrap();
+        assert_eq!(file.unix_modified(), Some(1_600_000_010));
+        assert_eq!(file.unix_accessed(), Some(1_600_000_100));
+        assert_eq!(file.unix_created(), Some(1_500_000_000));
+
+        // The central header only holds the modification time
+        let name = bytes
+            .windows(3)
+            .rposition(|window| window == b"all")
+            .unwrap();
+        let extra_field = &bytes[name + 3..][..9];
+        assert_eq!(&extra_field[..5], &[0x55, 0x54, 5, 0, 0b111]);
+        assert_eq!(&extra_field[5..], &1_600_000_010u32.to_le_bytes());
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn auto_compression() {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default().auto_compression();
+        for name in &["image.PNG", "notes.txt", "png", "archive.tar.gz"] {
+            writer.start_file(*name, options.clone()).unwrap();
+            writer.write_all(b"some data").unwrap();
+        }
+        let custom = FileOptions::default().auto_compression_with(&["txt"]);
+        writer.start_file("custom.txt", custom.clone()).unwrap();
+        writer.start_file("custom.png", custom).unwrap();
+        let mut archive = reopen(writer);
+        let mut method = |name| archive.by_name(name).unwrap().compression();
+        assert_eq!(method("image.PNG"), CompressionMethod::Stored);
+        assert_eq!(method("notes.txt"), CompressionMethod::Deflated);
+        assert_eq!(method("png"), CompressionMethod::Deflated);
+        assert_eq!(method("archive.tar.gz"), CompressionMethod::Stored);
+        assert_eq!(method("custom.txt"), CompressionMethod::Stored);
+        assert_eq!(method("custom.png"), CompressionMethod::Deflated);
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn min_compress_size() {
+        use std::io::Read;
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.set_min_compress_size(16);
+        writer.start_file("tiny", FileOptions::default()).unwrap();
+        writer.write_all(b"abc").unwrap();
+        writer.start_file("large", FileOptions::default()).unwrap();
+        writer.write_all(b"abcdefgh").unwrap();
+        writer.write_all(&[b'a'; 100]).unwrap();
+        writer.start_file("empty", FileOptions::default()).unwrap();
+        let result = writer.finish().unwrap();
+
+        // The local header names the method that was used in the end
+        assert_eq!(result.get_ref()[8..10], [0, 0]);
+
+        let mut archive = crate::ZipArchive::new(result).unwrap();
+        let mut file = archive.by_name("tiny").unwrap();
+        assert_eq!(file.compression(), CompressionMethod::Stored);
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "abc");
+        drop(file);
+        let mut file = archive.by_name("large").unwrap();
+        assert_eq!(file.compression(), CompressionMethod::Deflated);
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents.len(), 108);
+        drop(file);
+        let file = archive.by_name("empty").unwrap();
+        assert_eq!(file.compression(), CompressionMethod::Stored);
+    }
+
     #[test]
     fn path_to_string() {
         let mut path = std::path::PathBuf::new();